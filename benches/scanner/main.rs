@@ -0,0 +1,49 @@
+//! Scanning throughput of the scanner against the one it replaced, which copies
+//! the source into a `Vec<char>` and every lexeme into a `String`.
+//! Run with `cargo bench --bench scanner`.
+
+// Kept as it was, lints and all.
+#[allow(dead_code, clippy::all)]
+mod baseline;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rslox1::scanner::Scanner;
+
+/// Tokens both scanners handle. The old one panics on a comment or number at the
+/// very end of the source, so each line ends with a newline.
+const LINE: &str = "(alpha + 12.5) * beta >= \"some string\" != !gamma; // comment\n";
+
+fn scan(c: &mut Criterion) {
+    let source = LINE.repeat(16 * 1024);
+
+    // The old scanner doesn't count lines and gives identifiers a literal, so
+    // only the kinds and text of the tokens are compared.
+    let mut old = baseline::scanner::Scanner::new(&source);
+    old.scan_tokens();
+    let old: Vec<_> = old
+        .tokens
+        .iter()
+        .map(|token| (format!("{:?}", token.token_type), token.lexeme.clone()))
+        .collect();
+    let new: Vec<_> = Scanner::new(&source)
+        .map(|token| (format!("{:?}", token.token_type), token.lexeme.to_string()))
+        .collect();
+    assert_eq!(old, new, "the scanners disagree on the input");
+
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.bench_function("baseline", |b| {
+        b.iter(|| {
+            let mut scanner = baseline::scanner::Scanner::new(black_box(&source));
+            scanner.scan_tokens();
+            scanner.tokens.len()
+        })
+    });
+    group.bench_function("borrowed", |b| {
+        b.iter(|| Scanner::new(black_box(&source)).count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, scan);
+criterion_main!(benches);
@@ -0,0 +1,178 @@
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
+
+use crate::{expression::Expr, statement::Stmt};
+
+/// An expression in an [`Ast`]. IDs are only meaningful for the tree that handed them out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+/// A statement in an [`Ast`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StmtId(u32);
+
+/// A node ID, which numbers the nodes of one kind from 0 in the order they were added.
+pub trait NodeId: Copy {
+    fn index(self) -> usize;
+}
+
+impl NodeId for ExprId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl NodeId for StmtId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The nodes of a parsed source. Nodes refer to their children by ID, and
+/// children are added before their parents.
+///
+/// Passes keep what they find out about nodes in a [`SideTable`] rather than in the nodes.
+#[derive(Debug, Default)]
+pub struct Ast {
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+}
+
+impl Ast {
+    pub fn new() -> Self {
+        Ast::default()
+    }
+
+    pub fn add_expr(&mut self, expr: Expr) -> ExprId {
+        self.exprs.push(expr);
+        ExprId((self.exprs.len() - 1) as u32)
+    }
+
+    pub fn add_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId((self.stmts.len() - 1) as u32)
+    }
+
+    pub fn expr_count(&self) -> usize {
+        self.exprs.len()
+    }
+
+    pub fn stmt_count(&self) -> usize {
+        self.stmts.len()
+    }
+
+    /// A table with `value` for every expression of the tree.
+    pub fn expr_table<T: Clone>(&self, value: T) -> SideTable<ExprId, T> {
+        SideTable::new(self.exprs.len(), value)
+    }
+
+    /// A table with `value` for every statement of the tree.
+    pub fn stmt_table<T: Clone>(&self, value: T) -> SideTable<StmtId, T> {
+        SideTable::new(self.stmts.len(), value)
+    }
+}
+
+impl Index<ExprId> for Ast {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.index()]
+    }
+}
+
+impl Index<StmtId> for Ast {
+    type Output = Stmt;
+
+    fn index(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id.index()]
+    }
+}
+
+/// Data a pass attaches to each node of one kind, such as the scope the resolver
+/// finds a variable declared in, a type or a source span, stored by node ID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SideTable<I, T> {
+    values: Vec<T>,
+    ids: PhantomData<I>,
+}
+
+/// The table of a tree with no nodes.
+impl<I, T> Default for SideTable<I, T> {
+    fn default() -> Self {
+        SideTable {
+            values: Vec::new(),
+            ids: PhantomData,
+        }
+    }
+}
+
+impl<I: NodeId, T: Clone> SideTable<I, T> {
+    fn new(len: usize, value: T) -> Self {
+        SideTable {
+            values: vec![value; len],
+            ids: PhantomData,
+        }
+    }
+}
+
+impl<I: NodeId, T> SideTable<I, T> {
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+}
+
+impl<I: NodeId, T> Index<I> for SideTable<I, T> {
+    type Output = T;
+
+    fn index(&self, id: I) -> &T {
+        &self.values[id.index()]
+    }
+}
+
+impl<I: NodeId, T> IndexMut<I> for SideTable<I, T> {
+    fn index_mut(&mut self, id: I) -> &mut T {
+        &mut self.values[id.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner, token::Literal};
+
+    #[test]
+    fn test_ids_and_side_tables() {
+        let tokens = Scanner::new("1 + 2 * 3").scan_tokens();
+        let mut parser = Parser::new(tokens);
+        let root = parser.parse_expression().unwrap();
+        let ast = parser.into_ast();
+        assert_eq!(ast.expr_count(), 5);
+        assert_eq!(ast.stmt_count(), 0);
+
+        // Children come before their parents, so one pass in ID order sees
+        // every child's entry before the parent's.
+        let mut depths = ast.expr_table(0);
+        for index in 0..ast.expr_count() {
+            let id = ExprId(index as u32);
+            depths[id] = match &ast[id] {
+                Expr::Binary(left, _, right) => 1 + depths[*left].max(depths[*right]),
+                _ => 1,
+            };
+        }
+        assert_eq!(depths[root], 3);
+        assert_eq!(depths.len(), 5);
+
+        let Expr::Binary(left, _, _) = &ast[root] else {
+            panic!("expected a binary expression");
+        };
+        assert!(matches!(ast[*left], Expr::Literal(Literal::Number(n)) if n == 1.0));
+    }
+}
@@ -1,143 +1,412 @@
+use std::rc::Rc;
+
 use crate::{
-    expression::{Expr, Visitor},
+    ast::{Ast, ExprId, StmtId},
+    expression::Visitor,
+    statement::{FunctionDecl, Visitor as StmtVisitor},
     token::{Literal, Token},
 };
 
-pub struct AstPrinter {}
-impl AstPrinter {
-    pub fn print(&self, expr: &Box<Expr>) -> String {
-        expr.accept(self)
+pub struct AstPrinter<'a> {
+    ast: &'a Ast,
+}
+impl<'a> AstPrinter<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
+        AstPrinter { ast }
+    }
+    pub fn print(&self, expr: ExprId) -> String {
+        self.ast[expr].accept(self)
     }
-    fn parenthesize(&self, name: &str, exprs: &[&Box<Expr>]) -> String {
+    pub fn print_stmt(&self, stmt: StmtId) -> String {
+        self.ast[stmt].accept(self)
+    }
+    fn parenthesize(&self, name: &str, exprs: &[ExprId]) -> String {
         let mut builder = String::new();
         builder.push('(');
         builder.push_str(name);
         for expr in exprs {
             builder.push(' ');
-            builder.push_str(&expr.accept(self));
+            builder.push_str(&self.print(*expr));
+        }
+        builder.push(')');
+        builder
+    }
+    /// `(fun name (a b=default ...rest) body...)`, without the name for lambdas.
+    fn function(&self, declaration: &FunctionDecl) -> String {
+        let mut builder = String::from("(fun ");
+        if let Some(name) = &declaration.name {
+            builder.push_str(&name.lexeme);
+            builder.push(' ');
+        }
+        let mut params: Vec<String> = Vec::new();
+        for param in &declaration.params {
+            match &param.default {
+                Some(default) => {
+                    params.push(format!("{}={}", param.name.lexeme, self.print(*default)))
+                }
+                None => params.push(param.name.lexeme.to_string()),
+            }
+        }
+        if let Some(rest) = &declaration.rest {
+            params.push(format!("...{}", rest.lexeme));
+        }
+        builder.push_str(&format!("({})", params.join(" ")));
+        for statement in &declaration.body {
+            builder.push(' ');
+            builder.push_str(&self.print_stmt(*statement));
         }
         builder.push(')');
-        return builder;
+        builder
     }
 }
 
-impl Visitor<String> for AstPrinter {
+impl Visitor<String> for AstPrinter<'_> {
     fn visit_literal(&self, expr: &Literal) -> String {
         expr.to_string()
     }
-    fn visit_grouping(&self, expr: &Box<Expr>) -> String {
+    fn visit_grouping(&self, expr: ExprId) -> String {
         self.parenthesize("grouping", &[expr])
     }
-    fn visit_binary(&self, left: &Box<Expr>, operator: &Token, right: &Box<Expr>) -> String {
+    fn visit_binary(&self, left: ExprId, operator: &Token, right: ExprId) -> String {
         self.parenthesize(&operator.lexeme, &[left, right])
     }
-    fn visit_unary(&self, operator: &Token, right: &Box<Expr>) -> String {
+    fn visit_unary(&self, operator: &Token, right: ExprId) -> String {
         self.parenthesize(&operator.lexeme, &[right])
     }
+    fn visit_logical(&self, left: ExprId, operator: &Token, right: ExprId) -> String {
+        self.parenthesize(&operator.lexeme, &[left, right])
+    }
+    fn visit_ternary(&self, condition: ExprId, then_branch: ExprId, else_branch: ExprId) -> String {
+        self.parenthesize("?:", &[condition, then_branch, else_branch])
+    }
+    fn visit_comma(&self, left: ExprId, right: ExprId) -> String {
+        self.parenthesize(",", &[left, right])
+    }
+    fn visit_variable(&self, name: &Token) -> String {
+        name.lexeme.to_string()
+    }
+    fn visit_assign(&self, name: &Token, value: ExprId) -> String {
+        self.parenthesize(&format!("= {}", name.lexeme), &[value])
+    }
+    fn visit_compound_assign(&self, target: ExprId, operator: &Token, value: ExprId) -> String {
+        self.parenthesize(&operator.lexeme, &[target, value])
+    }
+    fn visit_increment(&self, target: ExprId, operator: &Token, prefix: bool) -> String {
+        if prefix {
+            self.parenthesize(&operator.lexeme, &[target])
+        } else {
+            self.parenthesize(&format!("post{}", operator.lexeme), &[target])
+        }
+    }
+    fn visit_call(
+        &self,
+        callee: ExprId,
+        _paren: &Token,
+        arguments: &[ExprId],
+        named: &[(Token, ExprId)],
+    ) -> String {
+        let mut builder = format!("(call {}", self.print(callee));
+        for argument in arguments {
+            builder.push(' ');
+            builder.push_str(&self.print(*argument));
+        }
+        for (name, argument) in named {
+            builder.push_str(&format!(" {}: {}", name.lexeme, self.print(*argument)));
+        }
+        builder.push(')');
+        builder
+    }
+    fn visit_lambda(&self, declaration: &Rc<FunctionDecl>) -> String {
+        self.function(declaration)
+    }
+    fn visit_list(&self, elements: &[ExprId]) -> String {
+        let mut builder = String::from("(list");
+        for element in elements {
+            builder.push(' ');
+            builder.push_str(&self.print(*element));
+        }
+        builder.push(')');
+        builder
+    }
+    fn visit_map(&self, _brace: &Token, entries: &[(ExprId, ExprId)]) -> String {
+        let mut builder = String::from("(map");
+        for (key, value) in entries {
+            builder.push_str(&format!(" ({} {})", self.print(*key), self.print(*value)));
+        }
+        builder.push(')');
+        builder
+    }
+    fn visit_index(&self, object: ExprId, _bracket: &Token, index: ExprId) -> String {
+        self.parenthesize("index", &[object, index])
+    }
+    fn visit_slice(
+        &self,
+        object: ExprId,
+        _bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> String {
+        let bound = |bound: Option<ExprId>| match bound {
+            Some(bound) => self.print(bound),
+            None => String::from("_"),
+        };
+        format!(
+            "(slice {} {} {})",
+            self.print(object),
+            bound(start),
+            bound(end)
+        )
+    }
+    fn visit_set_index(
+        &self,
+        object: ExprId,
+        _bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> String {
+        let target = self.parenthesize("index", &[object, index]);
+        format!("(= {} {})", target, self.print(value))
+    }
+    fn visit_get(&self, object: ExprId, name: &Token) -> String {
+        format!("(. {} {})", self.print(object), name.lexeme)
+    }
+    fn visit_set(&self, object: ExprId, name: &Token, value: ExprId) -> String {
+        let target = format!("(. {} {})", self.print(object), name.lexeme);
+        format!("(= {} {})", target, self.print(value))
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter<'_> {
+    fn visit_expression_stmt(&self, expr: ExprId) -> String {
+        self.parenthesize(";", &[expr])
+    }
+    fn visit_print_stmt(&self, expr: ExprId) -> String {
+        self.parenthesize("print", &[expr])
+    }
+    fn visit_var_stmt(&self, name: &Token, initializer: Option<ExprId>) -> String {
+        match initializer {
+            Some(initializer) => self.parenthesize(&format!("var {}", name.lexeme), &[initializer]),
+            None => format!("(var {})", name.lexeme),
+        }
+    }
+    fn visit_block_stmt(&self, statements: &[StmtId]) -> String {
+        let mut builder = String::from("(block");
+        for statement in statements {
+            builder.push(' ');
+            builder.push_str(&self.print_stmt(*statement));
+        }
+        builder.push(')');
+        builder
+    }
+    fn visit_if_stmt(
+        &self,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> String {
+        let mut builder = format!(
+            "(if {} {}",
+            self.print(condition),
+            self.print_stmt(then_branch)
+        );
+        if let Some(else_branch) = else_branch {
+            builder.push(' ');
+            builder.push_str(&self.print_stmt(else_branch));
+        }
+        builder.push(')');
+        builder
+    }
+    fn visit_while_stmt(
+        &self,
+        _keyword: &Token,
+        label: &Option<Token>,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+    ) -> String {
+        let mut builder = String::from("(while ");
+        if let Some(label) = label {
+            builder.push_str(&format!("{}: ", label.lexeme));
+        }
+        builder.push_str(&format!(
+            "{} {}",
+            self.print(condition),
+            self.print_stmt(body)
+        ));
+        if let Some(increment) = increment {
+            builder.push(' ');
+            builder.push_str(&self.print(increment));
+        }
+        builder.push(')');
+        builder
+    }
+    fn visit_for_in_stmt(
+        &self,
+        label: &Option<Token>,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> String {
+        let mut builder = String::from("(for ");
+        if let Some(label) = label {
+            builder.push_str(&format!("{}: ", label.lexeme));
+        }
+        builder.push_str(&format!(
+            "{} {} {})",
+            name.lexeme,
+            self.print(iterable),
+            self.print_stmt(body)
+        ));
+        builder
+    }
+    fn visit_break_stmt(&self, keyword: &Token, label: &Option<Token>) -> String {
+        match label {
+            Some(label) => format!("({} {})", keyword.lexeme, label.lexeme),
+            None => format!("({})", keyword.lexeme),
+        }
+    }
+    fn visit_continue_stmt(&self, keyword: &Token, label: &Option<Token>) -> String {
+        self.visit_break_stmt(keyword, label)
+    }
+    fn visit_function_stmt(&self, declaration: &Rc<FunctionDecl>) -> String {
+        self.function(declaration)
+    }
+    // Arrow functions return through their `=>` token, so don't print the keyword's lexeme.
+    fn visit_return_stmt(&self, _keyword: &Token, value: Option<ExprId>) -> String {
+        match value {
+            Some(value) => self.parenthesize("return", &[value]),
+            None => String::from("(return)"),
+        }
+    }
+    fn visit_throw_stmt(&self, _keyword: &Token, value: ExprId) -> String {
+        self.parenthesize("throw", &[value])
+    }
+    fn visit_try_stmt(
+        &self,
+        body: &[StmtId],
+        catch: &Option<(Token, Vec<StmtId>)>,
+        finally: &Option<Vec<StmtId>>,
+    ) -> String {
+        let mut builder = format!("(try {}", self.visit_block_stmt(body));
+        if let Some((name, handler)) = catch {
+            let handler = self.visit_block_stmt(handler);
+            builder.push_str(&format!(" (catch {} {})", name.lexeme, handler));
+        }
+        if let Some(finally) = finally {
+            builder.push_str(&format!(" (finally {})", self.visit_block_stmt(finally)));
+        }
+        builder.push(')');
+        builder
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::token::TokenType;
+    use crate::{expression::Expr, token::TokenType};
 
     use super::*;
 
+    fn number(ast: &mut Ast, value: f64) -> ExprId {
+        ast.add_expr(Expr::Literal(Literal::Number(value)))
+    }
+
     #[test]
     fn test_binary_op() {
-        let ast_printer = AstPrinter {};
-        let text = ast_printer.visit_binary(
-            &Box::new(Expr::Literal(Literal::Number(5.0))),
-            &Token::new(TokenType::Plus, String::from("+"), None, 1),
-            &Box::new(Expr::Literal(Literal::Number(2.0))),
-        );
+        let mut ast = Ast::new();
+        let (five, two) = (number(&mut ast, 5.0), number(&mut ast, 2.0));
+        let (five_two, two_five) = (number(&mut ast, 5.2), number(&mut ast, 2.5));
+        let ast_printer = AstPrinter::new(&ast);
+        let text = ast_printer.visit_binary(five, &Token::new(TokenType::Plus, "+", None, 1), two);
         assert_eq!(text, "(+ 5 2)");
 
         let text = ast_printer.visit_binary(
-            &Box::new(Expr::Literal(Literal::Number(5.2))),
-            &Token::new(TokenType::Plus, String::from("+"), None, 1),
-            &Box::new(Expr::Literal(Literal::Number(2.5))),
+            five_two,
+            &Token::new(TokenType::Plus, "+", None, 1),
+            two_five,
         );
         assert_eq!(text, "(+ 5.2 2.5)");
 
         let text = ast_printer.visit_binary(
-            &Box::new(Expr::Literal(Literal::Number(5.2))),
-            &Token::new(TokenType::Minus, String::from("-"), None, 1),
-            &Box::new(Expr::Literal(Literal::Number(2.5))),
+            five_two,
+            &Token::new(TokenType::Minus, "-", None, 1),
+            two_five,
         );
         assert_eq!(text, "(- 5.2 2.5)");
     }
 
     #[test]
     fn test_complex_op() {
-        let ast_printer = AstPrinter {};
+        let mut ast = Ast::new();
 
         // Create a complex expression: (+ (* 5 2) (- 10 3))
-        let multiply = Box::new(Expr::Binary(
-            Box::new(Expr::Literal(Literal::Number(5.0))),
-            Token::new(TokenType::Star, String::from("*"), None, 1),
-            Box::new(Expr::Literal(Literal::Number(2.0))),
+        let (five, two) = (number(&mut ast, 5.0), number(&mut ast, 2.0));
+        let multiply = ast.add_expr(Expr::Binary(
+            five,
+            Token::new(TokenType::Star, "*", None, 1),
+            two,
         ));
 
-        let subtract = Box::new(Expr::Binary(
-            Box::new(Expr::Literal(Literal::Number(10.0))),
-            Token::new(TokenType::Minus, String::from("-"), None, 1),
-            Box::new(Expr::Literal(Literal::Number(3.0))),
+        let (ten, three) = (number(&mut ast, 10.0), number(&mut ast, 3.0));
+        let subtract = ast.add_expr(Expr::Binary(
+            ten,
+            Token::new(TokenType::Minus, "-", None, 1),
+            three,
         ));
 
-        let text = ast_printer.visit_binary(
-            &multiply,
-            &Token::new(TokenType::Plus, String::from("+"), None, 1),
-            &subtract,
-        );
-
-        assert_eq!(text, "(+ (* 5 2) (- 10 3))");
-
         // Test with a unary operation inside a binary operation
         // Create: (* (- 5) 3)
-        let negate = Box::new(Expr::Unary(
-            Token::new(TokenType::Minus, String::from("-"), None, 1),
-            Box::new(Expr::Literal(Literal::Number(5.0))),
+        let negate = ast.add_expr(Expr::Unary(
+            Token::new(TokenType::Minus, "-", None, 1),
+            five,
         ));
 
+        let ast_printer = AstPrinter::new(&ast);
         let text = ast_printer.visit_binary(
-            &negate,
-            &Token::new(TokenType::Star, String::from("*"), None, 1),
-            &Box::new(Expr::Literal(Literal::Number(3.0))),
+            multiply,
+            &Token::new(TokenType::Plus, "+", None, 1),
+            subtract,
         );
 
+        assert_eq!(text, "(+ (* 5 2) (- 10 3))");
+
+        let text =
+            ast_printer.visit_binary(negate, &Token::new(TokenType::Star, "*", None, 1), three);
+
         assert_eq!(text, "(* (- 5) 3)");
     }
 
     #[test]
     fn more_complex_grouping() {
-        let ast_printer = AstPrinter {};
+        let mut ast = Ast::new();
 
         // Test a grouped expression: (group (+ 2 (* 3 4)))
-        let multiply = Box::new(Expr::Binary(
-            Box::new(Expr::Literal(Literal::Number(3.0))),
-            Token::new(TokenType::Star, String::from("*"), None, 1),
-            Box::new(Expr::Literal(Literal::Number(4.0))),
+        let (three, four) = (number(&mut ast, 3.0), number(&mut ast, 4.0));
+        let multiply = ast.add_expr(Expr::Binary(
+            three,
+            Token::new(TokenType::Star, "*", None, 1),
+            four,
         ));
 
-        let addition = Box::new(Expr::Binary(
-            Box::new(Expr::Literal(Literal::Number(2.0))),
-            Token::new(TokenType::Plus, String::from("+"), None, 1),
+        let two = number(&mut ast, 2.0);
+        let addition = ast.add_expr(Expr::Binary(
+            two,
+            Token::new(TokenType::Plus, "+", None, 1),
             multiply,
         ));
 
-        let grouped = Box::new(Expr::Grouping(addition));
-
-        let text = ast_printer.print(&grouped);
-        assert_eq!(text, "(grouping (+ 2 (* 3 4)))");
+        let grouped = ast.add_expr(Expr::Grouping(addition));
 
         // Test nested groupings: (group (group 42))
-        let inner_group = Box::new(Expr::Grouping(Box::new(Expr::Literal(Literal::Number(
-            42.0,
-        )))));
+        let answer = number(&mut ast, 42.0);
+        let inner_group = ast.add_expr(Expr::Grouping(answer));
 
-        let outer_group = Box::new(Expr::Grouping(inner_group));
+        let outer_group = ast.add_expr(Expr::Grouping(inner_group));
+
+        let ast_printer = AstPrinter::new(&ast);
+        let text = ast_printer.print(grouped);
+        assert_eq!(text, "(grouping (+ 2 (* 3 4)))");
 
-        let text = ast_printer.print(&outer_group);
+        let text = ast_printer.print(outer_group);
         assert_eq!(text, "(grouping (grouping 42))");
     }
 }
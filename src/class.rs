@@ -0,0 +1,466 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::{Rc, Weak};
+
+use crate::{
+    convert::{FromValue, IntoValue},
+    interpreter::RuntimeError,
+    token::Token,
+    value::Value,
+};
+
+/// Creates the Rust value behind a new instance from the arguments of the call.
+type Constructor = dyn Fn(&Token, Vec<Value>) -> Result<Rc<dyn Any>, RuntimeError>;
+
+/// The type-erased forms of the closures given to [`ClassBuilder`]. Each receives the
+/// instance's `RefCell<T>` and downcasts it back.
+type MethodFn = dyn Fn(&dyn Any, &Token, Vec<Value>) -> Result<Value, RuntimeError>;
+type Getter = dyn Fn(&dyn Any, &Token) -> Result<Value, RuntimeError>;
+type Setter = dyn Fn(&dyn Any, &Token, Value) -> Result<(), RuntimeError>;
+
+struct Method {
+    params: &'static [&'static str],
+    function: Box<MethodFn>,
+}
+
+/// A class implemented by a Rust type. Calling it constructs an instance, and
+/// its methods and properties are Rust closures operating on the instance's value.
+pub struct NativeClass {
+    pub name: &'static str,
+    type_id: TypeId,
+    constructor: Option<(&'static [&'static str], Box<Constructor>)>,
+    methods: HashMap<&'static str, Rc<Method>>,
+    getters: HashMap<&'static str, Box<Getter>>,
+    setters: HashMap<&'static str, Box<Setter>>,
+}
+
+impl NativeClass {
+    /// The constructor's parameters, or `None` if only the host can create instances.
+    pub fn params(&self) -> Option<&'static [&'static str]> {
+        self.constructor.as_ref().map(|(params, _)| *params)
+    }
+
+    /// How the class is called, e.g. `Counter(start)`.
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params().unwrap_or(&[]).join(", "))
+    }
+
+    /// Runs the constructor on arguments already checked against its parameters.
+    pub fn construct(
+        self: &Rc<Self>,
+        paren: &Token,
+        arguments: Vec<Value>,
+    ) -> Result<Instance, RuntimeError> {
+        let Some((_, constructor)) = &self.constructor else {
+            let message = format!("{} can't be constructed from scripts.", self.name);
+            return Err(RuntimeError::new(paren, &message));
+        };
+        Ok(Instance {
+            class: self.clone(),
+            data: constructor(paren, arguments)?,
+        })
+    }
+}
+
+impl fmt::Debug for NativeClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+/// Defines a [`NativeClass`] for the Rust type `T`.
+pub struct ClassBuilder<T> {
+    class: NativeClass,
+    marker: PhantomData<T>,
+}
+
+/// Borrows the value of an instance of a class built for `T`.
+fn borrow<T: 'static>(data: &dyn Any) -> &RefCell<T> {
+    data.downcast_ref()
+        .expect("Instances hold the type their class was built for")
+}
+
+/// The message for using an instance whose value is already borrowed, which
+/// happens when a method receives the instance it is called on as an argument.
+fn in_use(token: &Token, name: &str) -> RuntimeError {
+    let message = format!("{} instance is already in use.", name);
+    RuntimeError::new(token, &message)
+}
+
+impl<T: 'static> ClassBuilder<T> {
+    pub fn new(name: &'static str) -> Self {
+        ClassBuilder {
+            class: NativeClass {
+                name,
+                type_id: TypeId::of::<T>(),
+                constructor: None,
+                methods: HashMap::new(),
+                getters: HashMap::new(),
+                setters: HashMap::new(),
+            },
+            marker: PhantomData,
+        }
+    }
+
+    /// Lets scripts create instances by calling the class with exactly as many
+    /// arguments as it has parameters.
+    pub fn constructor(
+        mut self,
+        params: &'static [&'static str],
+        constructor: impl Fn(&Token, Vec<Value>) -> Result<T, RuntimeError> + 'static,
+    ) -> Self {
+        let constructor = move |paren: &Token, arguments| {
+            let data: Rc<dyn Any> = Rc::new(RefCell::new(constructor(paren, arguments)?));
+            Ok(data)
+        };
+        self.class.constructor = Some((params, Box::new(constructor)));
+        self
+    }
+
+    /// Adds a method, which is called like a native with the instance's value
+    /// borrowed mutably.
+    pub fn method(
+        mut self,
+        name: &'static str,
+        params: &'static [&'static str],
+        method: impl Fn(&mut T, &Token, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    ) -> Self {
+        let class = self.class.name;
+        let function = move |data: &dyn Any, paren: &Token, arguments| {
+            let mut this = borrow::<T>(data)
+                .try_borrow_mut()
+                .map_err(|_| in_use(paren, class))?;
+            method(&mut this, paren, arguments)
+        };
+        let method = Method {
+            params,
+            function: Box::new(function),
+        };
+        self.class.methods.insert(name, Rc::new(method));
+        self
+    }
+
+    /// Adds a property that scripts can read.
+    pub fn getter<R: IntoValue>(
+        mut self,
+        name: &'static str,
+        getter: impl Fn(&T) -> R + 'static,
+    ) -> Self {
+        let class = self.class.name;
+        let getter = move |data: &dyn Any, token: &Token| {
+            let this = borrow::<T>(data)
+                .try_borrow()
+                .map_err(|_| in_use(token, class))?;
+            Ok(getter(&this).into_value())
+        };
+        self.class.getters.insert(name, Box::new(getter));
+        self
+    }
+
+    /// Adds a property that scripts can assign, converting the assigned value to `V`.
+    pub fn setter<V: FromValue>(
+        mut self,
+        name: &'static str,
+        setter: impl Fn(&mut T, V) + 'static,
+    ) -> Self {
+        let class = self.class.name;
+        let setter = move |data: &dyn Any, token: &Token, value| {
+            let value = V::from_value(value).map_err(|error| {
+                let message = format!("{} for property '{}' of {}.", error, name, class);
+                RuntimeError::new(token, &message)
+            })?;
+            let mut this = borrow::<T>(data)
+                .try_borrow_mut()
+                .map_err(|_| in_use(token, class))?;
+            setter(&mut this, value);
+            Ok(())
+        };
+        self.class.setters.insert(name, Box::new(setter));
+        self
+    }
+
+    pub fn build(self) -> Rc<NativeClass> {
+        Rc::new(self.class)
+    }
+}
+
+/// An object created from a [`NativeClass`], sharing its value with the host.
+#[derive(Clone)]
+pub struct Instance {
+    pub class: Rc<NativeClass>,
+    /// A `RefCell<T>` for the `T` the class was built for.
+    data: Rc<dyn Any>,
+}
+
+impl Instance {
+    /// Wraps a value the host keeps a handle on. Panics if `class` wasn't built for `T`.
+    pub fn new<T: 'static>(class: &Rc<NativeClass>, data: Rc<RefCell<T>>) -> Self {
+        assert!(
+            class.type_id == TypeId::of::<T>(),
+            "{} isn't a class for this type",
+            class.name
+        );
+        Instance {
+            class: class.clone(),
+            data,
+        }
+    }
+
+    /// The instance's value, if it is a `T`.
+    pub fn downcast<T: 'static>(&self) -> Option<Rc<RefCell<T>>> {
+        self.data.clone().downcast().ok()
+    }
+
+    /// A handle on the instance's value that doesn't keep it alive.
+    pub fn downgrade(&self) -> Weak<dyn Any> {
+        Rc::downgrade(&self.data)
+    }
+
+    /// Whether both are the same object.
+    pub fn ptr_eq(&self, other: &Instance) -> bool {
+        Rc::ptr_eq(&self.data, &other.data)
+    }
+
+    /// Reads a property, or binds a method to the instance.
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        if let Some(getter) = self.class.getters.get(&*name.lexeme) {
+            return getter(self.data.as_ref(), name);
+        }
+        let Some((&method_name, method)) = self.class.methods.get_key_value(&*name.lexeme) else {
+            let message = format!(
+                "Undefined property '{}' on {}.",
+                name.lexeme, self.class.name
+            );
+            return Err(RuntimeError::new(name, &message));
+        };
+        let method = method.clone();
+        let data = self.data.clone();
+        Ok(Value::native(
+            method_name,
+            method.params,
+            move |paren, arguments| (method.function)(data.as_ref(), paren, arguments),
+        ))
+    }
+
+    pub fn set(&self, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        if let Some(setter) = self.class.setters.get(&*name.lexeme) {
+            return setter(self.data.as_ref(), name, value);
+        }
+        let message = if self.class.getters.contains_key(&*name.lexeme) {
+            format!(
+                "Property '{}' of {} is read-only.",
+                name.lexeme, self.class.name
+            )
+        } else {
+            format!(
+                "Can't set undefined property '{}' on {}.",
+                name.lexeme, self.class.name
+            )
+        };
+        Err(RuntimeError::new(name, &message))
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
+
+/// Lets typed natives take instances of a class built for `T`.
+impl<T: 'static> FromValue for Rc<RefCell<T>> {
+    const NAME: &'static str = "object";
+
+    fn from_value(value: Value) -> Result<Self, String> {
+        match &value {
+            Value::Instance(instance) => instance
+                .downcast()
+                .ok_or_else(|| format!("Unexpected {} instance", instance.class.name)),
+            _ => Err(format!("Expected object but got {}", value.repr())),
+        }
+    }
+}
+
+impl IntoValue for Instance {
+    fn into_value(self) -> Value {
+        Value::Instance(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::lox::Lox;
+
+    #[derive(Debug, PartialEq)]
+    struct Counter {
+        count: f64,
+        step: f64,
+    }
+
+    fn counter_class() -> Rc<NativeClass> {
+        ClassBuilder::new("Counter")
+            .constructor(&["start"], |paren, arguments| match arguments[0] {
+                Value::Number(count) => Ok(Counter { count, step: 1.0 }),
+                _ => Err(RuntimeError::new(paren, "Start must be a number.")),
+            })
+            .method("tick", &[], |counter: &mut Counter, _, _| {
+                counter.count += counter.step;
+                Ok(Value::Number(counter.count))
+            })
+            .method("add", &["other"], |counter, paren, arguments| {
+                let other = Rc::<RefCell<Counter>>::from_value(arguments[0].clone())
+                    .map_err(|error| RuntimeError::new(paren, &error))?;
+                let other = other
+                    .try_borrow()
+                    .map_err(|_| RuntimeError::new(paren, "Can't add a counter to itself."))?;
+                counter.count += other.count;
+                Ok(Value::Nil)
+            })
+            .getter("count", |counter| counter.count)
+            .getter("step", |counter| counter.step)
+            .setter("step", |counter, step: f64| counter.step = step)
+            .build()
+    }
+
+    #[test]
+    fn test_native_classes() {
+        let lox = Lox::new();
+        let class = counter_class();
+        lox.register_class(class.clone());
+
+        assert_eq!(
+            lox.eval("var c = Counter(10); c.tick(); c.tick();"),
+            Ok(Value::Number(12.0))
+        );
+        assert_eq!(
+            lox.eval("c.step = 5; c.step += 1; c.tick(); c.count;"),
+            Ok(Value::Number(18.0))
+        );
+        assert_eq!(
+            lox.eval("var t = c.tick; t(); c.count;"),
+            Ok(Value::Number(24.0))
+        );
+        assert_eq!(
+            lox.eval("c.add(Counter(1)); [str(c), str(Counter), type(c), type(Counter)];")
+                .unwrap()
+                .to_string(),
+            "[\"<Counter instance>\", \"<class Counter>\", \"Counter\", \"class\"]"
+        );
+        assert_eq!(
+            lox.eval("c == c and c != Counter(25)"),
+            Ok(Value::Boolean(true))
+        );
+
+        // The host shares instances with scripts.
+        let Some(Value::Instance(ref c)) = lox.get_global("c") else {
+            panic!("c should be an instance");
+        };
+        let counter = c.downcast::<Counter>().unwrap();
+        assert_eq!(counter.borrow().count, 25.0);
+        counter.borrow_mut().count = 0.0;
+        assert_eq!(lox.eval("c.count"), Ok(Value::Number(0.0)));
+
+        let shared = Rc::new(RefCell::new(Counter {
+            count: 100.0,
+            step: 10.0,
+        }));
+        lox.set_global("shared", Instance::new(&class, shared.clone()).into_value());
+        lox.eval("shared.tick();").unwrap();
+        assert_eq!(shared.borrow().count, 110.0);
+        assert!(c.downcast::<String>().is_none());
+
+        // Scripts can't reach a value while the host is using it.
+        let borrowed = shared.borrow_mut();
+        assert_eq!(
+            lox.eval("shared.count").unwrap_err().to_string(),
+            "[line 1] Error: Counter instance is already in use.\n    at <script> (<script>:1)"
+        );
+        drop(borrowed);
+        assert_eq!(lox.eval("shared.count"), Ok(Value::Number(110.0)));
+    }
+
+    #[test]
+    fn test_native_class_errors() {
+        let lox = Lox::new();
+        lox.register_class(counter_class());
+        lox.register_class(ClassBuilder::<()>::new("Handle").build());
+        lox.eval("var c = Counter(0);").unwrap();
+
+        let error = |source| lox.eval(source).unwrap_err().to_string();
+        let message = |source| match lox.eval(source) {
+            Err(crate::lox::Error::Runtime(error)) => error.message,
+            result => panic!("{} should be a runtime error, got {:?}", source, result),
+        };
+        assert_eq!(
+            error("Counter()"),
+            "[line 1] Error: Expected 1 argument but got 0 for Counter(start).\n    at <script> (<script>:1)"
+        );
+        assert_eq!(message("Counter(\"a\")"), "Start must be a number.");
+        assert_eq!(
+            message("Handle()"),
+            "Handle can't be constructed from scripts."
+        );
+        assert_eq!(message("c.size"), "Undefined property 'size' on Counter.");
+        assert_eq!(
+            message("c.count = 1"),
+            "Property 'count' of Counter is read-only."
+        );
+        assert_eq!(
+            message("c.size = 1"),
+            "Can't set undefined property 'size' on Counter."
+        );
+        assert_eq!(
+            message("c.step = \"big\""),
+            "Expected number but got \"big\" for property 'step' of Counter."
+        );
+        assert_eq!(message("c.add(c)"), "Can't add a counter to itself.");
+        assert_eq!(message("c.add(1)"), "Expected object but got 1");
+        assert_eq!(
+            message("[].x = 1"),
+            "Only instances have settable properties."
+        );
+    }
+
+    /// A countdown from `n`, whose iterator is an instance of a second class.
+    #[test]
+    fn test_iterator_protocol() {
+        let lox = Lox::new();
+        let ticks = ClassBuilder::<f64>::new("Ticks")
+            .method("next", &[], |left, _, _| {
+                if *left == 0.0 {
+                    return Ok(Value::Nil);
+                }
+                *left -= 1.0;
+                Ok(Value::Number(*left + 1.0))
+            })
+            .build();
+        let countdown = ClassBuilder::<f64>::new("Countdown")
+            .constructor(&["n"], |_, arguments| match arguments[0] {
+                Value::Number(n) => Ok(n),
+                _ => Ok(0.0),
+            })
+            .method("iter", &[], move |n, _, _| {
+                Ok(Instance::new(&ticks, Rc::new(RefCell::new(*n))).into_value())
+            })
+            .build();
+        lox.register_class(countdown);
+        lox.register_class(counter_class());
+        assert_eq!(
+            lox.eval("var out = []; for (x in Countdown(3)) out.push(x); out;")
+                .unwrap()
+                .to_string(),
+            "[3, 2, 1]"
+        );
+        assert_eq!(
+            lox.eval("for (x in Counter(0)) {}").unwrap_err().to_string(),
+            "[line 1] Error: Can only iterate over lists, maps, strings, ranges and objects with an 'iter' method.\n    at <script> (<script>:1)"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use crate::{
+    interpreter::RuntimeError,
+    map::{Key, Map},
+    token::Token,
+    value::Value,
+};
+
+/// A Rust type that can be taken from a Lox value, as a native's argument.
+pub trait FromValue: Sized {
+    /// What the type is called in native signatures, e.g. `add(number, number)`.
+    const NAME: &'static str;
+
+    /// Converts `value`, or describes what was wrong with it, as in
+    /// `Expected number but got "a"`.
+    fn from_value(value: Value) -> Result<Self, String>;
+}
+
+/// A Rust type that can be turned into a Lox value, as a native's result.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+fn mismatch(expected: &str, value: &Value) -> String {
+    format!("Expected {} but got {}", expected, value.repr())
+}
+
+impl FromValue for Value {
+    const NAME: &'static str = "value";
+
+    fn from_value(value: Value) -> Result<Self, String> {
+        Ok(value)
+    }
+}
+
+impl FromValue for f64 {
+    const NAME: &'static str = "number";
+
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Number(n) => Ok(n),
+            _ => Err(mismatch(Self::NAME, &value)),
+        }
+    }
+}
+
+impl FromValue for bool {
+    const NAME: &'static str = "boolean";
+
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            _ => Err(mismatch(Self::NAME, &value)),
+        }
+    }
+}
+
+impl FromValue for String {
+    const NAME: &'static str = "string";
+
+    fn from_value(mut value: Value) -> Result<Self, String> {
+        match &mut value {
+            Value::Str(s) => Ok(std::mem::take(s)),
+            _ => Err(mismatch(Self::NAME, &value)),
+        }
+    }
+}
+
+/// `T::NAME` followed by `?`, built at compile time since names are constants.
+/// Names may be up to 31 bytes long.
+struct Optional<T>(PhantomData<T>);
+
+impl<T: FromValue> Optional<T> {
+    const BUFFER: ([u8; 32], usize) = {
+        let name = T::NAME.as_bytes();
+        let mut buffer = [0; 32];
+        let mut index = 0;
+        while index < name.len() {
+            buffer[index] = name[index];
+            index += 1;
+        }
+        buffer[index] = b'?';
+        (buffer, index + 1)
+    };
+
+    const NAME: &'static str = match std::str::from_utf8(Self::BUFFER.0.split_at(Self::BUFFER.1).0)
+    {
+        Ok(name) => name,
+        Err(_) => panic!("type names are UTF-8"),
+    };
+}
+
+/// Nil is `None`; anything else has to convert to `T`. Named like `number?`.
+impl<T: FromValue> FromValue for Option<T> {
+    const NAME: &'static str = Optional::<T>::NAME;
+
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Nil => Ok(None),
+            _ => T::from_value(value).map(Some).map_err(|error| {
+                // A mismatch of the value itself, rather than of an element, names
+                // the optional type.
+                let expected = format!("Expected {} ", T::NAME);
+                match error.strip_prefix(&expected) {
+                    Some(rest) => format!("Expected {} {}", Self::NAME, rest),
+                    None => error,
+                }
+            }),
+        }
+    }
+}
+
+/// Converts a copy of the list's elements.
+impl<T: FromValue> FromValue for Vec<T> {
+    const NAME: &'static str = "list";
+
+    fn from_value(value: Value) -> Result<Self, String> {
+        let Value::List(list) = &value else {
+            return Err(mismatch(Self::NAME, &value));
+        };
+        let elements = list.borrow().clone();
+        elements
+            .into_iter()
+            .map(|element| T::from_value(element).map_err(|error| format!("{} in list", error)))
+            .collect()
+    }
+}
+
+/// Converts a copy of a map whose keys are all strings.
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    const NAME: &'static str = "map";
+
+    fn from_value(value: Value) -> Result<Self, String> {
+        let Value::Map(map) = &value else {
+            return Err(mismatch(Self::NAME, &value));
+        };
+        let entries: Vec<_> = map.borrow().iter().cloned().collect();
+        entries
+            .into_iter()
+            .map(|(key, value)| match key {
+                Key::Str(key) => {
+                    let value =
+                        T::from_value(value).map_err(|error| format!("{} in map", error))?;
+                    Ok((key, value))
+                }
+                _ => Err(format!(
+                    "{} as map key",
+                    mismatch("string", &key.to_value())
+                )),
+            })
+            .collect()
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+/// A native that returns nothing returns nil.
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::Str(self.to_string())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Nil,
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        let elements = self.into_iter().map(IntoValue::into_value).collect();
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+}
+
+/// The map's entries are ordered by key, since a `HashMap` has no order of its own.
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        let mut entries: Vec<_> = self.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut map = Map::new();
+        for (key, value) in entries {
+            map.insert(Key::Str(key), value.into_value());
+        }
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+}
+
+/// What a native closure may return: a value, or a `Result` whose error raises a
+/// runtime error at the call, with the error's text as its message.
+pub trait IntoResult {
+    fn into_result(self) -> Result<Value, String>;
+}
+
+impl<T: IntoValue> IntoResult for T {
+    fn into_result(self) -> Result<Value, String> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue, E: fmt::Display> IntoResult for Result<T, E> {
+    fn into_result(self) -> Result<Value, String> {
+        self.map(IntoValue::into_value)
+            .map_err(|error| error.to_string())
+    }
+}
+
+/// A Rust closure usable as a native: its parameters convert from values and
+/// its result into one. `Args` is the tuple of parameter types, which tells the
+/// implementations for different arities apart.
+pub trait IntoNative<Args>: 'static {
+    /// The parameters' type names, one per argument the native takes.
+    const PARAMS: &'static [&'static str];
+
+    /// Converts `arguments`, of which there are as many as `PARAMS`, and calls the closure.
+    fn call(&self, name: &str, paren: &Token, arguments: Vec<Value>)
+        -> Result<Value, RuntimeError>;
+}
+
+/// Converts the argument at `index`, reporting a mismatch against the native's signature.
+fn argument<T: FromValue>(
+    name: &str,
+    params: &[&str],
+    paren: &Token,
+    index: usize,
+    value: Value,
+) -> Result<T, RuntimeError> {
+    T::from_value(value).map_err(|error| {
+        let message = format!(
+            "{} for argument {} of {}({}).",
+            error,
+            index + 1,
+            name,
+            params.join(", ")
+        );
+        RuntimeError::new(paren, &message)
+    })
+}
+
+macro_rules! impl_into_native {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoResult,
+            $($arg: FromValue,)*
+        {
+            const PARAMS: &'static [&'static str] = &[$($arg::NAME),*];
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(
+                &self,
+                name: &str,
+                paren: &Token,
+                arguments: Vec<Value>,
+            ) -> Result<Value, RuntimeError> {
+                let mut arguments = arguments.into_iter().enumerate();
+                $(
+                    let (index, value) = arguments.next().unwrap();
+                    let $arg: $arg = argument(name, Self::PARAMS, paren, index, value)?;
+                )*
+                self($($arg),*)
+                    .into_result()
+                    .map_err(|message| RuntimeError::new(paren, &message))
+            }
+        }
+    };
+}
+
+impl_into_native!();
+impl_into_native!(A);
+impl_into_native!(A, B);
+impl_into_native!(A, B, C);
+impl_into_native!(A, B, C, D);
+impl_into_native!(A, B, C, D, E);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::lox::Lox;
+
+    #[test]
+    fn test_conversions() {
+        let list = vec![Some(1.0), None].into_value();
+        assert_eq!(list.to_string(), "[1, nil]");
+        assert_eq!(
+            Vec::<Option<f64>>::from_value(list),
+            Ok(vec![Some(1.0), None])
+        );
+
+        let map = HashMap::from([("b".to_string(), true), ("a".to_string(), false)]);
+        let value = map.clone().into_value();
+        assert_eq!(value.to_string(), "{\"a\": false, \"b\": true}");
+        assert_eq!(HashMap::<String, bool>::from_value(value), Ok(map));
+
+        assert_eq!(
+            String::from_value(Value::Number(1.0)),
+            Err("Expected string but got 1".to_string())
+        );
+        assert_eq!(
+            Vec::<f64>::from_value(vec!["a"].into_value()),
+            Err("Expected number but got \"a\" in list".to_string())
+        );
+        assert_eq!(
+            Vec::<f64>::from_value(Value::Nil),
+            Err("Expected list but got nil".to_string())
+        );
+    }
+
+    #[test]
+    fn test_typed_natives() {
+        let lox = Lox::new();
+        lox.register_fn("add", |a: f64, b: f64| a + b);
+        lox.register_fn("join", |parts: Vec<String>, separator: Option<String>| {
+            parts.join(separator.as_deref().unwrap_or(","))
+        });
+        lox.register_fn("answer", || 42.0);
+        lox.register_fn("sqrt", |n: f64| {
+            if n < 0.0 {
+                return Err(format!("Can't take the square root of {}.", n));
+            }
+            Ok(n.sqrt())
+        });
+        lox.register_fn("keys", |map: HashMap<String, Value>| {
+            let mut keys: Vec<String> = map.into_keys().collect();
+            keys.sort();
+            keys
+        });
+
+        assert_eq!(lox.eval("add(1, 2)"), Ok(Value::Number(3.0)));
+        assert_eq!(
+            lox.eval("join([\"a\", \"b\"], nil) + join([\"c\"], \"-\")"),
+            Ok(Value::Str("a,bc".to_string()))
+        );
+        assert_eq!(lox.eval("answer()"), Ok(Value::Number(42.0)));
+        assert_eq!(lox.eval("sqrt(4)"), Ok(Value::Number(2.0)));
+        assert_eq!(
+            lox.eval("var message; try { sqrt(-1); } catch (e) { message = e.message; } message;"),
+            Ok(Value::Str("Can't take the square root of -1.".to_string()))
+        );
+        assert_eq!(
+            lox.eval("keys({\"y\": 1, \"x\": nil})")
+                .unwrap()
+                .to_string(),
+            "[\"x\", \"y\"]"
+        );
+
+        let error = |source| lox.eval(source).unwrap_err().to_string();
+        assert_eq!(
+            error("add(1)"),
+            "[line 1] Error: Expected 2 arguments but got 1 for add(number, number).\n    at <script> (<script>:1)"
+        );
+        assert_eq!(
+            error("add(1, \"2\")"),
+            "[line 1] Error: Expected number but got \"2\" for argument 2 of add(number, number).\n    at <script> (<script>:1)"
+        );
+        assert_eq!(
+            error("join([1], nil)"),
+            "[line 1] Error: Expected string but got 1 in list for argument 1 of join(list, string?).\n    at <script> (<script>:1)"
+        );
+        assert_eq!(
+            error("join([], 1)"),
+            "[line 1] Error: Expected string? but got 1 for argument 2 of join(list, string?).\n    at <script> (<script>:1)"
+        );
+        assert_eq!(
+            error("keys({1: 2})"),
+            "[line 1] Error: Expected string but got 1 as map key for argument 1 of keys(map).\n    at <script> (<script>:1)"
+        );
+    }
+}
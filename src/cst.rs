@@ -0,0 +1,744 @@
+use crate::{
+    parser::{rule, Associativity, Precedence},
+    scanner::Scanner,
+    token::{Token, TokenType, Trivia, TriviaKind},
+};
+
+/// A token together with the trivia around it.
+/// Trailing trivia runs up to and including the end of the token's line,
+/// everything after that belongs to the next token's leading trivia.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstToken {
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+    pub trailing: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyntaxKind {
+    Root,
+    Literal,
+    Grouping,
+    Binary,
+    Unary,
+    Logical,
+    Ternary,
+    Comma,
+    Variable,
+    Assign,
+    Increment,
+    Call,
+    List,
+    Map,
+    Index,
+    Get,
+    Lambda,
+    Params,
+
+    // Statements.
+    Expression,
+    Print,
+    Var,
+    Block,
+    If,
+    While,
+    For,
+    ForIn,
+    Break,
+    Continue,
+    Function,
+    Return,
+    Throw,
+    Try,
+    Error,
+}
+
+/// Lossless concrete syntax tree. Unlike `Expr` and `Stmt` it keeps every token,
+/// including parentheses, semicolons and tokens the grammar could not make sense of
+/// (under `Error` nodes). Labels are kept in the loop they label.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstNode {
+    Token(CstToken),
+    Node(SyntaxKind, Vec<CstNode>),
+}
+
+impl CstNode {
+    /// Indented outline of the tree, one node or token per line.
+    pub fn dump(&self) -> String {
+        let mut builder = String::new();
+        self.write_dump(&mut builder, 0);
+        builder
+    }
+
+    fn write_dump(&self, builder: &mut String, depth: usize) {
+        builder.push_str(&"  ".repeat(depth));
+        match self {
+            CstNode::Token(token) => {
+                builder.push_str(&format!(
+                    "{:?} {:?}\n",
+                    token.token.token_type, token.token.lexeme
+                ));
+            }
+            CstNode::Node(kind, children) => {
+                builder.push_str(&format!("{:?}\n", kind));
+                for child in children {
+                    child.write_dump(builder, depth + 1);
+                }
+            }
+        }
+    }
+
+    /// Reprints the node exactly as it appeared in the source.
+    pub fn text(&self) -> String {
+        let mut builder = String::new();
+        self.write_text(&mut builder);
+        builder
+    }
+
+    fn write_text(&self, builder: &mut String) {
+        match self {
+            CstNode::Token(token) => {
+                for trivia in &token.leading {
+                    builder.push_str(&trivia.text);
+                }
+                builder.push_str(&token.token.lexeme);
+                for trivia in &token.trailing {
+                    builder.push_str(&trivia.text);
+                }
+            }
+            CstNode::Node(_, children) => {
+                for child in children {
+                    child.write_text(builder);
+                }
+            }
+        }
+    }
+}
+
+/// Scans `source` in lossless mode and distributes the trivia between tokens.
+pub fn lossless_tokens(source: &str) -> Vec<CstToken> {
+    let mut scanner = Scanner::new(source);
+    let mut tokens: Vec<CstToken> = Vec::new();
+    while let Some((mut trivia, token)) = scanner.next_with_trivia() {
+        if let Some(previous) = tokens.last_mut() {
+            let end_of_line = trivia
+                .iter()
+                .position(|trivia| trivia.kind == TriviaKind::Newline)
+                .map_or(trivia.len(), |newline| newline + 1);
+            previous.trailing = trivia.drain(..end_of_line).collect();
+        }
+        tokens.push(CstToken {
+            leading: trivia,
+            token,
+            trailing: Vec::new(),
+        });
+    }
+    tokens
+}
+
+pub fn parse_cst(source: &str) -> CstNode {
+    CstParser::new(lossless_tokens(source)).parse()
+}
+
+/// Error-tolerant parser producing a `CstNode`. It follows the same grammar as
+/// `Parser` but never fails: anything unexpected is wrapped in an `Error` node,
+/// and missing tokens are left out.
+pub struct CstParser {
+    tokens: Vec<CstToken>,
+    current: usize,
+}
+
+impl CstParser {
+    pub fn new(tokens: Vec<CstToken>) -> CstParser {
+        CstParser { tokens, current: 0 }
+    }
+
+    pub fn parse(&mut self) -> CstNode {
+        let mut children = Vec::new();
+        while !self.is_next(&[TokenType::Eof]) {
+            children.push(self.declaration());
+        }
+        if self.current < self.tokens.len() {
+            children.push(self.bump());
+        }
+        CstNode::Node(SyntaxKind::Root, children)
+    }
+
+    /// The type of the token `offset` tokens ahead.
+    fn peek_type_at(&self, offset: usize) -> TokenType {
+        self.tokens
+            .get(self.current + offset)
+            .map_or(TokenType::Eof, |token| token.token.token_type)
+    }
+
+    fn peek_type(&self) -> TokenType {
+        self.peek_type_at(0)
+    }
+
+    fn is_next(&self, expected: &[TokenType]) -> bool {
+        expected.contains(&self.peek_type())
+    }
+
+    fn bump(&mut self) -> CstNode {
+        let token = self.tokens[self.current].clone();
+        self.current += 1;
+        CstNode::Token(token)
+    }
+
+    /// Adds the next token to `children` if it is of the `expected` type.
+    fn expect(&mut self, children: &mut Vec<CstNode>, expected: TokenType) {
+        if self.is_next(&[expected]) {
+            children.push(self.bump());
+        }
+    }
+
+    /// Adds an expression to `children` unless the statement ends first.
+    fn expect_expression(&mut self, children: &mut Vec<CstNode>) {
+        if !self.is_next(&[TokenType::Semicolon, TokenType::RightBrace, TokenType::Eof]) {
+            children.push(self.expression());
+        }
+    }
+
+    fn declaration(&mut self) -> CstNode {
+        match self.peek_type() {
+            TokenType::Var => self.var_declaration(),
+            TokenType::Fun if self.peek_type_at(1) == TokenType::Identifier => {
+                let mut children = vec![self.bump(), self.bump()];
+                self.function(&mut children);
+                CstNode::Node(SyntaxKind::Function, children)
+            }
+            _ => self.statement(),
+        }
+    }
+
+    fn var_declaration(&mut self) -> CstNode {
+        let mut children = vec![self.bump()];
+        self.expect(&mut children, TokenType::Identifier);
+        if self.is_next(&[TokenType::Equal]) {
+            children.push(self.bump());
+            self.expect_expression(&mut children);
+        }
+        self.expect(&mut children, TokenType::Semicolon);
+        CstNode::Node(SyntaxKind::Var, children)
+    }
+
+    /// The parameter list and body following `fun name` or `fun`.
+    fn function(&mut self, children: &mut Vec<CstNode>) {
+        if self.is_next(&[TokenType::LeftParen]) {
+            children.push(self.parameters());
+        }
+        if self.is_next(&[TokenType::LeftBrace]) {
+            children.push(self.block());
+        }
+    }
+
+    /// `(a, b = 1, ...rest)`, up to the first token that can't be part of it.
+    fn parameters(&mut self) -> CstNode {
+        let mut children = vec![self.bump()];
+        loop {
+            match self.peek_type() {
+                TokenType::Identifier | TokenType::Comma | TokenType::DotDotDot => {
+                    children.push(self.bump());
+                }
+                TokenType::Equal => {
+                    children.push(self.bump());
+                    children.push(self.parse_precedence(Precedence::Assignment));
+                }
+                _ => break,
+            }
+        }
+        self.expect(&mut children, TokenType::RightParen);
+        CstNode::Node(SyntaxKind::Params, children)
+    }
+
+    fn statement(&mut self) -> CstNode {
+        let kind = match self.peek_type() {
+            TokenType::LeftBrace => return self.block(),
+            TokenType::If => return self.if_statement(),
+            TokenType::While | TokenType::For => return self.loop_statement(Vec::new()),
+            TokenType::Try => return self.try_statement(),
+            TokenType::Identifier if self.peek_type_at(1) == TokenType::Colon => {
+                let label = vec![self.bump(), self.bump()];
+                if !self.is_next(&[TokenType::While, TokenType::For]) {
+                    return CstNode::Node(SyntaxKind::Error, label);
+                }
+                return self.loop_statement(label);
+            }
+            TokenType::Semicolon => return CstNode::Node(SyntaxKind::Error, vec![self.bump()]),
+            TokenType::Print => SyntaxKind::Print,
+            TokenType::Return => SyntaxKind::Return,
+            TokenType::Throw => SyntaxKind::Throw,
+            TokenType::Break => SyntaxKind::Break,
+            TokenType::Continue => SyntaxKind::Continue,
+            _ => {
+                let children = vec![self.expression()];
+                return self.end_statement(SyntaxKind::Expression, children);
+            }
+        };
+        let mut children = vec![self.bump()];
+        if matches!(kind, SyntaxKind::Break | SyntaxKind::Continue) {
+            self.expect(&mut children, TokenType::Identifier);
+        } else {
+            self.expect_expression(&mut children);
+        }
+        self.end_statement(kind, children)
+    }
+
+    /// A statement of `kind`, ended by the semicolon if there is one.
+    fn end_statement(&mut self, kind: SyntaxKind, mut children: Vec<CstNode>) -> CstNode {
+        self.expect(&mut children, TokenType::Semicolon);
+        CstNode::Node(kind, children)
+    }
+
+    fn block(&mut self) -> CstNode {
+        let mut children = vec![self.bump()];
+        while !self.is_next(&[TokenType::RightBrace, TokenType::Eof]) {
+            children.push(self.declaration());
+        }
+        self.expect(&mut children, TokenType::RightBrace);
+        CstNode::Node(SyntaxKind::Block, children)
+    }
+
+    fn if_statement(&mut self) -> CstNode {
+        let mut children = vec![self.bump()];
+        self.condition(&mut children);
+        children.push(self.statement());
+        if self.is_next(&[TokenType::Else]) {
+            children.push(self.bump());
+            children.push(self.statement());
+        }
+        CstNode::Node(SyntaxKind::If, children)
+    }
+
+    /// A parenthesized condition, as of `if` and `while`.
+    fn condition(&mut self, children: &mut Vec<CstNode>) {
+        self.expect(children, TokenType::LeftParen);
+        self.condition_end(children);
+    }
+
+    /// A `while`, `for` or `for`-`in` loop, after its label if it has one.
+    fn loop_statement(&mut self, mut children: Vec<CstNode>) -> CstNode {
+        let keyword = self.peek_type();
+        children.push(self.bump());
+        if keyword == TokenType::While {
+            self.condition(&mut children);
+            children.push(self.statement());
+            return CstNode::Node(SyntaxKind::While, children);
+        }
+
+        self.expect(&mut children, TokenType::LeftParen);
+        let name = if self.is_next(&[TokenType::Var]) {
+            1
+        } else {
+            0
+        };
+        if self.peek_type_at(name) == TokenType::Identifier
+            && self.peek_type_at(name + 1) == TokenType::In
+        {
+            for _ in 0..name + 2 {
+                children.push(self.bump());
+            }
+            self.condition_end(&mut children);
+            children.push(self.statement());
+            return CstNode::Node(SyntaxKind::ForIn, children);
+        }
+
+        match self.peek_type() {
+            TokenType::Semicolon => children.push(self.bump()),
+            TokenType::Var => children.push(self.var_declaration()),
+            _ => {
+                let initializer = vec![self.expression()];
+                children.push(self.end_statement(SyntaxKind::Expression, initializer));
+            }
+        }
+        self.expect_expression(&mut children);
+        self.expect(&mut children, TokenType::Semicolon);
+        self.condition_end(&mut children);
+        children.push(self.statement());
+        CstNode::Node(SyntaxKind::For, children)
+    }
+
+    /// The rest of a parenthesized clause: an expression unless it is empty, and
+    /// the closing parenthesis.
+    fn condition_end(&mut self, children: &mut Vec<CstNode>) {
+        if !self.is_next(&[TokenType::RightParen, TokenType::Eof]) {
+            children.push(self.expression());
+        }
+        self.expect(children, TokenType::RightParen);
+    }
+
+    fn try_statement(&mut self) -> CstNode {
+        let mut children = vec![self.bump()];
+        if self.is_next(&[TokenType::LeftBrace]) {
+            children.push(self.block());
+        }
+        if self.is_next(&[TokenType::Catch]) {
+            children.push(self.bump());
+            self.expect(&mut children, TokenType::LeftParen);
+            self.expect(&mut children, TokenType::Identifier);
+            self.expect(&mut children, TokenType::RightParen);
+            if self.is_next(&[TokenType::LeftBrace]) {
+                children.push(self.block());
+            }
+        }
+        if self.is_next(&[TokenType::Finally]) {
+            children.push(self.bump());
+            if self.is_next(&[TokenType::LeftBrace]) {
+                children.push(self.block());
+            }
+        }
+        CstNode::Node(SyntaxKind::Try, children)
+    }
+
+    fn expression(&mut self) -> CstNode {
+        self.parse_precedence(Precedence::None.next())
+    }
+
+    /// Precedence climbing over the same operator table as `Parser`.
+    fn parse_precedence(&mut self, precedence: Precedence) -> CstNode {
+        let mut expr = self.unary();
+        loop {
+            let next = rule(self.peek_type());
+            if next.infix.is_none() || precedence > next.precedence {
+                return expr;
+            }
+            let op_type = self.peek_type();
+            let mut children = vec![expr, self.bump()];
+            if matches!(op_type, TokenType::PlusPlus | TokenType::MinusMinus) {
+                expr = CstNode::Node(SyntaxKind::Increment, children);
+                continue;
+            }
+            if op_type == TokenType::LeftParen {
+                self.delimited(&mut children, TokenType::RightParen);
+                expr = CstNode::Node(SyntaxKind::Call, children);
+                continue;
+            }
+            if op_type == TokenType::LeftBracket {
+                self.delimited(&mut children, TokenType::RightBracket);
+                expr = CstNode::Node(SyntaxKind::Index, children);
+                continue;
+            }
+            if op_type == TokenType::Dot {
+                if self.is_next(&[TokenType::Identifier]) {
+                    children.push(self.bump());
+                }
+                expr = CstNode::Node(SyntaxKind::Get, children);
+                continue;
+            }
+            if op_type == TokenType::Question {
+                children.push(self.expression());
+                if self.is_next(&[TokenType::Colon]) {
+                    children.push(self.bump());
+                }
+            }
+            children.push(match next.associativity {
+                Associativity::Left => self.parse_precedence(next.precedence.next()),
+                Associativity::Right => self.parse_precedence(next.precedence),
+            });
+            let kind = match op_type {
+                TokenType::Question => SyntaxKind::Ternary,
+                TokenType::Or | TokenType::And => SyntaxKind::Logical,
+                TokenType::Comma => SyntaxKind::Comma,
+                TokenType::Equal
+                | TokenType::PlusEqual
+                | TokenType::MinusEqual
+                | TokenType::StarEqual
+                | TokenType::SlashEqual
+                | TokenType::PercentEqual => SyntaxKind::Assign,
+                _ => SyntaxKind::Binary,
+            };
+            expr = CstNode::Node(kind, children);
+        }
+    }
+
+    /// Items of an argument list, list literal or subscript up to the `closing`
+    /// token. Commas and colons (of named arguments and slices) separate items.
+    fn delimited(&mut self, children: &mut Vec<CstNode>, closing: TokenType) {
+        while !self.is_next(&[closing, TokenType::Eof]) {
+            if self.is_next(&[TokenType::Comma, TokenType::Colon]) {
+                children.push(self.bump());
+            } else {
+                children.push(self.parse_precedence(Precedence::Assignment));
+            }
+        }
+        if self.is_next(&[closing]) {
+            children.push(self.bump());
+        }
+    }
+
+    fn unary(&mut self) -> CstNode {
+        if self.is_next(&[TokenType::Bang, TokenType::Minus, TokenType::Tilde]) {
+            let op = self.bump();
+            let right = self.parse_precedence(Precedence::Unary);
+            return CstNode::Node(SyntaxKind::Unary, vec![op, right]);
+        }
+        if self.is_next(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let op = self.bump();
+            let right = self.parse_precedence(Precedence::Unary);
+            return CstNode::Node(SyntaxKind::Increment, vec![op, right]);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> CstNode {
+        match self.peek_type() {
+            TokenType::False
+            | TokenType::True
+            | TokenType::Nil
+            | TokenType::Number
+            | TokenType::StringLiteral => CstNode::Node(SyntaxKind::Literal, vec![self.bump()]),
+            TokenType::Identifier => CstNode::Node(SyntaxKind::Variable, vec![self.bump()]),
+            TokenType::Fun => {
+                let mut children = vec![self.bump()];
+                self.function(&mut children);
+                CstNode::Node(SyntaxKind::Lambda, children)
+            }
+            TokenType::LeftParen if self.is_arrow_function() => {
+                let mut children = vec![self.parameters()];
+                self.expect(&mut children, TokenType::Arrow);
+                children.push(self.parse_precedence(Precedence::Assignment));
+                CstNode::Node(SyntaxKind::Lambda, children)
+            }
+            TokenType::LeftParen => {
+                let mut children = vec![self.bump()];
+                if !self.is_next(&[TokenType::RightParen, TokenType::Eof]) {
+                    children.push(self.expression());
+                }
+                if self.is_next(&[TokenType::RightParen]) {
+                    children.push(self.bump());
+                }
+                CstNode::Node(SyntaxKind::Grouping, children)
+            }
+            TokenType::LeftBracket => {
+                let mut children = vec![self.bump()];
+                self.delimited(&mut children, TokenType::RightBracket);
+                CstNode::Node(SyntaxKind::List, children)
+            }
+            TokenType::LeftBrace => {
+                let mut children = vec![self.bump()];
+                self.delimited(&mut children, TokenType::RightBrace);
+                CstNode::Node(SyntaxKind::Map, children)
+            }
+            TokenType::Eof => CstNode::Node(SyntaxKind::Error, Vec::new()),
+            _ => CstNode::Node(SyntaxKind::Error, vec![self.bump()]),
+        }
+    }
+
+    /// Whether the parenthesis ahead starts the parameters of `(a, b) => a + b`,
+    /// that is whether its matching parenthesis is followed by `=>`.
+    fn is_arrow_function(&self) -> bool {
+        let mut depth = 0;
+        for (offset, token) in self.tokens[self.current..].iter().enumerate() {
+            match token.token.token_type {
+                TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+                TokenType::Eof => return false,
+                _ => {}
+            }
+            if depth == 0 {
+                return self.peek_type_at(offset + 1) == TokenType::Arrow;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: &[&str] = &[
+        "",
+        "1 + 2 * 3",
+        "1 + 2 * 3\n",
+        "  (5.2 - 2.5)   // trailing comment\n\n// leading comment\n-!true",
+        "\"str\" + \"ing\" == \"string\";\r\n\tnil != false;",
+        "((((42))))",
+        "1 - 2 - 3 >= 4 < 5",
+        "a ? b : c ? d : e, 1 or 2 and 3",
+        "x ? : y ?",
+        "~1 << 2 ** -3 % 4 ~/ 5 & 6 | 7 ^ 8 >> 9",
+        "var a = 1; a += 2; print a++ + --a; 1 = 2;",
+        "\"héllo\" + ünïcode @ # $ ;",
+        "(1 + ",
+        ") ) ( 1",
+        "{ class fun var } . ,",
+        "\"multi\nline\" // no newline at end",
+        "f(1, g(2)(3), ) (a, b) => a; fun (x) { return x; }",
+        "[1, [2, 3]][0][1:] xs[-1] = xs.pop() + xs[:2].len(",
+        "{\"a\": {1: [true]}, nil: 2,}[\"a\"] {:}",
+        "for (x in 0..n + 1) for (var c in \"ab\") 1..=2 ..",
+        "try { throw 1; } catch (e) { e.message; } finally {} catch",
+        "p.x = 1; p.y += p.x; p.z++ = .",
+        "\"abc",
+        "print \"unterminated\n  string;",
+        "if (a) { print a; } else if (b) c(); else {}",
+        "outer: while (true) { for (;;) break outer; continue; } inner: 1;",
+        "for (var i = 0; i < 3; i++) {} for (i = 0; ; ) for (",
+        "fun f(a, b = 1, ...rest) { return; } fun g( { return a }",
+        "while x) { var = ; } else }",
+    ];
+
+    fn assert_round_trip(source: &str) {
+        assert_eq!(
+            parse_cst(source).text(),
+            source,
+            "round trip of {:?}",
+            source
+        );
+    }
+
+    #[test]
+    fn test_corpus_round_trip() {
+        for source in CORPUS {
+            assert_round_trip(source);
+        }
+    }
+
+    /// Property test: any concatenation of valid lexemes and trivia reprints unchanged.
+    #[test]
+    fn test_random_round_trip() {
+        #[rustfmt::skip]
+        const PIECES: &[&str] = &[
+            " ", "  ", "\t", "\n", "\r\n", "// comment", "// c\n", "(", ")", "{", "}", ",", ".",
+            "-", "+", ";", "/", "*", "!", "!=", "=", "==", ">", ">=", "<", "<=", "foo", "_bar1",
+            "12", "?", ":", "or", "**", "%", "~/", "~", "&", "|", "^", "<<", ">>", "++", "--", "+=",
+            "-=", "*=", "/=", "%=", "var", "print", "3.5", "\"s\"", "\"a b\"", "and", "nil", "true",
+            "false", "@", "é", "#", "=>", "fun", "return", "...", "x:", "[", "]", ".len", "..",
+            "..=", "in", "throw", "try", "catch", "finally", "\"", "if", "else", "while", "for",
+            "break", "continue",
+        ];
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..500 {
+            let mut source = String::new();
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            for _ in 0..state % 40 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                source.push_str(PIECES[(state % PIECES.len() as u64) as usize]);
+            }
+            assert_round_trip(&source);
+        }
+    }
+
+    #[test]
+    fn test_trivia_attachment() {
+        let tokens = lossless_tokens("1 // one\n  + 2");
+        assert_eq!(tokens[0].token.lexeme, "1");
+        let trailing: Vec<&str> = tokens[0].trailing.iter().map(|t| &*t.text).collect();
+        assert_eq!(trailing, [" ", "// one", "\n"]);
+        let leading: Vec<&str> = tokens[1].leading.iter().map(|t| &*t.text).collect();
+        assert_eq!(leading, ["  "]);
+        assert_eq!(tokens[1].token.lexeme, "+");
+        assert_eq!(tokens[3].token.token_type, TokenType::Eof);
+    }
+
+    #[test]
+    fn test_tree_shape() {
+        let root = parse_cst("-(1 + 2) * 3");
+        let expected = "\
+Root
+  Expression
+    Binary
+      Unary
+        Minus \"-\"
+        Grouping
+          LeftParen \"(\"
+          Binary
+            Literal
+              Number \"1\"
+            Plus \"+\"
+            Literal
+              Number \"2\"
+          RightParen \")\"
+      Star \"*\"
+      Literal
+        Number \"3\"
+  Eof \"\"
+";
+        assert_eq!(root.dump(), expected);
+    }
+
+    #[test]
+    fn test_call_shape() {
+        let root = parse_cst("f(1, x)");
+        let expected = "\
+Root
+  Expression
+    Call
+      Variable
+        Identifier \"f\"
+      LeftParen \"(\"
+      Literal
+        Number \"1\"
+      Comma \",\"
+      Variable
+        Identifier \"x\"
+      RightParen \")\"
+  Eof \"\"
+";
+        assert_eq!(root.dump(), expected);
+    }
+
+    #[test]
+    fn test_statement_shape() {
+        let root = parse_cst("var x = 1; if (x) { print x; }");
+        let expected = "\
+Root
+  Var
+    Var \"var\"
+    Identifier \"x\"
+    Equal \"=\"
+    Literal
+      Number \"1\"
+    Semicolon \";\"
+  If
+    If \"if\"
+    LeftParen \"(\"
+    Variable
+      Identifier \"x\"
+    RightParen \")\"
+    Block
+      LeftBrace \"{\"
+      Print
+        Print \"print\"
+        Variable
+          Identifier \"x\"
+        Semicolon \";\"
+      RightBrace \"}\"
+  Eof \"\"
+";
+        assert_eq!(root.dump(), expected);
+    }
+
+    #[test]
+    fn test_statement_kinds() {
+        let kinds = |source: &str| match parse_cst(source) {
+            CstNode::Node(_, children) => children
+                .iter()
+                .filter_map(|child| match child {
+                    CstNode::Node(kind, _) => Some(*kind),
+                    CstNode::Token(_) => None,
+                })
+                .collect::<Vec<_>>(),
+            CstNode::Token(_) => unreachable!(),
+        };
+        use SyntaxKind::*;
+        assert_eq!(
+            kinds("fun f(a) { return a; } f(1); { 2; } throw 3;"),
+            [Function, Expression, Block, Throw]
+        );
+        assert_eq!(
+            kinds("outer: for (x in xs) break outer; while (y) continue; for (;;) {}"),
+            [ForIn, While, For]
+        );
+        assert_eq!(
+            kinds("try {} catch (e) {} finally {} var f = (a) => a;"),
+            [Try, Var]
+        );
+        assert_eq!(kinds("; } x: print"), [Error, Expression, Error, Print]);
+    }
+}
@@ -0,0 +1,70 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{interpreter::RuntimeError, token::Token, value::Value};
+
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// The environment `depth` scopes out from `environment`, where the resolver
+    /// found a variable declared.
+    pub fn ancestor(
+        environment: &Rc<RefCell<Environment>>,
+        depth: usize,
+    ) -> Rc<RefCell<Environment>> {
+        let mut ancestor = environment.clone();
+        for _ in 0..depth {
+            let enclosing = ancestor.borrow().enclosing.clone();
+            ancestor = enclosing.expect("Resolver only counts enclosing scopes");
+        }
+        ancestor
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    /// Looks up a variable by name, without a token to report a missing one at.
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        match (self.values.get(name), &self.enclosing) {
+            (Some(value), _) => Some(value.clone()),
+            (None, Some(enclosing)) => enclosing.borrow().lookup(name),
+            (None, None) => None,
+        }
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        match (self.values.get(&*name.lexeme), &self.enclosing) {
+            (Some(value), _) => Ok(value.clone()),
+            (None, Some(enclosing)) => enclosing.borrow().get(name),
+            (None, None) => Err(undefined(name)),
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        match (self.values.get_mut(&*name.lexeme), &self.enclosing) {
+            (Some(slot), _) => {
+                *slot = value;
+                Ok(())
+            }
+            (None, Some(enclosing)) => enclosing.borrow_mut().assign(name, value),
+            (None, None) => Err(undefined(name)),
+        }
+    }
+}
+
+fn undefined(name: &Token) -> RuntimeError {
+    RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme))
+}
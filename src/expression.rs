@@ -1,30 +1,236 @@
-use crate::token::{Literal, Token};
+use std::rc::Rc;
+
+use crate::{
+    ast::{Ast, ExprId},
+    statement::FunctionDecl,
+    token::{Literal, Token},
+};
 
 #[derive(Debug)]
 pub enum Expr {
     Literal(Literal),
-    Grouping(Box<Expr>),
-    Binary(Box<Expr>, Token, Box<Expr>),
-    Unary(Token, Box<Expr>),
+    Grouping(ExprId),
+    Binary(ExprId, Token, ExprId),
+    Unary(Token, ExprId),
+    Logical(ExprId, Token, ExprId),
+    /// `condition ? then_branch : else_branch`
+    Ternary(ExprId, ExprId, ExprId),
+    /// `left, right`: evaluates both, yields `right`.
+    Comma(ExprId, ExprId),
+    Variable(Token),
+    Assign(Token, ExprId),
+    /// `target op= value`, where `target` is an assignable expression.
+    CompoundAssign(ExprId, Token, ExprId),
+    /// `++target` / `--target` when the flag is true (yields the new value),
+    /// `target++` / `target--` otherwise (yields the old value).
+    Increment(ExprId, Token, bool),
+    /// Callee, closing parenthesis (for error locations), positional arguments and
+    /// named `name: value` arguments, which always come last.
+    Call(ExprId, Token, Vec<ExprId>, Vec<(Token, ExprId)>),
+    /// `fun (params) { body }` or `(params) => expression`.
+    Lambda(Rc<FunctionDecl>),
+    /// `[a, b, c]`
+    List(Vec<ExprId>),
+    /// `{key: value, ...}`, with the opening brace for error locations.
+    Map(Token, Vec<(ExprId, ExprId)>),
+    /// `object[index]`, with the opening bracket for error locations.
+    Index(ExprId, Token, ExprId),
+    /// `object[start:end]`, where either bound may be left out.
+    Slice(ExprId, Token, Option<ExprId>, Option<ExprId>),
+    /// `object[index] = value`
+    SetIndex(ExprId, Token, ExprId, ExprId),
+    /// `object.name`
+    Get(ExprId, Token),
+    /// `object.name = value`
+    Set(ExprId, Token, ExprId),
 }
 
+/// A pass over expressions that only reads its own state, such as the AST printer.
 pub trait Visitor<R> {
     fn visit_literal(&self, literal: &Literal) -> R;
-    fn visit_grouping(&self, expr: &Box<Expr>) -> R;
-    fn visit_binary(&self, left: &Box<Expr>, operator: &Token, right: &Box<Expr>) -> R;
-    fn visit_unary(&self, operator: &Token, right: &Box<Expr>) -> R;
+    fn visit_grouping(&self, expr: ExprId) -> R;
+    fn visit_binary(&self, left: ExprId, operator: &Token, right: ExprId) -> R;
+    fn visit_unary(&self, operator: &Token, right: ExprId) -> R;
+    fn visit_logical(&self, left: ExprId, operator: &Token, right: ExprId) -> R;
+    fn visit_ternary(&self, condition: ExprId, then_branch: ExprId, else_branch: ExprId) -> R;
+    fn visit_comma(&self, left: ExprId, right: ExprId) -> R;
+    fn visit_variable(&self, name: &Token) -> R;
+    fn visit_assign(&self, name: &Token, value: ExprId) -> R;
+    fn visit_compound_assign(&self, target: ExprId, operator: &Token, value: ExprId) -> R;
+    fn visit_increment(&self, target: ExprId, operator: &Token, prefix: bool) -> R;
+    fn visit_call(
+        &self,
+        callee: ExprId,
+        paren: &Token,
+        arguments: &[ExprId],
+        named: &[(Token, ExprId)],
+    ) -> R;
+    fn visit_lambda(&self, declaration: &Rc<FunctionDecl>) -> R;
+    fn visit_list(&self, elements: &[ExprId]) -> R;
+    fn visit_map(&self, brace: &Token, entries: &[(ExprId, ExprId)]) -> R;
+    fn visit_index(&self, object: ExprId, bracket: &Token, index: ExprId) -> R;
+    fn visit_slice(
+        &self,
+        object: ExprId,
+        bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> R;
+    fn visit_set_index(&self, object: ExprId, bracket: &Token, index: ExprId, value: ExprId) -> R;
+    fn visit_get(&self, object: ExprId, name: &Token) -> R;
+    fn visit_set(&self, object: ExprId, name: &Token, value: ExprId) -> R;
+}
+
+/// A pass over expressions that updates its own state as it goes, such as the
+/// interpreter. The tree itself is still only borrowed.
+pub trait VisitorMut<R> {
+    fn visit_literal(&mut self, literal: &Literal) -> R;
+    fn visit_grouping(&mut self, expr: ExprId) -> R;
+    fn visit_binary(&mut self, left: ExprId, operator: &Token, right: ExprId) -> R;
+    fn visit_unary(&mut self, operator: &Token, right: ExprId) -> R;
+    fn visit_logical(&mut self, left: ExprId, operator: &Token, right: ExprId) -> R;
+    fn visit_ternary(&mut self, condition: ExprId, then_branch: ExprId, else_branch: ExprId) -> R;
+    fn visit_comma(&mut self, left: ExprId, right: ExprId) -> R;
+    /// `expr` is the variable expression itself, by which the resolver recorded
+    /// where the variable is declared. The same goes for assignments.
+    fn visit_variable(&mut self, expr: ExprId, name: &Token) -> R;
+    fn visit_assign(&mut self, expr: ExprId, name: &Token, value: ExprId) -> R;
+    fn visit_compound_assign(&mut self, target: ExprId, operator: &Token, value: ExprId) -> R;
+    fn visit_increment(&mut self, target: ExprId, operator: &Token, prefix: bool) -> R;
+    fn visit_call(
+        &mut self,
+        callee: ExprId,
+        paren: &Token,
+        arguments: &[ExprId],
+        named: &[(Token, ExprId)],
+    ) -> R;
+    fn visit_lambda(&mut self, declaration: &Rc<FunctionDecl>) -> R;
+    fn visit_list(&mut self, elements: &[ExprId]) -> R;
+    fn visit_map(&mut self, brace: &Token, entries: &[(ExprId, ExprId)]) -> R;
+    fn visit_index(&mut self, object: ExprId, bracket: &Token, index: ExprId) -> R;
+    fn visit_slice(
+        &mut self,
+        object: ExprId,
+        bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> R;
+    fn visit_set_index(
+        &mut self,
+        object: ExprId,
+        bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> R;
+    fn visit_get(&mut self, object: ExprId, name: &Token) -> R;
+    fn visit_set(&mut self, object: ExprId, name: &Token, value: ExprId) -> R;
 }
 
 impl Expr {
     pub fn accept<T: Visitor<R>, R>(&self, visitor: &T) -> R {
         match self {
             Expr::Literal(literal) => visitor.visit_literal(literal),
-            Expr::Grouping(expr) => visitor.visit_grouping(expr),
-            Expr::Binary(left, op, right) => visitor.visit_binary(left, op, right),
-            Expr::Unary(op, right) => visitor.visit_unary(op, right),
+            Expr::Grouping(expr) => visitor.visit_grouping(*expr),
+            Expr::Binary(left, op, right) => visitor.visit_binary(*left, op, *right),
+            Expr::Unary(op, right) => visitor.visit_unary(op, *right),
+            Expr::Logical(left, op, right) => visitor.visit_logical(*left, op, *right),
+            Expr::Ternary(condition, then_branch, else_branch) => {
+                visitor.visit_ternary(*condition, *then_branch, *else_branch)
+            }
+            Expr::Comma(left, right) => visitor.visit_comma(*left, *right),
+            Expr::Variable(name) => visitor.visit_variable(name),
+            Expr::Assign(name, value) => visitor.visit_assign(name, *value),
+            Expr::CompoundAssign(target, op, value) => {
+                visitor.visit_compound_assign(*target, op, *value)
+            }
+            Expr::Increment(target, op, prefix) => visitor.visit_increment(*target, op, *prefix),
+            Expr::Call(callee, paren, arguments, named) => {
+                visitor.visit_call(*callee, paren, arguments, named)
+            }
+            Expr::Lambda(declaration) => visitor.visit_lambda(declaration),
+            Expr::List(elements) => visitor.visit_list(elements),
+            Expr::Map(brace, entries) => visitor.visit_map(brace, entries),
+            Expr::Index(object, bracket, index) => visitor.visit_index(*object, bracket, *index),
+            Expr::Slice(object, bracket, start, end) => {
+                visitor.visit_slice(*object, bracket, *start, *end)
+            }
+            Expr::SetIndex(object, bracket, index, value) => {
+                visitor.visit_set_index(*object, bracket, *index, *value)
+            }
+            Expr::Get(object, name) => visitor.visit_get(*object, name),
+            Expr::Set(object, name, value) => visitor.visit_set(*object, name, *value),
+        }
+    }
+
+    /// Calls the visitor's method for this expression, whose ID is `id`.
+    pub fn accept_mut<T: VisitorMut<R>, R>(&self, id: ExprId, visitor: &mut T) -> R {
+        match self {
+            Expr::Literal(literal) => visitor.visit_literal(literal),
+            Expr::Grouping(expr) => visitor.visit_grouping(*expr),
+            Expr::Binary(left, op, right) => visitor.visit_binary(*left, op, *right),
+            Expr::Unary(op, right) => visitor.visit_unary(op, *right),
+            Expr::Logical(left, op, right) => visitor.visit_logical(*left, op, *right),
+            Expr::Ternary(condition, then_branch, else_branch) => {
+                visitor.visit_ternary(*condition, *then_branch, *else_branch)
+            }
+            Expr::Comma(left, right) => visitor.visit_comma(*left, *right),
+            Expr::Variable(name) => visitor.visit_variable(id, name),
+            Expr::Assign(name, value) => visitor.visit_assign(id, name, *value),
+            Expr::CompoundAssign(target, op, value) => {
+                visitor.visit_compound_assign(*target, op, *value)
+            }
+            Expr::Increment(target, op, prefix) => visitor.visit_increment(*target, op, *prefix),
+            Expr::Call(callee, paren, arguments, named) => {
+                visitor.visit_call(*callee, paren, arguments, named)
+            }
+            Expr::Lambda(declaration) => visitor.visit_lambda(declaration),
+            Expr::List(elements) => visitor.visit_list(elements),
+            Expr::Map(brace, entries) => visitor.visit_map(brace, entries),
+            Expr::Index(object, bracket, index) => visitor.visit_index(*object, bracket, *index),
+            Expr::Slice(object, bracket, start, end) => {
+                visitor.visit_slice(*object, bracket, *start, *end)
+            }
+            Expr::SetIndex(object, bracket, index, value) => {
+                visitor.visit_set_index(*object, bracket, *index, *value)
+            }
+            Expr::Get(object, name) => visitor.visit_get(*object, name),
+            Expr::Set(object, name, value) => visitor.visit_set(*object, name, *value),
         }
     }
 
+    /// The line of one of the expression's tokens, preferring the operator, for
+    /// errors that aren't raised at a token of their own. Literals have none.
+    pub fn line(&self, ast: &Ast) -> Option<u32> {
+        match self {
+            Expr::Literal(_) => None,
+            Expr::Grouping(expr) => ast[*expr].line(ast),
+            Expr::Binary(_, operator, _)
+            | Expr::Unary(operator, _)
+            | Expr::Logical(_, operator, _)
+            | Expr::CompoundAssign(_, operator, _)
+            | Expr::Increment(_, operator, _) => Some(operator.line),
+            Expr::Ternary(condition, then_branch, else_branch) => ast[*condition]
+                .line(ast)
+                .or_else(|| ast[*then_branch].line(ast))
+                .or_else(|| ast[*else_branch].line(ast)),
+            Expr::Comma(left, right) => ast[*left].line(ast).or_else(|| ast[*right].line(ast)),
+            Expr::Variable(name) | Expr::Assign(name, _) => Some(name.line),
+            Expr::Call(_, paren, _, _) => Some(paren.line),
+            Expr::Lambda(declaration) => declaration.line(ast),
+            Expr::List(elements) => elements.iter().find_map(|element| ast[*element].line(ast)),
+            Expr::Map(brace, _) => Some(brace.line),
+            Expr::Index(_, bracket, _)
+            | Expr::Slice(_, bracket, _, _)
+            | Expr::SetIndex(_, bracket, _, _) => Some(bracket.line),
+            Expr::Get(_, name) | Expr::Set(_, name, _) => Some(name.line),
+        }
+    }
+
+    /// Whether the expression can appear on the left of `=`, `+=` or `++`.
+    pub fn is_assignable(&self) -> bool {
+        matches!(self, Expr::Variable(_) | Expr::Index(..) | Expr::Get(..))
+    }
+
     // fn string(&self) -> String {
     //     match self {
     //         Expr::Literal(literal) => format!("{literal}"),
@@ -1,112 +1,1324 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::{Rc, Weak};
+
 use crate::{
-    expression::{Expr, Visitor},
+    ast::{Ast, ExprId, StmtId},
+    class::{ClassBuilder, Instance, NativeClass},
+    environment::Environment,
+    expression::{Expr, VisitorMut},
+    limits::{Abort, Budget, InterruptHandle, Limits},
+    list,
+    map::{self, Key, Map},
+    resolver::Locals,
+    statement::{FunctionDecl, VisitorMut as StmtVisitorMut},
+    stdlib,
     token::{Literal, Token, TokenType},
+    value::{Function, Value},
 };
 
-fn is_truthy(literal: &Literal) -> bool {
-    *literal != Literal::Nil && *literal != Literal::Boolean(false)
+/// An error raised while running a program, either by the interpreter itself or by
+/// `throw`. It unwinds until a `catch` handles it or it reaches the top level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub line: u32,
+    pub message: String,
+    /// The value given to `throw`, which `catch` receives unchanged.
+    pub thrown: Option<Value>,
+    /// The active calls when the error was raised, innermost first. Filled in by the
+    /// interpreter as the error leaves the function it was raised in.
+    pub stack: Vec<Frame>,
+    /// Set when a limit stopped the script, which `catch` doesn't handle.
+    pub abort: Option<Abort>,
+}
+
+/// A function that was running when an error was raised, and the line it was at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub file: Rc<str>,
+    pub line: u32,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}:{})", self.function, self.file, self.line)
+    }
+}
+
+impl RuntimeError {
+    pub fn new(token: &Token, message: &str) -> Self {
+        RuntimeError {
+            line: token.line,
+            message: message.to_string(),
+            thrown: None,
+            stack: Vec::new(),
+            abort: None,
+        }
+    }
+
+    pub fn aborted(line: u32, abort: Abort) -> Self {
+        RuntimeError {
+            line,
+            message: abort.to_string(),
+            thrown: None,
+            stack: Vec::new(),
+            abort: Some(abort),
+        }
+    }
+
+    /// An error for `throw value`. Strings and error objects supply their message.
+    fn thrown(keyword: &Token, value: Value) -> Self {
+        let message = match &value {
+            Value::Str(s) => s.clone(),
+            Value::Instance(instance) => match instance.downcast::<CaughtError>() {
+                Some(error) => error.borrow().message.clone(),
+                None => value.to_string(),
+            },
+            _ => value.to_string(),
+        };
+        RuntimeError {
+            thrown: Some(value),
+            ..RuntimeError::new(keyword, &message)
+        }
+    }
+
+    /// What a `catch` clause binds: the thrown value, or for errors raised by the
+    /// interpreter an instance of `error_class`.
+    fn into_value(self, error_class: &Rc<NativeClass>) -> Value {
+        if let Some(value) = self.thrown {
+            return value;
+        }
+        let error = CaughtError {
+            message: self.message,
+            line: self.line,
+            stack: self.stack.iter().map(|frame| frame.to_string()).collect(),
+        };
+        Value::Instance(Instance::new(error_class, Rc::new(RefCell::new(error))))
+    }
+}
+
+/// An error raised by the interpreter, as a `catch` clause sees it.
+struct CaughtError {
+    message: String,
+    line: u32,
+    stack: Vec<String>,
 }
 
-fn is_eqaul(a: &Literal, b: &Literal) -> bool {
+/// The class of caught errors, whose `message`, `line` and `stack` properties
+/// describe the error. Scripts can't construct it.
+fn error_class() -> Rc<NativeClass> {
+    ClassBuilder::<CaughtError>::new("Error")
+        .getter("message", |error| error.message.clone())
+        .getter("line", |error| error.line as f64)
+        .getter("stack", |error| error.stack.clone())
+        .build()
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)?;
+        // Runs of the same frame, as in a recursion that overflowed, are shown once.
+        for run in self.stack.chunk_by(|a, b| a == b) {
+            write!(f, "\n    at {}", run[0])?;
+            if run.len() > 1 {
+                write!(f, "\n    ... repeated {} more times", run.len() - 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+type EvalResult = Result<Value, RuntimeError>;
+
+/// Why a statement stopped before running to completion. Loops catch the
+/// `break`/`continue` meant for them, calls catch `return`; anything else
+/// propagates outwards.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unwind {
+    Error(RuntimeError),
+    Break(Option<String>),
+    Continue(Option<String>),
+    Return(Value),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type ExecResult = Result<(), Unwind>;
+
+/// Whether a `break`/`continue` with the given label targets a loop labeled `label`.
+fn targets_loop(target: &Option<String>, label: &Option<Token>) -> bool {
+    match (target, label) {
+        (None, _) => true,
+        (Some(target), Some(label)) => *target == *label.lexeme,
+        (Some(_), None) => false,
+    }
+}
+
+pub fn is_truthy(value: &Value) -> bool {
+    !matches!(value, Value::Nil | Value::Boolean(false))
+}
+
+fn is_eqaul(a: &Value, b: &Value) -> bool {
     a == b
 }
 
-pub struct Interpreter {}
+/// Bitwise operators only accept numbers with no fractional part that fit in an `i64`,
+/// and operate on their 64-bit two's complement representation.
+fn to_integer(operator: &Token, value: f64) -> Result<i64, RuntimeError> {
+    if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 {
+        return Ok(value as i64);
+    }
+    let message = format!("Operands of '{}' must be integers.", operator.lexeme);
+    Err(RuntimeError::new(operator, &message))
+}
 
-impl Interpreter {
-    pub fn interpret(&self, expr: &Box<Expr>) -> Literal {
-        expr.accept(self)
+fn to_shift_amount(operator: &Token, value: f64) -> Result<u32, RuntimeError> {
+    match to_integer(operator, value)? {
+        amount @ 0..=63 => Ok(amount as u32),
+        _ => Err(RuntimeError::new(
+            operator,
+            "Shift amount must be between 0 and 63.",
+        )),
     }
 }
 
-impl Visitor<Literal> for Interpreter {
-    fn visit_literal(&self, literal: &Literal) -> Literal {
-        literal.clone()
+/// A call in progress: the function's name and file, and the line it was called
+/// from in its caller.
+struct Call {
+    function: String,
+    file: Rc<str>,
+    line: u32,
+}
+
+/// Where `print` writes to, shared with the `print` built-in.
+pub type Output = Rc<RefCell<Box<dyn Write>>>;
+
+/// How many calls may be active at once by default. Calls recurse on the Rust
+/// stack, which grows as needed, so this bounds the memory runaway recursion
+/// takes rather than protecting the stack.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// Statements and expressions run on the thread's stack until less than the red
+/// zone is left, then on a new segment of the heap. Deep recursion in a script
+/// is limited by the call depth rather than by the stack of the host's thread.
+const STACK_RED_ZONE: usize = 64 * 1024;
+const STACK_SEGMENT: usize = 1024 * 1024;
+
+pub struct Interpreter {
+    /// The outermost scope, which holds the built-ins and other natives.
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    /// The active calls, outermost first, kept alongside the environment chain
+    /// for stack traces.
+    calls: Vec<Call>,
+    max_call_depth: usize,
+    stdout: Output,
+    /// The name that stack traces give to the source being run.
+    file: Rc<str>,
+    budget: Budget,
+    /// The line of the last statement run that had one, for errors raised
+    /// before a statement without one.
+    line: u32,
+    /// The tree of the code running now: the script's, or the called function's.
+    ast: Rc<Ast>,
+    /// Where the resolver found the local variables of `ast` declared.
+    locals: Rc<Locals>,
+    error_class: Rc<NativeClass>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::with_limits(Limits::default())
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter::default()
     }
 
-    fn visit_grouping(&self, expr: &Box<Expr>) -> Literal {
-        expr.accept(self)
+    /// Creates an interpreter that stops scripts exceeding `limits`, and leaves
+    /// out the built-ins they don't allow.
+    pub fn with_limits(limits: Limits) -> Self {
+        let globals = Rc::new(RefCell::new(Environment::default()));
+        let allow_system = limits.allow_system;
+        let interpreter = Interpreter {
+            globals: globals.clone(),
+            environment: globals,
+            calls: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            stdout: Rc::new(RefCell::new(Box::new(io::stdout()))),
+            file: Rc::from("<script>"),
+            budget: Budget::new(limits),
+            line: 0,
+            ast: Rc::default(),
+            locals: Rc::default(),
+            error_class: error_class(),
+        };
+        stdlib::define_builtins(&interpreter, allow_system);
+        interpreter
     }
 
-    fn visit_unary(&self, operator: &Token, right: &Box<Expr>) -> Literal {
-        let right = right.accept(self);
-        match operator.token_type {
-            TokenType::Minus => {
-                if let Literal::Number(value) = right {
-                    return Literal::Number(-value);
+    pub fn limits(&self) -> Limits {
+        self.budget.limits().clone()
+    }
+
+    /// A handle for stopping this interpreter's scripts from elsewhere.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.budget.interrupt_handle()
+    }
+
+    /// Gives the code run next the whole step and time budget again, and clears
+    /// any interrupt. Objects stay counted, since those already created are still around.
+    pub fn reset_budget(&mut self) {
+        self.budget.reset();
+    }
+
+    /// Defines a global function implemented in Rust, which is called with exactly
+    /// as many arguments as it has parameters.
+    pub fn define_native(
+        &self,
+        name: &'static str,
+        params: &'static [&'static str],
+        function: impl Fn(&Token, Vec<Value>) -> EvalResult + 'static,
+    ) {
+        let native = Value::native(name, params, function);
+        self.globals.borrow_mut().define(name, native);
+    }
+
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().lookup(name)
+    }
+
+    pub fn set_global(&self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name, value);
+    }
+
+    /// Calls a function or native from Rust. Errors are reported at line 0.
+    pub fn call_value(&mut self, callee: Value, arguments: Vec<Value>) -> EvalResult {
+        let paren = Token::new(TokenType::RightParen, ")", None, 0);
+        self.call(callee, &paren, arguments, Vec::new())
+            .map_err(|mut error| {
+                self.capture_stack(&mut error);
+                error
+            })
+    }
+
+    pub fn stdout(&self) -> Output {
+        self.stdout.clone()
+    }
+
+    /// Sends the output of `print` to `out` instead of standard output.
+    pub fn set_stdout(&self, out: impl Write + 'static) {
+        *self.stdout.borrow_mut() = Box::new(out);
+    }
+
+    /// Names the source that the following code comes from. Functions remember
+    /// the file they were defined in.
+    pub fn set_file(&mut self, file: &str) {
+        self.file = Rc::from(file);
+    }
+
+    /// Calls nested deeper than this fail with a catchable "Stack overflow." error.
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    /// Records the active calls in `error`, unless a more deeply nested call
+    /// already has. `error.line` is where the innermost one is at.
+    fn capture_stack(&self, error: &mut RuntimeError) {
+        if !error.stack.is_empty() {
+            return;
+        }
+        let mut line = error.line;
+        for call in self.calls.iter().rev() {
+            error.stack.push(Frame {
+                function: call.function.clone(),
+                file: call.file.clone(),
+                line,
+            });
+            line = call.line;
+        }
+        error.stack.push(Frame {
+            function: "<script>".to_string(),
+            file: self.file.clone(),
+            line,
+        });
+    }
+
+    /// Evaluates `expr`, a node of `ast`, with the `locals` the resolver found in it.
+    pub fn interpret(&mut self, ast: &Rc<Ast>, locals: &Rc<Locals>, expr: ExprId) -> EvalResult {
+        self.ast = ast.clone();
+        self.locals = locals.clone();
+        self.step(ast[expr].line(ast))
+            .and_then(|()| self.evaluate(expr))
+            .map_err(|mut error| {
+                self.capture_stack(&mut error);
+                error
+            })
+    }
+
+    /// Runs `statements`, which are nodes of `ast`, with the `locals` the resolver found in it.
+    pub fn execute(
+        &mut self,
+        ast: &Rc<Ast>,
+        locals: &Rc<Locals>,
+        statements: &[StmtId],
+    ) -> Result<(), RuntimeError> {
+        self.ast = ast.clone();
+        self.locals = locals.clone();
+        for statement in statements {
+            match self.execute_statement(*statement) {
+                Ok(()) => {}
+                Err(Unwind::Error(mut error)) => {
+                    self.capture_stack(&mut error);
+                    return Err(error);
                 }
-                // panic!("Unary minus can only be applied to numbers");
+                Err(unwind) => unreachable!("Resolver rejects {unwind:?} at the top level"),
             }
-            TokenType::Bang => return Literal::Boolean(!is_truthy(&right)),
-            _ => panic!("Interpreter does not support this"),
         }
-        Literal::Nil
+        Ok(())
     }
 
-    fn visit_binary(&self, left: &Box<Expr>, operator: &Token, right: &Box<Expr>) -> Literal {
-        let left = left.accept(self);
-        let right = right.accept(self);
+    /// Accounts for a statement, or an expression run on its own, at `line`.
+    fn step(&mut self, line: Option<u32>) -> Result<(), RuntimeError> {
+        if let Some(line) = line {
+            self.line = line;
+        }
+        self.budget
+            .step()
+            .map_err(|abort| RuntimeError::aborted(self.line, abort))
+    }
 
-        match operator.token_type {
-            TokenType::Minus => {
-                if let (Literal::Number(value_left), Literal::Number(value_right)) = (&left,&right) {
-                    return Literal::Number(value_left - value_right);
+    /// Runs a statement, unless the script is out of budget.
+    fn execute_statement(&mut self, statement: StmtId) -> ExecResult {
+        let ast = self.ast.clone();
+        self.step(ast[statement].line(&ast))?;
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            ast[statement].accept_mut(self)
+        })
+    }
+
+    fn evaluate(&mut self, expr: ExprId) -> EvalResult {
+        let ast = self.ast.clone();
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            ast[expr].accept_mut(expr, self)
+        })
+    }
+
+    /// Counts an object the script created, or checks the length of a string it made.
+    fn track(&mut self, token: &Token, value: Value) -> EvalResult {
+        match &value {
+            Value::Str(s) => {
+                if let Err(abort) = self.budget.check_string(s) {
+                    return Err(RuntimeError::aborted(token.line, abort));
                 }
             }
-            TokenType::Star => {
-                if let (Literal::Number(value_left), Literal::Number(value_right)) = (&left,&right) {
-                    return Literal::Number(value_left * value_right);
+            _ => {
+                if let Some(object) = value.object() {
+                    self.budget.allocate(object);
                 }
             }
-            TokenType::Slash => {
-                if let (Literal::Number(value_left), Literal::Number(value_right)) = (&left,&right) {
-                    return Literal::Number(value_left / value_right);
-                }
+        }
+        Ok(value)
+    }
+
+    /// Runs `run` with `scope` as the current environment, then restores the previous one.
+    fn in_scope<T>(&mut self, scope: Environment, run: impl FnOnce(&mut Self) -> T) -> T {
+        let previous = mem::replace(&mut self.environment, Rc::new(RefCell::new(scope)));
+        let result = run(self);
+        self.environment = previous;
+        result
+    }
+
+    fn execute_block(&mut self, statements: &[StmtId], scope: Environment) -> ExecResult {
+        self.in_scope(scope, |interpreter| {
+            statements
+                .iter()
+                .try_for_each(|statement| interpreter.execute_statement(*statement))
+        })
+    }
+
+    fn call_function(
+        &mut self,
+        function: &Function,
+        paren: &Token,
+        arguments: Vec<Value>,
+        named: Vec<(&Token, Value)>,
+    ) -> EvalResult {
+        let (slots, extra) = match_arguments(function, paren, arguments, named)?;
+        if self.calls.len() == self.max_call_depth {
+            return Err(RuntimeError::new(paren, "Stack overflow."));
+        }
+        self.calls.push(Call {
+            function: function.name().to_string(),
+            file: function.file.clone(),
+            line: paren.line,
+        });
+        let scope = Environment::new(function.closure.clone());
+        let caller_ast = mem::replace(&mut self.ast, function.ast.clone());
+        let caller_locals = mem::replace(&mut self.locals, function.locals.clone());
+        let result = self.in_scope(scope, |interpreter| {
+            interpreter.bind_arguments(function, slots, extra)?;
+            function
+                .declaration
+                .body
+                .iter()
+                .try_for_each(|statement| interpreter.execute_statement(*statement))
+        });
+        let result = match result {
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(mut error)) => {
+                self.capture_stack(&mut error);
+                Err(error)
             }
-            TokenType::Plus => {
-                if let (Literal::Number(value_left), Literal::Number(value_right)) = (&left, &right)
-                {
-                    return Literal::Number(value_left + value_right);
-                }
-                if let (Literal::Str(value_left), Literal::Str(value_right)) = (&left, &right) {
-                    let mut result = value_left.clone(); // Clone to avoid moving
-                    result.push_str(value_right);
-                    return Literal::Str(result);
-                }
-                // panic!("Operands must be two numbers or two strings for `+` operator.");
+            Err(unwind) => unreachable!("Resolver rejects {unwind:?} outside of loops"),
+        };
+        self.calls.pop();
+        self.ast = caller_ast;
+        self.locals = caller_locals;
+        result
+    }
+
+    /// Defines the parameters of `function` in the current environment, from the
+    /// slots `match_arguments` filled and defaults for the empty ones, and the rest
+    /// parameter from the `extra` arguments.
+    fn bind_arguments(
+        &mut self,
+        function: &Function,
+        slots: Vec<Option<Value>>,
+        extra: Vec<Value>,
+    ) -> Result<(), RuntimeError> {
+        for (param, slot) in function.declaration.params.iter().zip(slots) {
+            let value = match (slot, param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => self.evaluate(default)?,
+                (None, None) => unreachable!("match_arguments fills required parameters"),
+            };
+            self.environment
+                .borrow_mut()
+                .define(&param.name.lexeme, value);
+        }
+        if let Some(rest) = &function.declaration.rest {
+            let rest_list = self.track(rest, Value::List(Rc::new(RefCell::new(extra))))?;
+            self.environment
+                .borrow_mut()
+                .define(&rest.lexeme, rest_list);
+        }
+        Ok(())
+    }
+
+    /// Calls a function, native or class with already evaluated arguments.
+    fn call(
+        &mut self,
+        callee: Value,
+        paren: &Token,
+        arguments: Vec<Value>,
+        named: Vec<(&Token, Value)>,
+    ) -> EvalResult {
+        match &callee {
+            Value::Function(function) => self.call_function(function, paren, arguments, named),
+            Value::Native(native) => {
+                check_arguments(
+                    &native.signature(),
+                    native.params,
+                    paren,
+                    &arguments,
+                    &named,
+                )?;
+                self.track(paren, (native.function)(paren, arguments)?)
             }
-            TokenType::Greater => {
-                if let (Literal::Number(value_left), Literal::Number(value_right)) = (&left,&right) {
-                    return Literal::Boolean(value_left > value_right);
+            Value::Class(class) => {
+                if let Some(params) = class.params() {
+                    check_arguments(&class.signature(), params, paren, &arguments, &named)?;
                 }
+                self.track(paren, Value::Instance(class.construct(paren, arguments)?))
             }
-            TokenType::GreaterEqual => {
-                if let (Literal::Number(value_left), Literal::Number(value_right)) = (&left,&right) {
-                    return Literal::Boolean(value_left >= value_right);
-                }
+            _ => Err(RuntimeError::new(
+                paren,
+                "Can only call functions and classes.",
+            )),
+        }
+    }
+
+    /// Starts a `for (name in ...)` loop over `iterable`.
+    fn iterate(&mut self, name: &Token, iterable: Value) -> Result<Iteration, RuntimeError> {
+        let iteration = match &iterable {
+            Value::List(list) => Iteration::List(list.clone(), 0),
+            Value::Str(s) => {
+                let characters: Vec<_> = s.chars().map(|c| Value::Str(c.to_string())).collect();
+                Iteration::Values(characters.into_iter())
+            }
+            Value::Range(start, end, inclusive) => Iteration::Range(*start, *end, *inclusive),
+            Value::Map(map) => {
+                let keys: Vec<_> = map.borrow().iter().map(|(key, _)| key.to_value()).collect();
+                Iteration::Values(keys.into_iter())
+            }
+            _ => {
+                let iter = Token::new(TokenType::Identifier, "iter", None, name.line);
+                let Ok(method) = get_property(&iterable, &iter) else {
+                    return Err(RuntimeError::new(
+                        name,
+                        "Can only iterate over lists, maps, strings, ranges and objects with an 'iter' method.",
+                    ));
+                };
+                let iterator = self.call(method, &iter, Vec::new(), Vec::new())?;
+                let next = Token::new(TokenType::Identifier, "next", None, name.line);
+                Iteration::Protocol(get_property(&iterator, &next)?, next)
+            }
+        };
+        Ok(iteration)
+    }
+
+    /// Reads the variable `expr` refers to: a local where the resolver found it
+    /// declared, or else a global.
+    fn look_up(&self, expr: ExprId, name: &Token) -> EvalResult {
+        match self.locals[expr] {
+            Some(depth) => Environment::ancestor(&self.environment, depth)
+                .borrow()
+                .get(name),
+            None => self.globals.borrow().get(name),
+        }
+    }
+
+    fn assign_variable(
+        &mut self,
+        expr: ExprId,
+        name: &Token,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        match self.locals[expr] {
+            Some(depth) => Environment::ancestor(&self.environment, depth)
+                .borrow_mut()
+                .assign(name, value),
+            None => self.globals.borrow_mut().assign(name, value),
+        }
+    }
+
+    /// Creates a function value closing over the current environment.
+    fn make_function(&mut self, declaration: &Rc<FunctionDecl>) -> Value {
+        let function = Rc::new(Function {
+            declaration: declaration.clone(),
+            closure: self.environment.clone(),
+            ast: self.ast.clone(),
+            locals: self.locals.clone(),
+            file: self.file.clone(),
+        });
+        self.budget
+            .allocate(Rc::downgrade(&function) as Weak<dyn Any>);
+        Value::Function(function)
+    }
+
+    /// Reads the current value of an assignment target, stores `update(old)` back into it
+    /// and returns both values. Subexpressions of the target are evaluated exactly once.
+    fn update_target(
+        &mut self,
+        target: ExprId,
+        update: impl FnOnce(&mut Self, Value) -> EvalResult,
+    ) -> Result<(Value, Value), RuntimeError> {
+        let ast = self.ast.clone();
+        match &ast[target] {
+            Expr::Variable(name) => {
+                let old = self.look_up(target, name)?;
+                let new = update(self, old.clone())?;
+                self.assign_variable(target, name, new.clone())?;
+                Ok((old, new))
+            }
+            Expr::Index(object, bracket, index) => {
+                let object = self.evaluate(*object)?;
+                let index = self.evaluate(*index)?;
+                let old = get_index(&object, bracket, &index)?;
+                let new = update(self, old.clone())?;
+                // `update` may have resized a list, as in `xs[-1] += xs.pop()`,
+                // so the index is resolved again.
+                set_index(&object, bracket, &index, new.clone())?;
+                Ok((old, new))
+            }
+            Expr::Get(object, name) => {
+                let object = self.evaluate(*object)?;
+                let old = get_property(&object, name)?;
+                let new = update(self, old.clone())?;
+                set_property(&object, name, new.clone())?;
+                Ok((old, new))
             }
-            TokenType::Less => {
-                if let (Literal::Number(value_left), Literal::Number(value_right)) = (&left,&right) {
-                    return Literal::Boolean(value_left < value_right);
+            target => unreachable!("Parser only produces assignable targets, not {target:?}"),
+        }
+    }
+}
+
+impl VisitorMut<EvalResult> for Interpreter {
+    fn visit_literal(&mut self, literal: &Literal) -> EvalResult {
+        Ok(Value::from(literal.clone()))
+    }
+
+    fn visit_grouping(&mut self, expr: ExprId) -> EvalResult {
+        self.evaluate(expr)
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: ExprId) -> EvalResult {
+        let right = self.evaluate(right)?;
+        unary_operation(operator, right)
+    }
+
+    fn visit_logical(&mut self, left: ExprId, operator: &Token, right: ExprId) -> EvalResult {
+        let left = self.evaluate(left)?;
+        match operator.token_type {
+            TokenType::Or if is_truthy(&left) => Ok(left),
+            TokenType::And if !is_truthy(&left) => Ok(left),
+            _ => self.evaluate(right),
+        }
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
+    ) -> EvalResult {
+        if is_truthy(&self.evaluate(condition)?) {
+            self.evaluate(then_branch)
+        } else {
+            self.evaluate(else_branch)
+        }
+    }
+
+    fn visit_comma(&mut self, left: ExprId, right: ExprId) -> EvalResult {
+        self.evaluate(left)?;
+        self.evaluate(right)
+    }
+
+    fn visit_binary(&mut self, left: ExprId, operator: &Token, right: ExprId) -> EvalResult {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+        self.track(operator, binary_operation(operator, left, right)?)
+    }
+
+    fn visit_variable(&mut self, expr: ExprId, name: &Token) -> EvalResult {
+        self.look_up(expr, name)
+    }
+
+    fn visit_assign(&mut self, expr: ExprId, name: &Token, value: ExprId) -> EvalResult {
+        let value = self.evaluate(value)?;
+        self.assign_variable(expr, name, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        target: ExprId,
+        operator: &Token,
+        value: ExprId,
+    ) -> EvalResult {
+        // `a += b` applies `+`, whose lexeme is the compound operator minus its trailing '='.
+        let lexeme = &operator.lexeme[..operator.lexeme.len() - 1];
+        let token_type = match operator.token_type {
+            TokenType::PlusEqual => TokenType::Plus,
+            TokenType::MinusEqual => TokenType::Minus,
+            TokenType::StarEqual => TokenType::Star,
+            TokenType::SlashEqual => TokenType::Slash,
+            TokenType::PercentEqual => TokenType::Percent,
+            other => unreachable!("Parser only produces compound assignments, not {other:?}"),
+        };
+        let binary_operator = Token::new(token_type, lexeme, None, operator.line);
+        let (_, new) = self.update_target(target, |interpreter, old| {
+            let value = interpreter.evaluate(value)?;
+            interpreter.track(operator, binary_operation(&binary_operator, old, value)?)
+        })?;
+        Ok(new)
+    }
+
+    fn visit_increment(&mut self, target: ExprId, operator: &Token, prefix: bool) -> EvalResult {
+        let delta = match operator.token_type {
+            TokenType::PlusPlus => 1.0,
+            _ => -1.0,
+        };
+        let (old, new) = self.update_target(target, |_, old| match old {
+            Value::Number(value) => Ok(Value::Number(value + delta)),
+            _ => {
+                let message = format!("Operand of '{}' must be a number.", operator.lexeme);
+                Err(RuntimeError::new(operator, &message))
+            }
+        })?;
+        Ok(if prefix { new } else { old })
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: ExprId,
+        paren: &Token,
+        arguments: &[ExprId],
+        named: &[(Token, ExprId)],
+    ) -> EvalResult {
+        let callee = self.evaluate(callee)?;
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.evaluate(*argument))
+            .collect::<Result<Vec<_>, _>>()?;
+        let named = named
+            .iter()
+            .map(|(name, argument)| Ok((name, self.evaluate(*argument)?)))
+            .collect::<Result<Vec<_>, RuntimeError>>()?;
+        self.call(callee, paren, arguments, named)
+    }
+
+    fn visit_lambda(&mut self, declaration: &Rc<FunctionDecl>) -> EvalResult {
+        Ok(self.make_function(declaration))
+    }
+
+    fn visit_list(&mut self, elements: &[ExprId]) -> EvalResult {
+        let elements = elements
+            .iter()
+            .map(|element| self.evaluate(*element))
+            .collect::<Result<Vec<_>, _>>()?;
+        let list = Rc::new(RefCell::new(elements));
+        self.budget.allocate(Rc::downgrade(&list) as Weak<dyn Any>);
+        Ok(Value::List(list))
+    }
+
+    fn visit_index(&mut self, object: ExprId, bracket: &Token, index: ExprId) -> EvalResult {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        get_index(&object, bracket, &index)
+    }
+
+    fn visit_slice(
+        &mut self,
+        object: ExprId,
+        bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> EvalResult {
+        let object = self.evaluate(object)?;
+        let Value::List(list) = &object else {
+            return Err(RuntimeError::new(bracket, "Only lists can be sliced."));
+        };
+        let start = start.map(|start| self.evaluate(start)).transpose()?;
+        let end = end.map(|end| self.evaluate(end)).transpose()?;
+        let list = list.borrow();
+        let start = list::slice_bound(bracket, start, 0, list.len())?;
+        let end = list::slice_bound(bracket, end, list.len(), list.len())?;
+        let elements = list[start..end.max(start)].to_vec();
+        self.track(bracket, Value::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: ExprId,
+        bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> EvalResult {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = self.evaluate(value)?;
+        set_index(&object, bracket, &index, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_map(&mut self, brace: &Token, entries: &[(ExprId, ExprId)]) -> EvalResult {
+        let mut map = Map::new();
+        for (key, value) in entries {
+            let key = Key::new(brace, &self.evaluate(*key)?)?;
+            map.insert(key, self.evaluate(*value)?);
+        }
+        self.track(brace, Value::Map(Rc::new(RefCell::new(map))))
+    }
+
+    fn visit_get(&mut self, object: ExprId, name: &Token) -> EvalResult {
+        get_property(&self.evaluate(object)?, name)
+    }
+
+    fn visit_set(&mut self, object: ExprId, name: &Token, value: ExprId) -> EvalResult {
+        let object = self.evaluate(object)?;
+        let value = self.evaluate(value)?;
+        set_property(&object, name, value.clone())?;
+        Ok(value)
+    }
+}
+
+impl StmtVisitorMut<ExecResult> for Interpreter {
+    fn visit_expression_stmt(&mut self, expr: ExprId) -> ExecResult {
+        self.evaluate(expr)?;
+        Ok(())
+    }
+
+    fn visit_print_stmt(&mut self, expr: ExprId) -> ExecResult {
+        let value = self.evaluate(expr)?;
+        stdlib::print(&self.stdout, &value);
+        Ok(())
+    }
+
+    fn visit_var_stmt(&mut self, name: &Token, initializer: Option<ExprId>) -> ExecResult {
+        let value = match initializer {
+            Some(initializer) => self.evaluate(initializer)?,
+            None => Value::Nil,
+        };
+        self.environment.borrow_mut().define(&name.lexeme, value);
+        Ok(())
+    }
+
+    fn visit_block_stmt(&mut self, statements: &[StmtId]) -> ExecResult {
+        let scope = Environment::new(self.environment.clone());
+        self.execute_block(statements, scope)
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> ExecResult {
+        if is_truthy(&self.evaluate(condition)?) {
+            self.execute_statement(then_branch)
+        } else if let Some(else_branch) = else_branch {
+            self.execute_statement(else_branch)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        keyword: &Token,
+        label: &Option<Token>,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+    ) -> ExecResult {
+        while is_truthy(&self.evaluate(condition)?) {
+            // The body may have no line of its own, as in `while (true) {}`.
+            self.line = keyword.line;
+            match self.execute_statement(body) {
+                Ok(()) => {}
+                Err(Unwind::Break(target)) if targets_loop(&target, label) => break,
+                Err(Unwind::Continue(target)) if targets_loop(&target, label) => {}
+                Err(unwind) => return Err(unwind),
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        label: &Option<Token>,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> ExecResult {
+        let iterable = self.evaluate(iterable)?;
+        let mut iteration = self.iterate(name, iterable)?;
+        while let Some(value) = iteration.next(self)? {
+            self.line = name.line;
+            // A fresh scope per iteration, so closures capture that iteration's value.
+            let mut scope = Environment::new(self.environment.clone());
+            scope.define(&name.lexeme, value);
+            match self.in_scope(scope, |interpreter| interpreter.execute_statement(body)) {
+                Ok(()) => {}
+                Err(Unwind::Break(target)) if targets_loop(&target, label) => break,
+                Err(Unwind::Continue(target)) if targets_loop(&target, label) => {}
+                Err(unwind) => return Err(unwind),
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_break_stmt(&mut self, _keyword: &Token, label: &Option<Token>) -> ExecResult {
+        Err(Unwind::Break(
+            label.as_ref().map(|label| label.lexeme.to_string()),
+        ))
+    }
+
+    fn visit_continue_stmt(&mut self, _keyword: &Token, label: &Option<Token>) -> ExecResult {
+        Err(Unwind::Continue(
+            label.as_ref().map(|label| label.lexeme.to_string()),
+        ))
+    }
+
+    fn visit_function_stmt(&mut self, declaration: &Rc<FunctionDecl>) -> ExecResult {
+        let function = self.make_function(declaration);
+        let name = &declaration.name.as_ref().unwrap().lexeme;
+        self.environment.borrow_mut().define(name, function);
+        Ok(())
+    }
+
+    fn visit_return_stmt(&mut self, _keyword: &Token, value: Option<ExprId>) -> ExecResult {
+        let value = match value {
+            Some(value) => self.evaluate(value)?,
+            None => Value::Nil,
+        };
+        Err(Unwind::Return(value))
+    }
+
+    fn visit_throw_stmt(&mut self, keyword: &Token, value: ExprId) -> ExecResult {
+        let value = self.evaluate(value)?;
+        Err(Unwind::Error(RuntimeError::thrown(keyword, value)))
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        body: &[StmtId],
+        catch: &Option<(Token, Vec<StmtId>)>,
+        finally: &Option<Vec<StmtId>>,
+    ) -> ExecResult {
+        let result = match (self.visit_block_stmt(body), catch) {
+            (Err(Unwind::Error(mut error)), Some((name, handler))) if error.abort.is_none() => {
+                self.capture_stack(&mut error);
+                let value = self.track(name, error.into_value(&self.error_class))?;
+                let mut scope = Environment::new(self.environment.clone());
+                scope.define(&name.lexeme, value);
+                self.execute_block(handler, scope)
+            }
+            (result, _) => result,
+        };
+        // A script that ran out of budget stops without running any more of its
+        // handlers, so neither `catch` nor `finally` can swallow the abort.
+        if matches!(&result, Err(Unwind::Error(error)) if error.abort.is_some()) {
+            return result;
+        }
+        // `finally` runs however the rest ended, and only replaces that outcome
+        // if it jumps or throws itself.
+        if let Some(finally) = finally {
+            self.visit_block_stmt(finally)?;
+        }
+        result
+    }
+}
+
+/// Where a `for (x in ...)` loop is in its iterable.
+enum Iteration {
+    /// Lists are walked by position, so elements pushed during the loop are visited too.
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    /// Characters of a string, or keys of a map as of the start of the loop.
+    Values(std::vec::IntoIter<Value>),
+    /// The next number, the end and whether the end is included.
+    Range(f64, f64, bool),
+    /// The `next` method of the object returned by `iter()`; it returns nil when done.
+    Protocol(Value, Token),
+}
+
+impl Iteration {
+    fn next(&mut self, interpreter: &mut Interpreter) -> Result<Option<Value>, RuntimeError> {
+        match self {
+            Iteration::List(list, position) => {
+                let value = list.borrow().get(*position).cloned();
+                *position += 1;
+                Ok(value)
+            }
+            Iteration::Values(values) => Ok(values.next()),
+            Iteration::Range(next, end, inclusive) => {
+                if *next < *end || (*inclusive && *next == *end) {
+                    let value = *next;
+                    *next += 1.0;
+                    Ok(Some(Value::Number(value)))
+                } else {
+                    Ok(None)
                 }
             }
-            TokenType::LessEqual => {
-                if let (Literal::Number(value_left), Literal::Number(value_right)) = (&left,&right) {
-                    return Literal::Boolean(value_left <= value_right);
+            Iteration::Protocol(method, token) => {
+                match interpreter.call(method.clone(), token, Vec::new(), Vec::new())? {
+                    Value::Nil => Ok(None),
+                    value => Ok(Some(value)),
                 }
             }
-            TokenType::BangEqual => {
-                return Literal::Boolean(!is_eqaul(&left, &right));
+        }
+    }
+}
+
+/// Matches the arguments of a call to the parameters of `function`: positional
+/// arguments first, then named ones. Returns a slot per parameter, left empty
+/// for its default, and the positional arguments left over for the rest
+/// parameter. Runs before the function is entered, so that a call that doesn't
+/// fit is reported in the caller.
+fn match_arguments(
+    function: &Function,
+    paren: &Token,
+    arguments: Vec<Value>,
+    named: Vec<(&Token, Value)>,
+) -> Result<(Vec<Option<Value>>, Vec<Value>), RuntimeError> {
+    let params = &function.declaration.params;
+    let given = arguments.len() + named.len();
+    let named_given = !named.is_empty();
+    let arity_error = || {
+        let required = params
+            .iter()
+            .filter(|param| param.default.is_none())
+            .count();
+        let expected = match function.declaration.rest {
+            Some(_) => format!("at least {}", count_arguments(required)),
+            None if required == params.len() => count_arguments(required),
+            None => format!("{required} to {}", count_arguments(params.len())),
+        };
+        let message = format!(
+            "Expected {expected} but got {given} for {}.",
+            function.signature()
+        );
+        RuntimeError::new(paren, &message)
+    };
+
+    let mut arguments = arguments.into_iter();
+    let mut slots: Vec<Option<Value>> = arguments.by_ref().take(params.len()).map(Some).collect();
+    slots.resize(params.len(), None);
+    let extra: Vec<Value> = arguments.collect();
+    if !extra.is_empty() && function.declaration.rest.is_none() {
+        return Err(arity_error());
+    }
+    for (name, value) in named {
+        let Some(index) = params
+            .iter()
+            .position(|param| param.name.lexeme == name.lexeme)
+        else {
+            let message = format!(
+                "Unknown argument '{}' for {}.",
+                name.lexeme,
+                function.signature()
+            );
+            return Err(RuntimeError::new(name, &message));
+        };
+        if slots[index].is_some() {
+            let message = format!(
+                "Argument '{}' given twice for {}.",
+                name.lexeme,
+                function.signature()
+            );
+            return Err(RuntimeError::new(name, &message));
+        }
+        slots[index] = Some(value);
+    }
+
+    let missing = params
+        .iter()
+        .zip(&slots)
+        .find(|(param, slot)| slot.is_none() && param.default.is_none());
+    if let Some((param, _)) = missing {
+        if !named_given {
+            return Err(arity_error());
+        }
+        let message = format!(
+            "Missing argument '{}' for {}.",
+            param.name.lexeme,
+            function.signature()
+        );
+        return Err(RuntimeError::new(paren, &message));
+    }
+    Ok((slots, extra))
+}
+
+/// `count` followed by "argument" or "arguments", whichever agrees with it.
+fn count_arguments(count: usize) -> String {
+    match count {
+        1 => "1 argument".to_string(),
+        _ => format!("{count} arguments"),
+    }
+}
+
+/// Rejects arguments that don't fit a native's or native class's parameters.
+fn check_arguments(
+    signature: &str,
+    params: &[&str],
+    paren: &Token,
+    arguments: &[Value],
+    named: &[(&Token, Value)],
+) -> Result<(), RuntimeError> {
+    if let Some((name, _)) = named.first() {
+        let message = format!("Unknown argument '{}' for {}.", name.lexeme, signature);
+        return Err(RuntimeError::new(name, &message));
+    }
+    if arguments.len() != params.len() {
+        let message = format!(
+            "Expected {} but got {} for {}.",
+            count_arguments(params.len()),
+            arguments.len(),
+            signature
+        );
+        return Err(RuntimeError::new(paren, &message));
+    }
+    Ok(())
+}
+
+/// Looks up `object.name`: a property or method of an instance, or a built-in method
+/// of a list or map.
+fn get_property(object: &Value, name: &Token) -> EvalResult {
+    let (method, kind) = match object {
+        Value::Instance(instance) => return instance.get(name),
+        Value::List(list) => (list::method(list, name), "Lists"),
+        Value::Map(map) => (map::method(map, name), "Maps"),
+        _ => {
+            return Err(RuntimeError::new(
+                name,
+                "Only lists, maps and instances have properties.",
+            ))
+        }
+    };
+    method.ok_or_else(|| {
+        let message = format!("{} have no method '{}'.", kind, name.lexeme);
+        RuntimeError::new(name, &message)
+    })
+}
+
+/// Stores `object.name = value` through a setter of an instance.
+fn set_property(object: &Value, name: &Token, value: Value) -> Result<(), RuntimeError> {
+    match object {
+        Value::Instance(instance) => instance.set(name, value),
+        _ => Err(RuntimeError::new(
+            name,
+            "Only instances have settable properties.",
+        )),
+    }
+}
+
+/// Reads `object[index]` from a list or map.
+fn get_index(object: &Value, bracket: &Token, index: &Value) -> EvalResult {
+    match object {
+        Value::List(list) => {
+            let list = list.borrow();
+            let position = list::position(bracket, index, list.len())?;
+            Ok(list[position].clone())
+        }
+        Value::Map(map) => {
+            let key = Key::new(bracket, index)?;
+            map.borrow().get(&key).cloned().ok_or_else(|| {
+                let message = format!("Key {} not found in map.", index.repr());
+                RuntimeError::new(bracket, &message)
+            })
+        }
+        _ => Err(RuntimeError::new(
+            bracket,
+            "Only lists and maps can be indexed.",
+        )),
+    }
+}
+
+/// Stores `object[index] = value`. Lists must already have the index, maps gain the key.
+fn set_index(
+    object: &Value,
+    bracket: &Token,
+    index: &Value,
+    value: Value,
+) -> Result<(), RuntimeError> {
+    match object {
+        Value::List(list) => {
+            let mut list = list.borrow_mut();
+            let position = list::position(bracket, index, list.len())?;
+            list[position] = value;
+        }
+        Value::Map(map) => {
+            let key = Key::new(bracket, index)?;
+            map.borrow_mut().insert(key, value);
+        }
+        _ => {
+            return Err(RuntimeError::new(
+                bracket,
+                "Only lists and maps can be indexed.",
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Applies a unary operator to an already evaluated operand.
+pub fn unary_operation(operator: &Token, right: Value) -> EvalResult {
+    match operator.token_type {
+        TokenType::Minus => {
+            if let Value::Number(value) = right {
+                return Ok(Value::Number(-value));
             }
-            TokenType::EqualEqual => {
-                return Literal::Boolean(is_eqaul(&left, &right));
+        }
+        TokenType::Tilde => {
+            if let Value::Number(value) = right {
+                return Ok(Value::Number(!to_integer(operator, value)? as f64));
             }
-            _ => {
-                panic!("Interpreter does not support this");
+        }
+        TokenType::Bang => return Ok(Value::Boolean(!is_truthy(&right))),
+        other => unreachable!("Parser only produces unary operators, not {other:?}"),
+    }
+    Err(RuntimeError::new(operator, "Operand must be a number."))
+}
+
+/// Applies a binary operator to two already evaluated operands.
+pub fn binary_operation(operator: &Token, left: Value, right: Value) -> EvalResult {
+    match operator.token_type {
+        TokenType::BangEqual => {
+            return Ok(Value::Boolean(!is_eqaul(&left, &right)));
+        }
+        TokenType::EqualEqual => {
+            return Ok(Value::Boolean(is_eqaul(&left, &right)));
+        }
+        TokenType::Plus => {
+            if let (Value::Str(value_left), Value::Str(value_right)) = (&left, &right) {
+                let mut result = value_left.clone(); // Clone to avoid moving
+                result.push_str(value_right);
+                return Ok(Value::Str(result));
             }
         }
-        Literal::Nil
+        _ => {}
     }
+
+    let (Value::Number(value_left), Value::Number(value_right)) = (&left, &right) else {
+        if operator.token_type == TokenType::Plus {
+            return Err(RuntimeError::new(
+                operator,
+                "Operands must be two numbers or two strings.",
+            ));
+        }
+        return Err(RuntimeError::new(operator, "Operands must be numbers."));
+    };
+    let (value_left, value_right) = (*value_left, *value_right);
+
+    let result = match operator.token_type {
+        TokenType::Minus => Value::Number(value_left - value_right),
+        TokenType::Plus => Value::Number(value_left + value_right),
+        TokenType::Star => Value::Number(value_left * value_right),
+        TokenType::Slash => Value::Number(value_left / value_right),
+        // Remainder truncates like C's fmod: the result takes the sign of the dividend.
+        TokenType::Percent => Value::Number(value_left % value_right),
+        TokenType::StarStar => Value::Number(value_left.powf(value_right)),
+        TokenType::TildeSlash => {
+            if value_right == 0.0 {
+                return Err(RuntimeError::new(operator, "Integer division by zero."));
+            }
+            Value::Number((value_left / value_right).trunc())
+        }
+        TokenType::Greater => Value::Boolean(value_left > value_right),
+        TokenType::GreaterEqual => Value::Boolean(value_left >= value_right),
+        TokenType::Less => Value::Boolean(value_left < value_right),
+        TokenType::LessEqual => Value::Boolean(value_left <= value_right),
+        TokenType::DotDot => Value::Range(value_left, value_right, false),
+        TokenType::DotDotEqual => Value::Range(value_left, value_right, true),
+        TokenType::Ampersand | TokenType::Pipe | TokenType::Caret => {
+            let value_left = to_integer(operator, value_left)?;
+            let value_right = to_integer(operator, value_right)?;
+            let value = match operator.token_type {
+                TokenType::Ampersand => value_left & value_right,
+                TokenType::Pipe => value_left | value_right,
+                _ => value_left ^ value_right,
+            };
+            Value::Number(value as f64)
+        }
+        TokenType::LessLess => {
+            let value_left = to_integer(operator, value_left)?;
+            let amount = to_shift_amount(operator, value_right)?;
+            Value::Number(value_left.wrapping_shl(amount) as f64)
+        }
+        TokenType::GreaterGreater => {
+            let value_left = to_integer(operator, value_left)?;
+            let amount = to_shift_amount(operator, value_right)?;
+            Value::Number((value_left >> amount) as f64)
+        }
+        other => unreachable!("Parser only produces binary operators, not {other:?}"),
+    };
+    Ok(result)
 }
 
 #[cfg(test)]
@@ -114,74 +1326,880 @@ mod tests {
     use super::*;
     use crate::expression::Expr;
     use crate::token::{Literal, Token, TokenType};
+    use crate::value::Value;
 
     #[test]
     fn test_literal() {
-        let interpreter = Interpreter {};
+        let mut interpreter = Interpreter::new();
         let literal = Literal::Number(42.0);
-        assert_eq!(interpreter.visit_literal(&literal), Literal::Number(42.0));
+        assert_eq!(interpreter.visit_literal(&literal), Ok(Value::Number(42.0)));
+    }
+
+    fn token(token_type: TokenType, lexeme: &str) -> Token {
+        Token::new(token_type, lexeme, None, 1)
+    }
+
+    fn literal(ast: &mut Ast, literal: Literal) -> ExprId {
+        ast.add_expr(Expr::Literal(literal))
+    }
+
+    fn number(ast: &mut Ast, value: f64) -> ExprId {
+        literal(ast, Literal::Number(value))
+    }
+
+    /// An expression the interpreter panics on, to check it is never evaluated.
+    fn unevaluated(ast: &mut Ast) -> ExprId {
+        let left = number(ast, 1.0);
+        let right = number(ast, 2.0);
+        ast.add_expr(Expr::Binary(left, token(TokenType::Dot, "."), right))
+    }
+
+    fn evaluate(ast: Ast, expr: ExprId) -> EvalResult {
+        // The trees built here have no local variables to resolve.
+        let locals = Rc::new(ast.expr_table(None));
+        Interpreter::new().interpret(&Rc::new(ast), &locals, expr)
     }
 
     #[test]
     fn test_unary_minus() {
-        let interpreter = Interpreter {};
-        let expr = Expr::Unary(
-            Token {
-                token_type: TokenType::Minus,
-                lexeme: "-".to_string(),
-                literal: None,
-                line: 1,
-            },
-            Box::new(Expr::Literal(Literal::Number(10.0))),
-        );
-        assert_eq!(expr.accept(&interpreter), Literal::Number(-10.0));
+        let mut ast = Ast::new();
+        let operand = number(&mut ast, 10.0);
+        let expr = ast.add_expr(Expr::Unary(token(TokenType::Minus, "-"), operand));
+        assert_eq!(evaluate(ast, expr), Ok(Value::Number(-10.0)));
     }
 
     #[test]
     fn test_unary_not() {
-        let interpreter = Interpreter {};
-        let expr = Expr::Unary(
-            Token {
-                token_type: TokenType::Bang,
-                lexeme: "!".to_string(),
-                literal: None,
-                line: 1,
-            },
-            Box::new(Expr::Literal(Literal::Boolean(true))),
-        );
-        assert_eq!(expr.accept(&interpreter), Literal::Boolean(false));
+        let mut ast = Ast::new();
+        let operand = literal(&mut ast, Literal::Boolean(true));
+        let expr = ast.add_expr(Expr::Unary(token(TokenType::Bang, "!"), operand));
+        assert_eq!(evaluate(ast, expr), Ok(Value::Boolean(false)));
     }
 
     #[test]
     fn test_binary_addition() {
-        let interpreter = Interpreter {};
-        let expr = Expr::Binary(
-            Box::new(Expr::Literal(Literal::Number(5.0))),
-            Token {
-                token_type: TokenType::Plus,
-                lexeme: "+".to_string(),
-                literal: None,
-                line: 1,
-            },
-            Box::new(Expr::Literal(Literal::Number(3.0))),
-        );
-        assert_eq!(expr.accept(&interpreter), Literal::Number(8.0));
+        let mut ast = Ast::new();
+        let left = number(&mut ast, 5.0);
+        let right = number(&mut ast, 3.0);
+        let expr = ast.add_expr(Expr::Binary(left, token(TokenType::Plus, "+"), right));
+        assert_eq!(evaluate(ast, expr), Ok(Value::Number(8.0)));
     }
 
     #[test]
     fn test_binary_multiplication() {
-        let interpreter = Interpreter {};
-        let expr = Expr::Binary(
-            Box::new(Expr::Literal(Literal::Number(4.0))),
-            Token {
-                token_type: TokenType::Star,
-                lexeme: "*".to_string(),
-                literal: None,
+        let mut ast = Ast::new();
+        let left = number(&mut ast, 4.0);
+        let right = number(&mut ast, 2.0);
+        let expr = ast.add_expr(Expr::Binary(left, token(TokenType::Star, "*"), right));
+        assert_eq!(evaluate(ast, expr), Ok(Value::Number(8.0)));
+    }
+
+    #[test]
+    fn test_ternary_evaluates_chosen_branch_only() {
+        let mut ast = Ast::new();
+        let condition = literal(&mut ast, Literal::Boolean(true));
+        let then_branch = number(&mut ast, 1.0);
+        let else_branch = unevaluated(&mut ast);
+        let expr = ast.add_expr(Expr::Ternary(condition, then_branch, else_branch));
+        assert_eq!(evaluate(ast, expr), Ok(Value::Number(1.0)));
+
+        let mut ast = Ast::new();
+        let condition = literal(&mut ast, Literal::Nil);
+        let then_branch = unevaluated(&mut ast);
+        let else_branch = number(&mut ast, 2.0);
+        let expr = ast.add_expr(Expr::Ternary(condition, then_branch, else_branch));
+        assert_eq!(evaluate(ast, expr), Ok(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn test_logical_short_circuit() {
+        let mut ast = Ast::new();
+        let left = number(&mut ast, 1.0);
+        let right = unevaluated(&mut ast);
+        let expr = ast.add_expr(Expr::Logical(left, token(TokenType::Or, "or"), right));
+        assert_eq!(evaluate(ast, expr), Ok(Value::Number(1.0)));
+
+        let mut ast = Ast::new();
+        let left = literal(&mut ast, Literal::Boolean(false));
+        let right = unevaluated(&mut ast);
+        let expr = ast.add_expr(Expr::Logical(left, token(TokenType::And, "and"), right));
+        assert_eq!(evaluate(ast, expr), Ok(Value::Boolean(false)));
+    }
+
+    #[test]
+    fn test_comma() {
+        let mut ast = Ast::new();
+        let left = number(&mut ast, 1.0);
+        let right = number(&mut ast, 2.0);
+        let expr = ast.add_expr(Expr::Comma(left, right));
+        assert_eq!(evaluate(ast, expr), Ok(Value::Number(2.0)));
+    }
+
+    fn eval(source: &str) -> EvalResult {
+        let tokens = crate::scanner::Scanner::new(source).scan_tokens();
+        let mut parser = crate::parser::Parser::new(tokens);
+        let expr = parser.parse_expression().unwrap();
+        evaluate(parser.into_ast(), expr)
+    }
+
+    #[test]
+    fn test_arithmetic_operators() {
+        assert_eq!(eval("7 % 3"), Ok(Value::Number(1.0)));
+        assert_eq!(eval("-7 % 3"), Ok(Value::Number(-1.0)));
+        assert_eq!(eval("2 ** 3 ** 2"), Ok(Value::Number(512.0)));
+        assert_eq!(eval("-2 ** 2"), Ok(Value::Number(-4.0)));
+        assert_eq!(eval("2 ** -1"), Ok(Value::Number(0.5)));
+        assert_eq!(eval("7 ~/ 2"), Ok(Value::Number(3.0)));
+        assert_eq!(eval("-7 ~/ 2"), Ok(Value::Number(-3.0)));
+        assert_eq!(
+            eval("1 ~/ 0").unwrap_err().message,
+            "Integer division by zero."
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        assert_eq!(eval("6 & 3"), Ok(Value::Number(2.0)));
+        assert_eq!(eval("6 | 3"), Ok(Value::Number(7.0)));
+        assert_eq!(eval("6 ^ 3"), Ok(Value::Number(5.0)));
+        assert_eq!(eval("~5"), Ok(Value::Number(-6.0)));
+        assert_eq!(eval("1 << 4"), Ok(Value::Number(16.0)));
+        assert_eq!(eval("-16 >> 2"), Ok(Value::Number(-4.0)));
+        assert_eq!(eval("1 | 2 == 3"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("1 + 1 << 1"), Ok(Value::Number(4.0)));
+    }
+
+    #[test]
+    fn test_bitwise_operands_must_be_integers() {
+        assert_eq!(
+            eval("1.5 & 1").unwrap_err().message,
+            "Operands of '&' must be integers."
+        );
+        assert_eq!(
+            eval("~0.5").unwrap_err().message,
+            "Operands of '~' must be integers."
+        );
+        assert_eq!(
+            eval("1 << 64").unwrap_err().message,
+            "Shift amount must be between 0 and 63."
+        );
+        assert_eq!(
+            eval("\"a\" | 1").unwrap_err().message,
+            "Operands must be numbers."
+        );
+    }
+
+    fn run(interpreter: &mut Interpreter, source: &str) -> Result<(), RuntimeError> {
+        let tokens = crate::scanner::Scanner::new(source).scan_tokens();
+        let mut parser = crate::parser::Parser::new(tokens);
+        let statements = parser.parse().unwrap();
+        let locals = crate::resolver::Resolver::new(parser.ast())
+            .resolve(&statements)
+            .unwrap();
+        interpreter.execute(&Rc::new(parser.into_ast()), &Rc::new(locals), &statements)
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> Value {
+        let token = Token::new(TokenType::Identifier, name, None, 1);
+        interpreter.environment.borrow().get(&token).unwrap()
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "var a = 1; var b; a = b = a + 1;").unwrap();
+        assert_eq!(global(&interpreter, "a"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "b"), Value::Number(2.0));
+        assert_eq!(
+            run(&mut interpreter, "c = 1;").unwrap_err().message,
+            "Undefined variable 'c'."
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = 10; var b = a -= 3; a *= 2; a /= 7; a %= 3;",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "a"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "b"), Value::Number(7.0));
+
+        run(&mut interpreter, "var s = \"a\"; s += \"b\";").unwrap();
+        assert_eq!(global(&interpreter, "s"), Value::Str("ab".to_string()));
+        assert_eq!(
+            run(&mut interpreter, "s -= 1;").unwrap_err().message,
+            "Operands must be numbers."
+        );
+    }
+
+    #[test]
+    fn test_increment_and_decrement() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = 1; var b = a++; var c = ++a; var d = a--; var e = --a;",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "a"), Value::Number(1.0));
+        assert_eq!(global(&interpreter, "b"), Value::Number(1.0));
+        assert_eq!(global(&interpreter, "c"), Value::Number(3.0));
+        assert_eq!(global(&interpreter, "d"), Value::Number(3.0));
+        assert_eq!(global(&interpreter, "e"), Value::Number(1.0));
+        assert_eq!(
+            run(&mut interpreter, "var s = nil; s++;")
+                .unwrap_err()
+                .message,
+            "Operand of '++' must be a number."
+        );
+    }
+
+    #[test]
+    fn test_blocks_and_scopes() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = 1; var b = 1; { var a = 2; b = a; } { a = 3; }",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "a"), Value::Number(3.0));
+        assert_eq!(global(&interpreter, "b"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn test_if_and_while() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var i = 0; var evens = 0; while (i < 10) { if (i % 2 == 0) evens++; else {} i++; }",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "evens"), Value::Number(5.0));
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var sum = 0; for (var i = 0; i < 10; i++) { if (i == 7) break; if (i % 2 == 0) continue; sum += i; }",
+        )
+        .unwrap();
+        // 1 + 3 + 5: the increment clause still runs after `continue`.
+        assert_eq!(global(&interpreter, "sum"), Value::Number(9.0));
+
+        run(
+            &mut interpreter,
+            "var n = 0; while (true) { n++; { if (n < 3) continue; } break; }",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "n"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn test_labeled_break_and_continue() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var pairs = 0;
+            outer: for (var i = 0; i < 5; i++) {
+                for (var j = 0; j < 5; j++) {
+                    if (j > i) continue outer;
+                    if (i == 3) break outer;
+                    pairs++;
+                }
+            }",
+        )
+        .unwrap();
+        // (0,0), (1,0..1), (2,0..2) before `break outer` at i == 3.
+        assert_eq!(global(&interpreter, "pairs"), Value::Number(6.0));
+    }
+
+    #[test]
+    fn test_functions() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            fun nothing() {}
+            var a = fib(10); var b = nothing();",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "a"), Value::Number(55.0));
+        assert_eq!(global(&interpreter, "b"), Value::Nil);
+        assert_eq!(
+            run(&mut interpreter, "fib(1, 2);").unwrap_err().message,
+            "Expected 1 argument but got 2 for fib(n)."
+        );
+        assert_eq!(
+            run(&mut interpreter, "\"fib\"(1);").unwrap_err().message,
+            "Can only call functions and classes."
+        );
+    }
+
+    #[test]
+    fn test_lambdas_as_callbacks() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun fold(n, f, acc) { for (var i = 1; i <= n; i++) acc = f(acc, i); return acc; }
+            var sum = fold(4, (a, b) => a + b, 0);
+            var product = fold(4, fun (a, b) { return a * b; }, 1);
+            var curried = (a) => (b) => a - b;
+            var difference = curried(10)(3);",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "sum"), Value::Number(10.0));
+        assert_eq!(global(&interpreter, "product"), Value::Number(24.0));
+        assert_eq!(global(&interpreter, "difference"), Value::Number(7.0));
+    }
+
+    #[test]
+    fn test_closures_capture_their_environment() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun counter() { var count = 0; return () => ++count; }
+            var a = counter(); var b = counter();
+            a(); a(); b();
+            var first = a(); var second = b();",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "first"), Value::Number(3.0));
+        assert_eq!(global(&interpreter, "second"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn test_closures_see_variables_declared_before_them() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = \"global\"; var r1; var r2; var r3;
+            {
+              fun show() { return a; }
+              var set = (value) => a = value;
+              r1 = show();
+              var a = \"block\";
+              set(\"assigned\");
+              r2 = show();
+              r3 = a;
+            }",
+        )
+        .unwrap();
+        let str = |s: &str| Value::Str(s.to_string());
+        assert_eq!(global(&interpreter, "r1"), str("global"));
+        assert_eq!(global(&interpreter, "r2"), str("assigned"));
+        assert_eq!(global(&interpreter, "r3"), str("block"));
+    }
+
+    #[test]
+    fn test_return_unwinds_loops() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun find(n) { for (var i = 0;; i++) { while (true) { if (i * i >= n) return i; break; } } }
+            var root = find(50);",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "root"), Value::Number(8.0));
+    }
+
+    #[test]
+    fn test_default_parameters() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var calls = 0;
+            fun next() { return ++calls; }
+            fun f(a, b = a * 10, c = next()) { return a + b + c; }
+            var x = f(1); var y = f(1, 2); var z = f(1, 2, 3);",
+        )
+        .unwrap();
+        // Defaults see earlier parameters and are evaluated on every call that omits them.
+        assert_eq!(global(&interpreter, "x"), Value::Number(12.0));
+        assert_eq!(global(&interpreter, "y"), Value::Number(5.0));
+        assert_eq!(global(&interpreter, "z"), Value::Number(6.0));
+        assert_eq!(global(&interpreter, "calls"), Value::Number(2.0));
+        // The callee is never entered, so the error is reported in the caller.
+        assert_eq!(
+            run(&mut interpreter, "f();").unwrap_err().to_string(),
+            "[line 1] Error: Expected 1 to 3 arguments but got 0 for f(a, [b], [c]).
+    at <script> (<script>:1)"
+        );
+        run(&mut interpreter, "fun g() { return f(a: 1, a: 2); }").unwrap();
+        assert_eq!(
+            run(&mut interpreter, "g();").unwrap_err().to_string(),
+            "[line 1] Error: Argument 'a' given twice for f(a, [b], [c]).
+    at g (<script>:1)
+    at <script> (<script>:1)"
+        );
+    }
+
+    #[test]
+    fn test_named_arguments() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun range(start, end, step = 1) { return (end - start) / step; }
+            var a = range(0, step: 2, end: 10); var b = range(end: 3, start: 1);",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "a"), Value::Number(5.0));
+        assert_eq!(global(&interpreter, "b"), Value::Number(2.0));
+        assert_eq!(
+            run(&mut interpreter, "range(0, stop: 1);")
+                .unwrap_err()
+                .message,
+            "Unknown argument 'stop' for range(start, end, [step])."
+        );
+        assert_eq!(
+            run(&mut interpreter, "range(0, 1, start: 1);")
+                .unwrap_err()
+                .message,
+            "Argument 'start' given twice for range(start, end, [step])."
+        );
+        assert_eq!(
+            run(&mut interpreter, "range(step: 1, start: 0);")
+                .unwrap_err()
+                .message,
+            "Missing argument 'end' for range(start, end, [step])."
+        );
+    }
+
+    #[test]
+    fn test_rest_parameters() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun collect(first, ...rest) { return rest; }
+            var none = collect(1); var some = collect(1, 2, \"three\");
+            var wrap = (...xs) => xs;",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "none").to_string(), "[]");
+        assert_eq!(global(&interpreter, "some").to_string(), "[2, \"three\"]");
+        assert_eq!(
+            run(&mut interpreter, "collect();").unwrap_err().message,
+            "Expected at least 1 argument but got 0 for collect(first, ...rest)."
+        );
+        assert_eq!(
+            run(&mut interpreter, "wrap(1, 2, 3, rest: 4);")
+                .unwrap_err()
+                .message,
+            "Unknown argument 'rest' for lambda(...xs)."
+        );
+    }
+
+    #[test]
+    fn test_list_indexing() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var xs = [10, 20, 30, 40];
+            var first = xs[0]; var last = xs[-1];
+            xs[1] = 21; xs[-2] += 1; xs[0]++;
+            var tail = xs[1:]; var middle = xs[-3:-1]; var clamped = xs[-10:10]; var empty = xs[3:1];",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "first"), Value::Number(10.0));
+        assert_eq!(global(&interpreter, "last"), Value::Number(40.0));
+        assert_eq!(global(&interpreter, "xs").to_string(), "[11, 21, 31, 40]");
+        assert_eq!(global(&interpreter, "tail").to_string(), "[21, 31, 40]");
+        assert_eq!(global(&interpreter, "middle").to_string(), "[21, 31]");
+        assert_eq!(
+            global(&interpreter, "clamped").to_string(),
+            "[11, 21, 31, 40]"
+        );
+        assert_eq!(global(&interpreter, "empty").to_string(), "[]");
+        // Slices are copies; lists themselves are shared by reference.
+        run(&mut interpreter, "var ys = xs; ys[0] = 0; tail[0] = 0;").unwrap();
+        assert_eq!(global(&interpreter, "xs").to_string(), "[0, 21, 31, 40]");
+    }
+
+    #[test]
+    fn test_list_index_errors() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "var xs = [1, 2];").unwrap();
+        let error = run(&mut interpreter, "\n\nxs[\n2];").unwrap_err();
+        assert_eq!(error.message, "List index 2 out of range for length 2.");
+        assert_eq!(error.line, 3);
+        assert_eq!(
+            run(&mut interpreter, "xs[-3] = 1;").unwrap_err().message,
+            "List index -3 out of range for length 2."
+        );
+        assert_eq!(
+            run(&mut interpreter, "xs[0.5];").unwrap_err().message,
+            "List index must be an integer."
+        );
+        assert_eq!(
+            run(&mut interpreter, "var n = 1; n[0];")
+                .unwrap_err()
+                .message,
+            "Only lists and maps can be indexed."
+        );
+    }
+
+    #[test]
+    fn test_list_methods() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var xs = [3, 1];
+            xs.push(2); var popped = xs.pop(); xs.push(popped);
+            xs.insert(0, 5); xs.insert(-1, 4); xs.insert(xs.len(), 0);
+            var removed = xs.remove(1);
+            var has = xs.contains(4); var hasnt = xs.contains(\"4\");
+            xs.sort();
+            var push = xs.push;
+            push(9);
+            var words = [\"b\", \"a\"]; words.sort();",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "popped"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "removed"), Value::Number(3.0));
+        assert_eq!(global(&interpreter, "has"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "hasnt"), Value::Boolean(false));
+        assert_eq!(global(&interpreter, "xs").to_string(), "[0, 1, 2, 4, 5, 9]");
+        assert_eq!(global(&interpreter, "words").to_string(), "[\"a\", \"b\"]");
+
+        assert_eq!(
+            run(&mut interpreter, "[].pop();").unwrap_err().message,
+            "Can't pop from an empty list."
+        );
+        assert_eq!(
+            run(&mut interpreter, "[1, \"a\"].sort();")
+                .unwrap_err()
+                .message,
+            "Can only sort lists of numbers or of strings."
+        );
+        assert_eq!(
+            run(&mut interpreter, "xs.insert(1);").unwrap_err().message,
+            "Expected 2 arguments but got 1 for insert(index, value)."
+        );
+        assert_eq!(
+            run(&mut interpreter, "xs.size();").unwrap_err().message,
+            "Lists have no method 'size'."
+        );
+        assert_eq!(
+            run(&mut interpreter, "nil.len();").unwrap_err().message,
+            "Only lists, maps and instances have properties."
+        );
+    }
+
+    #[test]
+    fn test_maps() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var m = {\"b\": 1, 2: true, nil: \"none\"};
+            m[\"a\"] = 3; m[\"b\"] += 10; m[false] = [];
+            var b = m[\"b\"]; var n = m[nil];
+            var keys = m.keys(); var values = m.values();
+            var has = m.has(2); var deleted = m.delete(2); var again = m.delete(2);",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "b"), Value::Number(11.0));
+        assert_eq!(global(&interpreter, "n"), Value::Str("none".to_string()));
+        // Insertion order; overwriting a key keeps its place.
+        assert_eq!(
+            global(&interpreter, "keys").to_string(),
+            "[\"b\", 2, nil, \"a\", false]"
+        );
+        assert_eq!(
+            global(&interpreter, "values").to_string(),
+            "[11, true, \"none\", 3, []]"
+        );
+        assert_eq!(global(&interpreter, "has"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "deleted"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "again"), Value::Boolean(false));
+        assert_eq!(
+            global(&interpreter, "m").to_string(),
+            "{\"b\": 11, nil: \"none\", \"a\": 3, false: []}"
+        );
+        assert_eq!(
+            run(&mut interpreter, "m[\"c\"];").unwrap_err().message,
+            "Key \"c\" not found in map."
+        );
+        assert_eq!(
+            run(&mut interpreter, "m.push(1);").unwrap_err().message,
+            "Maps have no method 'push'."
+        );
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var m = {0: \"zero\"}; m[-0] = \"negative zero\"; m[1.5] = 1;
+            var len = m.len(); var zero = m[0];",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "len"), Value::Number(2.0));
+        assert_eq!(
+            global(&interpreter, "zero"),
+            Value::Str("negative zero".to_string())
+        );
+        assert_eq!(
+            run(&mut interpreter, "m[0 / 0] = 1;").unwrap_err().message,
+            "Map key can't be NaN."
+        );
+        assert_eq!(
+            run(&mut interpreter, "var k = {[]: 1};")
+                .unwrap_err()
+                .message,
+            "Map keys must be numbers, strings, booleans or nil."
+        );
+        assert_eq!(
+            run(&mut interpreter, "m.has(m);").unwrap_err().message,
+            "Map keys must be numbers, strings, booleans or nil."
+        );
+    }
+
+    #[test]
+    fn test_for_in() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var out = [];
+            for (x in [1, 2]) out.push(x);
+            for (k in {\"a\": 1, \"b\": 2}) out.push(k);
+            for (c in \"hé\") out.push(c);
+            for (i in 0..3) out.push(i);
+            for (i in 3..=4) out.push(i);
+            for (i in 5..5) out.push(i);
+            var r = 1..=2;",
+        )
+        .unwrap();
+        assert_eq!(
+            global(&interpreter, "out").to_string(),
+            "[1, 2, \"a\", \"b\", \"h\", \"é\", 0, 1, 2, 3, 4]"
+        );
+        assert_eq!(global(&interpreter, "r").to_string(), "1..=2");
+        assert_eq!(
+            run(&mut interpreter, "for (x in 1) {}")
+                .unwrap_err()
+                .message,
+            "Can only iterate over lists, maps, strings, ranges and objects with an 'iter' method."
+        );
+        assert_eq!(
+            run(&mut interpreter, "0..\"a\";").unwrap_err().message,
+            "Operands must be numbers."
+        );
+    }
+
+    #[test]
+    fn test_for_in_jumps_and_scopes() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var out = []; var fs = [];
+            outer: for (i in 0..3) {
+                for (j in 0..3) {
+                    if (j == 1) continue outer;
+                    if (i == 2) break outer;
+                    out.push([i, j]);
+                }
+            }
+            for (i in 0..2) fs.push(() => i);
+            var first = fs[0]();
+            var xs = [1];
+            for (x in xs) if (x < 3) xs.push(x + 1);",
+        )
+        .unwrap();
+        assert_eq!(global(&interpreter, "out").to_string(), "[[0, 0], [1, 0]]");
+        // Each iteration binds a fresh variable.
+        assert_eq!(global(&interpreter, "first"), Value::Number(0.0));
+        // Elements pushed while looping over a list are visited.
+        assert_eq!(global(&interpreter, "xs").to_string(), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn test_maps_are_not_objects() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var out = [];
+            for (k in {\"iter\": () => 1}) out.push(k);
+            var m = {\"next\": 1};",
+        )
+        .unwrap();
+        // Entries named like the protocol's methods are still just entries.
+        assert_eq!(global(&interpreter, "out").to_string(), "[\"iter\"]");
+        assert_eq!(
+            run(&mut interpreter, "m.next;").unwrap_err().message,
+            "Maps have no method 'next'."
+        );
+    }
+
+    #[test]
+    fn test_catching_errors() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "fun inner() { return 1 + nil; }
+            fun outer() { return inner(); }
+            var error;
+            try {
+                outer();
+            } catch (e) {
+                error = e;
+            }
+            var message = error.message; var line = error.line; var stack = error.stack;
+            var thrown;
+            try { throw [1, 2]; } catch (e) { thrown = e; }
+            var rethrown;
+            try {
+                try { throw \"inner\"; } catch (e) { throw e + \"!\"; }
+            } catch (e) {
+                rethrown = e;
+            }",
+        )
+        .unwrap();
+        assert_eq!(
+            global(&interpreter, "message"),
+            Value::Str("Operands must be two numbers or two strings.".to_string())
+        );
+        assert_eq!(global(&interpreter, "line"), Value::Number(1.0));
+        assert_eq!(
+            global(&interpreter, "stack").to_string(),
+            "[\"inner (<script>:1)\", \"outer (<script>:2)\", \"<script> (<script>:5)\"]"
+        );
+        // Thrown values are caught as they are.
+        assert_eq!(global(&interpreter, "thrown").to_string(), "[1, 2]");
+        assert_eq!(
+            global(&interpreter, "rethrown"),
+            Value::Str("inner!".to_string())
+        );
+
+        let error = run(&mut interpreter, "throw \"oops\";").unwrap_err();
+        assert_eq!(error.message, "oops");
+        assert_eq!(error.thrown, Some(Value::Str("oops".to_string())));
+        // Rethrowing a caught error object keeps its message.
+        assert_eq!(
+            run(&mut interpreter, "try { nil(); } catch (e) { throw e; }")
+                .unwrap_err()
+                .message,
+            "Can only call functions and classes."
+        );
+    }
+
+    #[test]
+    fn test_finally() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var log = [];
+            fun f() {
+                try { return \"try\"; } finally { log.push(\"returned\"); }
+            }
+            var result = f();
+            while (true) {
+                try { break; } finally { log.push(\"broke\"); }
+            }
+            for (i in 0..2) {
+                try { continue; } catch (e) { log.push(\"never\"); } finally { log.push(i); }
+            }
+            try {
+                try { throw \"again\"; } finally { log.push(\"rethrown\"); }
+            } catch (e) {
+                log.push(e);
+            }
+            try {
+                try { throw 1; } catch (e) { throw 2; } finally { log.push(\"after catch\"); }
+            } catch (e) {
+                log.push(e);
+            }
+            fun g() {
+                try { throw \"lost\"; } finally { return \"finally wins\"; }
+            }
+            var overridden = g();",
+        )
+        .unwrap();
+        assert_eq!(
+            global(&interpreter, "result"),
+            Value::Str("try".to_string())
+        );
+        assert_eq!(
+            global(&interpreter, "log").to_string(),
+            "[\"returned\", \"broke\", 0, 1, \"rethrown\", \"again\", \"after catch\", 2]"
+        );
+        assert_eq!(
+            global(&interpreter, "overridden"),
+            Value::Str("finally wins".to_string())
+        );
+        assert_eq!(
+            run(
+                &mut interpreter,
+                "try { throw 3; } finally { log.push(4); }"
+            )
+            .unwrap_err()
+            .message,
+            "3"
+        );
+    }
+
+    #[test]
+    fn test_stack_traces() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_file("lib.lox");
+        run(
+            &mut interpreter,
+            "fun check(x) {
+                if (x < 0) throw \"negative\";
+                return x;
+            }
+            var apply = (f, x) => f(x);",
+        )
+        .unwrap();
+        interpreter.set_file("main.lox");
+        let error = run(&mut interpreter, "\n\napply(check, -1);").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 2] Error: negative
+    at check (lib.lox:2)
+    at lambda (lib.lox:5)
+    at <script> (main.lox:3)"
+        );
+        // The frames of a caught error stay behind for the next one.
+        run(&mut interpreter, "try { apply(check, -1); } catch (e) {}").unwrap();
+        assert_eq!(
+            run(&mut interpreter, "nil();").unwrap_err().stack,
+            [Frame {
+                function: "<script>".to_string(),
+                file: Rc::from("main.lox"),
                 line: 1,
-            },
-            Box::new(Expr::Literal(Literal::Number(2.0))),
+            }]
         );
-        assert_eq!(expr.accept(&interpreter), Literal::Number(8.0));
     }
-}
 
+    #[test]
+    fn test_stack_overflow() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(50);
+        run(
+            &mut interpreter,
+            "fun down(n) { if (n == 0) return 0; return down(n - 1); }
+            var fine = down(49);
+            fun forever() { forever(); }
+            var caught;
+            try { forever(); } catch (e) { caught = e.message; }",
+        )
+        .unwrap();
+        assert_eq!(
+            global(&interpreter, "caught"),
+            Value::Str("Stack overflow.".to_string())
+        );
+        let error = run(&mut interpreter, "down(50);").unwrap_err();
+        assert_eq!(error.stack.len(), 51);
+        assert_eq!(
+            error.to_string(),
+            "[line 1] Error: Stack overflow.
+    at down (<script>:1)
+    ... repeated 49 more times
+    at <script> (<script>:1)"
+        );
+    }
+}
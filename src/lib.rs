@@ -0,0 +1,37 @@
+//! A tree-walking interpreter for Lox.
+//!
+//! Hosts embed it through [`Lox`], together with the value and error types
+//! re-exported here. The modules are public for the REPL and tooling in this
+//! repository, but are not a stable API.
+
+pub mod ast;
+pub mod ast_printer;
+pub mod class;
+pub mod convert;
+pub mod cst;
+pub mod environment;
+pub mod expression;
+pub mod interpreter;
+pub mod limits;
+pub mod list;
+pub mod lox;
+pub mod map;
+pub mod optimizer;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+pub mod statement;
+pub mod stdlib;
+pub mod token;
+pub mod value;
+pub mod walk;
+
+pub use crate::class::{ClassBuilder, Instance, NativeClass};
+pub use crate::convert::{FromValue, IntoNative, IntoResult, IntoValue};
+pub use crate::interpreter::{Frame, RuntimeError};
+pub use crate::limits::{Abort, InterruptHandle, Limits};
+pub use crate::lox::{Error, Lox};
+pub use crate::parser::ParseError;
+pub use crate::resolver::ResolveError;
+pub use crate::token::Token;
+pub use crate::value::Value;
@@ -0,0 +1,370 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Weak;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// What a script may use, for hosts running code they don't trust. The default
+/// has no limits and allows everything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// How many statements may run, counting each statement of a loop body or
+    /// function every time it runs.
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    /// How many lists, maps, functions and instances may be alive at once. Values
+    /// of those types returned by natives count from then on, even if the host
+    /// created them.
+    pub max_objects: Option<usize>,
+    /// The longest string, in bytes, that operators and natives may produce.
+    pub max_string_length: Option<usize>,
+    /// Whether scripts get the built-ins that reach outside the interpreter:
+    /// `input` and `exit`.
+    pub allow_system: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            timeout: None,
+            max_objects: None,
+            max_string_length: None,
+            allow_system: true,
+        }
+    }
+}
+
+/// Stops the script an interpreter is running, from another thread or a signal
+/// handler. The script stops before its next statement with an "Interrupted."
+/// error; if none is running, the next one to start doesn't notice.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// The flag that [`InterruptHandle::interrupt`] sets, for APIs that set one
+    /// themselves, such as signal handlers.
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.0.clone()
+    }
+}
+
+/// Why the interpreter stopped a script. Unlike other runtime errors, these
+/// can't be caught by the script: they always return to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Abort {
+    Steps,
+    Timeout,
+    Objects,
+    StringLength,
+    Interrupted,
+}
+
+impl fmt::Display for Abort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Abort::Steps => "Step limit exceeded.",
+            Abort::Timeout => "Time limit exceeded.",
+            Abort::Objects => "Object limit exceeded.",
+            Abort::StringLength => "String length limit exceeded.",
+            Abort::Interrupted => "Interrupted.",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// The limits together with what a run has used of them so far.
+#[derive(Debug)]
+pub struct Budget {
+    limits: Limits,
+    steps: u64,
+    /// The objects counted against the object limit, by address. Only kept when
+    /// there is a limit; those dropped since are removed when it is reached.
+    objects: HashMap<*const (), Weak<dyn Any>>,
+    /// Whether objects were counted since the last check, which is when the
+    /// limit can have been exceeded.
+    new_objects: bool,
+    started: Instant,
+    interrupt: InterruptHandle,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Budget {
+            limits,
+            steps: 0,
+            objects: HashMap::new(),
+            new_objects: false,
+            started: Instant::now(),
+            interrupt: InterruptHandle::default(),
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Starts a new run with no steps or time used, forgetting interrupts meant
+    /// for the previous one. Objects still alive from earlier runs still count.
+    pub fn reset(&mut self) {
+        self.steps = 0;
+        self.started = Instant::now();
+        self.interrupt.0.store(false, Ordering::Relaxed);
+    }
+
+    /// Accounts for a statement about to run. Objects are checked here rather
+    /// than where they are created, which may not have a line to report.
+    pub fn step(&mut self) -> Result<(), Abort> {
+        if self.interrupt.0.load(Ordering::Relaxed) {
+            self.interrupt.0.store(false, Ordering::Relaxed);
+            return Err(Abort::Interrupted);
+        }
+        self.steps += 1;
+        if self.limits.max_steps.is_some_and(|max| self.steps > max) {
+            return Err(Abort::Steps);
+        }
+        if let Some(max) = self.limits.max_objects.filter(|_| self.new_objects) {
+            self.new_objects = false;
+            if self.objects.len() > max {
+                self.objects.retain(|_, object| object.strong_count() > 0);
+                if self.objects.len() > max {
+                    return Err(Abort::Objects);
+                }
+            }
+        }
+        if self
+            .limits
+            .timeout
+            .is_some_and(|timeout| self.started.elapsed() > timeout)
+        {
+            return Err(Abort::Timeout);
+        }
+        Ok(())
+    }
+
+    /// Counts `object` until it is dropped. Counting an object again does nothing.
+    pub fn allocate(&mut self, object: Weak<dyn Any>) {
+        if self.limits.max_objects.is_some() {
+            self.objects.insert(object.as_ptr() as *const (), object);
+            self.new_objects = true;
+        }
+    }
+
+    pub fn check_string(&self, s: &str) -> Result<(), Abort> {
+        match self.limits.max_string_length {
+            Some(max) if s.len() > max => Err(Abort::StringLength),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::lox::{Error, Lox};
+    use crate::value::Value;
+
+    fn aborted(result: Result<Value, Error>) -> String {
+        match result {
+            Err(Error::Aborted(error)) => error.to_string(),
+            result => panic!("expected the script to be aborted, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut budget = Budget::new(Limits {
+            max_steps: Some(2),
+            max_objects: Some(1),
+            ..Limits::default()
+        });
+        assert_eq!(budget.step(), Ok(()));
+        let (a, b): (Rc<dyn Any>, Rc<dyn Any>) = (Rc::new(1), Rc::new(2));
+        budget.allocate(Rc::downgrade(&a));
+        budget.allocate(Rc::downgrade(&a));
+        assert_eq!(budget.step(), Ok(()));
+        budget.allocate(Rc::downgrade(&b));
+        budget.reset();
+        assert_eq!(budget.step(), Err(Abort::Objects));
+        // The limit is only checked again once more objects are counted.
+        assert_eq!(budget.step(), Ok(()));
+        drop(a);
+        budget.reset();
+        budget.allocate(Rc::downgrade(&b));
+        assert_eq!(budget.step(), Ok(()));
+
+        let mut budget = Budget::new(Limits {
+            max_steps: Some(2),
+            ..Limits::default()
+        });
+        assert_eq!(budget.step(), Ok(()));
+        assert_eq!(budget.step(), Ok(()));
+        assert_eq!(budget.step(), Err(Abort::Steps));
+        budget.reset();
+        assert_eq!(budget.step(), Ok(()));
+
+        let mut budget = Budget::new(Limits {
+            timeout: Some(Duration::from_millis(1)),
+            max_string_length: Some(3),
+            ..Limits::default()
+        });
+        assert_eq!(budget.check_string("abc"), Ok(()));
+        assert_eq!(budget.check_string("abcd"), Err(Abort::StringLength));
+        thread::sleep(Duration::from_millis(2));
+        assert_eq!(budget.step(), Err(Abort::Timeout));
+    }
+
+    #[test]
+    fn test_limits() {
+        let lox = Lox::with_limits(Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        });
+        assert_eq!(
+            aborted(lox.eval("var i = 0;\nwhile (true) { i++; }")),
+            "[line 2] Error: Step limit exceeded.\n    at <script> (<script>:2)"
+        );
+        // Each run gets the whole budget again.
+        assert_eq!(
+            lox.eval("for (x in 0..10) i++; i;"),
+            Ok(Value::Number(509.0))
+        );
+
+        let lox = Lox::with_limits(Limits {
+            timeout: Some(Duration::from_millis(10)),
+            ..Limits::default()
+        });
+        assert_eq!(
+            aborted(lox.eval("fun spin() {\n  while (true) {}\n}\nspin();")),
+            "[line 2] Error: Time limit exceeded.\n    at spin (<script>:2)\n    at <script> (<script>:4)"
+        );
+
+        let lox = Lox::with_limits(Limits {
+            max_objects: Some(100),
+            ..Limits::default()
+        });
+        assert!(lox
+            .eval("var xs = []; for (i in 0..99) xs.push(() => i);")
+            .is_ok());
+        assert_eq!(
+            aborted(lox.eval("var ys = [];\nys.push(1);")),
+            "[line 2] Error: Object limit exceeded.\n    at <script> (<script>:2)"
+        );
+        // Only objects that are still alive count.
+        assert_eq!(
+            lox.eval("xs = nil; for (i in 0..1000) { var pair = [i, {}]; }"),
+            Ok(Value::Nil)
+        );
+
+        let lox = Lox::with_limits(Limits {
+            max_string_length: Some(1000),
+            ..Limits::default()
+        });
+        assert_eq!(
+            aborted(lox.eval("var s = \"ab\";\nwhile (true)\n  s = s +\n    s;")),
+            "[line 3] Error: String length limit exceeded.\n    at <script> (<script>:3)"
+        );
+        assert_eq!(lox.eval("len(s)"), Ok(Value::Number(512.0)));
+    }
+
+    #[test]
+    fn test_aborts_are_not_catchable() {
+        let lox = Lox::with_limits(Limits {
+            max_steps: Some(100),
+            ..Limits::default()
+        });
+        let source = "var log = [];
+            try {
+                try { while (true) {} } catch (e) { log.push(e); } finally { log.push(1); }
+            } finally {
+                log.push(2);
+            }";
+        assert_eq!(
+            aborted(lox.eval(source)),
+            "[line 3] Error: Step limit exceeded.\n    at <script> (<script>:3)"
+        );
+        assert_eq!(lox.eval("log").unwrap().to_string(), "[]");
+
+        // Nor can a `finally` that returns replace an abort raised by the handler.
+        let lox = Lox::with_limits(Limits {
+            max_string_length: Some(10),
+            ..Limits::default()
+        });
+        let source = "fun f() {
+                var s = \"abcdef\";
+                try { throw 1; } catch (e) { s = s + s; } finally { return \"swallowed\"; }
+            }
+            f();";
+        assert_eq!(
+            aborted(lox.eval(source)),
+            "[line 3] Error: String length limit exceeded.\n    at f (<script>:3)\n    at <script> (<script>:5)"
+        );
+    }
+
+    #[test]
+    fn test_deep_values() {
+        // Writing or dropping lists and maps nested this deep must not overflow the
+        // host's stack, whatever limits are set.
+        let lox = Lox::with_limits(Limits::default());
+        let source = "var a = [];
+            var m = {};
+            for (i in 0..100000) {
+              a = [a];
+              m = {\"m\": m};
+            }";
+        assert_eq!(lox.eval(source), Ok(Value::Nil));
+        assert_eq!(lox.eval("len(str(a))"), Ok(Value::Number(200002.0)));
+        assert_eq!(lox.eval("len(str(m))"), Ok(Value::Number(700002.0)));
+        assert_eq!(lox.eval("a = nil; m = nil;"), Ok(Value::Nil));
+    }
+
+    #[test]
+    fn test_interrupts() {
+        let lox = Lox::new();
+        let interrupt = lox.interrupt_handle();
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            interrupt.interrupt();
+        });
+        let source = "var n = 0;\ntry {\n  while (true) n++;\n} catch (e) {}";
+        assert_eq!(
+            aborted(lox.eval(source)),
+            "[line 3] Error: Interrupted.\n    at <script> (<script>:3)"
+        );
+        interrupter.join().unwrap();
+        assert_eq!(lox.eval("n > 0"), Ok(Value::Boolean(true)));
+
+        // An interrupt with no script running is forgotten by the next run.
+        lox.interrupt_handle().interrupt();
+        assert_eq!(lox.eval("var m = 1; m;"), Ok(Value::Number(1.0)));
+    }
+
+    #[test]
+    fn test_system_builtins() {
+        let lox = Lox::with_limits(Limits {
+            allow_system: false,
+            ..Limits::default()
+        });
+        let Err(Error::Runtime(error)) = lox.eval("exit(1)") else {
+            panic!("exit should be undefined");
+        };
+        assert_eq!(error.message, "Undefined variable 'exit'.");
+        assert_eq!(lox.get_global("input"), None);
+        assert!(lox.get_global("clock").is_some());
+        assert!(Lox::new().get_global("input").is_some());
+    }
+}
@@ -0,0 +1,101 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::{interpreter::RuntimeError, token::Token, value::Value};
+
+pub type List = Rc<RefCell<Vec<Value>>>;
+
+fn integer(token: &Token, value: &Value) -> Result<i64, RuntimeError> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 => Ok(*n as i64),
+        _ => Err(RuntimeError::new(token, "List index must be an integer.")),
+    }
+}
+
+/// Resolves `index` into a position in a list of `len` elements.
+/// Negative indices count from the end, so -1 is the last element.
+pub fn position(token: &Token, index: &Value, len: usize) -> Result<usize, RuntimeError> {
+    let index = integer(token, index)?;
+    let position = if index < 0 { index + len as i64 } else { index };
+    if position < 0 || position >= len as i64 {
+        let message = format!("List index {} out of range for length {}.", index, len);
+        return Err(RuntimeError::new(token, &message));
+    }
+    Ok(position as usize)
+}
+
+/// Resolves a slice bound. Like an index it may be negative, but bounds past
+/// either end of the list are clamped rather than rejected.
+pub fn slice_bound(
+    token: &Token,
+    bound: Option<Value>,
+    default: usize,
+    len: usize,
+) -> Result<usize, RuntimeError> {
+    let Some(bound) = bound else {
+        return Ok(default);
+    };
+    let bound = integer(token, &bound)?;
+    let position = if bound < 0 { bound + len as i64 } else { bound };
+    Ok(position.clamp(0, len as i64) as usize)
+}
+
+/// Looks up a built-in method and binds it to `list`.
+pub fn method(list: &List, name: &Token) -> Option<Value> {
+    let list = list.clone();
+    let method = match &*name.lexeme {
+        "len" => Value::native("len", &[], move |_, _| {
+            Ok(Value::Number(list.borrow().len() as f64))
+        }),
+        "push" => Value::native("push", &["value"], move |_, arguments| {
+            list.borrow_mut().extend(arguments);
+            Ok(Value::Nil)
+        }),
+        "pop" => Value::native("pop", &[], move |paren, _| {
+            list.borrow_mut()
+                .pop()
+                .ok_or_else(|| RuntimeError::new(paren, "Can't pop from an empty list."))
+        }),
+        "insert" => Value::native("insert", &["index", "value"], move |paren, arguments| {
+            let [index, value] = <[Value; 2]>::try_from(arguments).unwrap();
+            let len = list.borrow().len();
+            // Inserting at `len` appends.
+            let position = match index {
+                Value::Number(n) if n == len as f64 => len,
+                _ => position(paren, &index, len)?,
+            };
+            list.borrow_mut().insert(position, value);
+            Ok(Value::Nil)
+        }),
+        "remove" => Value::native("remove", &["index"], move |paren, arguments| {
+            let len = list.borrow().len();
+            let position = position(paren, &arguments[0], len)?;
+            Ok(list.borrow_mut().remove(position))
+        }),
+        "contains" => Value::native("contains", &["value"], move |_, arguments| {
+            Ok(Value::Boolean(list.borrow().contains(&arguments[0])))
+        }),
+        "sort" => Value::native("sort", &[], move |paren, _| {
+            let mut list = list.borrow_mut();
+            if list.iter().all(|value| matches!(value, Value::Number(_))) {
+                list.sort_by(|a, b| match (a, b) {
+                    (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+                    _ => unreachable!(),
+                });
+            } else if list.iter().all(|value| matches!(value, Value::Str(_))) {
+                list.sort_by(|a, b| match (a, b) {
+                    (Value::Str(a), Value::Str(b)) => a.cmp(b),
+                    _ => unreachable!(),
+                });
+            } else {
+                return Err(RuntimeError::new(
+                    paren,
+                    "Can only sort lists of numbers or of strings.",
+                ));
+            }
+            Ok(Value::Nil)
+        }),
+        _ => return None,
+    };
+    Some(method)
+}
@@ -0,0 +1,443 @@
+use std::cell::{Cell, RefCell};
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::{
+    class::NativeClass,
+    convert::IntoNative,
+    interpreter::{Interpreter, RuntimeError},
+    limits::{InterruptHandle, Limits},
+    parser::{self, ParseError, Parser},
+    resolver::{ResolveError, Resolver},
+    scanner::Scanner,
+    statement::Stmt,
+    token::Token,
+    value::Value,
+};
+
+/// Why a call into [`Lox`] failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Parse(ParseError),
+    Resolve(Vec<ResolveError>),
+    Runtime(RuntimeError),
+    /// A limit or an interrupt stopped the script. See [`Lox::with_limits`] and
+    /// [`Lox::interrupt_handle`].
+    Aborted(RuntimeError),
+}
+
+impl From<RuntimeError> for Error {
+    fn from(error: RuntimeError) -> Self {
+        match error.abort {
+            Some(_) => Error::Aborted(error),
+            None => Error::Runtime(error),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(error) => write!(f, "{}", error),
+            Error::Resolve(errors) => {
+                for (index, error) in errors.iter().enumerate() {
+                    if index > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            Error::Runtime(error) | Error::Aborted(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// An interpreter session for hosts embedding Lox. Globals persist from one
+/// call to the next.
+///
+/// Sources are copied in, so the values and errors a session returns don't
+/// borrow from them.
+pub struct Lox {
+    /// Borrowed mutably only while code runs. Natives can't reach the session,
+    /// so runs never nest.
+    interpreter: RefCell<Interpreter>,
+    stderr: RefCell<Box<dyn Write>>,
+    max_depth: Cell<usize>,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Lox::with_limits(Limits::default())
+    }
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Lox::default()
+    }
+
+    /// Creates a session for untrusted scripts. Each call to [`Lox::eval`],
+    /// [`Lox::run`] or [`Lox::call`] gets the whole step and time budget;
+    /// objects still alive from earlier calls count towards the object limit.
+    /// A script exceeding a limit fails with [`Error::Aborted`], which it
+    /// can't catch.
+    pub fn with_limits(limits: Limits) -> Self {
+        Lox {
+            interpreter: RefCell::new(Interpreter::with_limits(limits)),
+            stderr: RefCell::new(Box::new(io::stderr())),
+            max_depth: Cell::new(parser::DEFAULT_MAX_DEPTH),
+        }
+    }
+
+    /// Runs `source`, which is either a single expression or a program. Returns
+    /// the value of the expression, or of the program's final expression
+    /// statement, and nil for a program ending in any other statement.
+    pub fn eval(&self, source: &str) -> Result<Value, Error> {
+        let tokens = Scanner::new(source).scan_tokens();
+        let mut parser = Parser::new(tokens.clone());
+        parser.set_max_depth(self.max_depth.get());
+        let (ast, mut statements) = match parser.parse_expression() {
+            Ok(expr) => {
+                let mut ast = parser.into_ast();
+                let statement = ast.add_stmt(Stmt::Expression(expr));
+                (ast, vec![statement])
+            }
+            Err(_) => {
+                let mut parser = Parser::new(tokens);
+                parser.set_max_depth(self.max_depth.get());
+                let statements = parser.parse().map_err(Error::Parse)?;
+                (parser.into_ast(), statements)
+            }
+        };
+        let locals = Resolver::new(&ast)
+            .resolve(&statements)
+            .map_err(Error::Resolve)?;
+        let (ast, locals) = (Rc::new(ast), Rc::new(locals));
+        let mut interpreter = self.interpreter.borrow_mut();
+        interpreter.reset_budget();
+
+        let last = match statements.last().map(|&statement| &ast[statement]) {
+            Some(Stmt::Expression(expr)) => {
+                let expr = *expr;
+                statements.pop();
+                Some(expr)
+            }
+            _ => None,
+        };
+        interpreter.execute(&ast, &locals, &statements)?;
+        match last {
+            Some(expr) => Ok(interpreter.interpret(&ast, &locals, expr)?),
+            None => Ok(Value::Nil),
+        }
+    }
+
+    /// Runs `source` like [`Lox::eval`], but reports errors on the error output
+    /// instead of returning them. Returns whether it succeeded.
+    pub fn run(&self, source: &str) -> bool {
+        match self.eval(source) {
+            Ok(_) => true,
+            Err(error) => {
+                let _ = writeln!(self.stderr.borrow_mut(), "{}", error);
+                false
+            }
+        }
+    }
+
+    /// Calls a Lox function, or a native, with the given arguments.
+    pub fn call(&self, function: &Value, arguments: Vec<Value>) -> Result<Value, Error> {
+        let mut interpreter = self.interpreter.borrow_mut();
+        interpreter.reset_budget();
+        Ok(interpreter.call_value(function.clone(), arguments)?)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpreter.borrow().global(name)
+    }
+
+    pub fn set_global(&self, name: &str, value: Value) {
+        self.interpreter.borrow().set_global(name, value);
+    }
+
+    /// Defines a global function implemented in Rust. It receives the closing
+    /// parenthesis of the call, to report errors at, and exactly as many
+    /// arguments as it has parameters.
+    pub fn register_native(
+        &self,
+        name: &'static str,
+        params: &'static [&'static str],
+        function: impl Fn(&Token, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        self.interpreter
+            .borrow()
+            .define_native(name, params, function);
+    }
+
+    /// Defines a global function from a Rust closure whose parameters implement
+    /// `FromValue` and whose result implements `IntoValue`, or is a `Result` of one
+    /// whose error becomes a runtime error. The arity and the signature in error
+    /// messages come from the parameter types.
+    pub fn register_fn<Args>(&self, name: &'static str, function: impl IntoNative<Args>) {
+        self.register_native(name, params(&function), move |paren, arguments| {
+            function.call(name, paren, arguments)
+        });
+    }
+
+    /// A handle that stops the script running in this session, as with
+    /// [`Error::Aborted`]. It can be sent to other threads.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interpreter.borrow().interrupt_handle()
+    }
+
+    /// Defines a global class implemented in Rust, under the class's name.
+    pub fn register_class(&self, class: Rc<NativeClass>) {
+        let name = class.name;
+        self.interpreter
+            .borrow()
+            .set_global(name, Value::Class(class));
+    }
+
+    /// Sends what scripts print to `out` instead of standard output.
+    pub fn set_stdout(&self, out: impl Write + 'static) {
+        self.interpreter.borrow().set_stdout(out);
+    }
+
+    /// Sends the errors reported by [`Lox::run`] to `out` instead of standard error.
+    pub fn set_stderr(&self, out: impl Write + 'static) {
+        *self.stderr.borrow_mut() = Box::new(out);
+    }
+
+    /// Names the source in stack traces of the code run next.
+    pub fn set_file(&self, file: &str) {
+        self.interpreter.borrow_mut().set_file(file);
+    }
+
+    pub fn set_max_call_depth(&self, max_call_depth: usize) {
+        self.interpreter
+            .borrow_mut()
+            .set_max_call_depth(max_call_depth);
+    }
+
+    pub fn set_max_depth(&self, max_depth: usize) {
+        self.max_depth.set(max_depth);
+    }
+}
+
+fn params<Args, F: IntoNative<Args>>(_function: &F) -> &'static [&'static str] {
+    F::PARAMS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::interpreter::DEFAULT_MAX_CALL_DEPTH;
+
+    /// A sink that tests can read back after handing it to the interpreter.
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Buffer {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        let lox = Lox::new();
+        assert_eq!(lox.eval("1 + 2"), Ok(Value::Number(3.0)));
+        assert_eq!(lox.eval("var x = 2; x * 3;"), Ok(Value::Number(6.0)));
+        assert_eq!(lox.eval("var y = x;"), Ok(Value::Nil));
+        assert_eq!(lox.get_global("y"), Some(Value::Number(2.0)));
+        assert_eq!(lox.get_global("z"), None);
+
+        lox.set_global("z", Value::Str("host".to_string()));
+        assert_eq!(lox.eval("z + \"!\""), Ok(Value::Str("host!".to_string())));
+    }
+
+    #[test]
+    fn test_sources_are_copied() {
+        let lox = Lox::new();
+        for i in 0..3 {
+            lox.eval(&format!("fun f{i}() {{ return {i}; }}")).unwrap();
+        }
+        let f2 = lox.get_global("f2").unwrap();
+        assert_eq!(lox.call(&f2, Vec::new()), Ok(Value::Number(2.0)));
+
+        // Values and errors outlive the source they came from.
+        let (value, error) = {
+            let source = String::from("[1, 2]");
+            (
+                lox.eval(&source).unwrap(),
+                lox.eval(&source.replace(']', "")).unwrap_err(),
+            )
+        };
+        assert_eq!(value.to_string(), "[1, 2]");
+        let _: Box<dyn error::Error> = Box::new(error);
+    }
+
+    /// Programs nested as deeply as the limits allow run on a test thread's
+    /// stack, and anything deeper fails with an error instead of overflowing it.
+    #[test]
+    fn test_deep_programs() {
+        let lox = Lox::new();
+        lox.eval("fun down(n) { if (n == 0) return 0; return 1 + down(n - 1); }")
+            .unwrap();
+        let depth = DEFAULT_MAX_CALL_DEPTH - 1;
+        assert_eq!(
+            lox.eval(&format!("down({})", depth)),
+            Ok(Value::Number(depth as f64))
+        );
+        let Err(Error::Runtime(error)) = lox.eval("fun forever() { return forever(); } forever();")
+        else {
+            panic!("infinite recursion should be a runtime error");
+        };
+        assert_eq!(error.message, "Stack overflow.");
+
+        let depth = parser::DEFAULT_MAX_DEPTH - 1;
+        let nested = |open: &str, inner: &str, close: &str, depth: usize| {
+            format!("{}{}{}", open.repeat(depth), inner, close.repeat(depth))
+        };
+        assert_eq!(
+            lox.eval(&nested("(", "1", ")", depth)),
+            Ok(Value::Number(1.0))
+        );
+        assert_eq!(
+            lox.eval(&nested("!", "1", "", depth)),
+            Ok(Value::Boolean(false))
+        );
+        assert_eq!(
+            lox.eval(&nested("[", "", "]", depth))
+                .unwrap()
+                .to_string()
+                .len(),
+            2 * depth
+        );
+        // Statements inside blocks take a few levels for themselves.
+        assert_eq!(
+            lox.eval(&nested("{", "var x = 1;", "}", depth - 2)),
+            Ok(Value::Nil)
+        );
+        let chain = vec!["1"; depth / 2].join(" + ");
+        assert_eq!(lox.eval(&chain), Ok(Value::Number((depth / 2) as f64)));
+        let chain = vec!["1"; 20_000].join(" + ");
+        assert!(matches!(lox.eval(&chain), Err(Error::Parse(_))));
+        let calls = format!("var f = () => f; f{}", "()".repeat(20_000));
+        assert!(matches!(lox.eval(&calls), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_errors() {
+        let lox = Lox::new();
+        assert_eq!(
+            lox.eval("1 +").unwrap_err().to_string(),
+            "[line 1] Error: Expect expression at end"
+        );
+        assert_eq!(
+            lox.eval("\"abc").unwrap_err().to_string(),
+            "[line 1] Error: Unterminated string at '\"abc'"
+        );
+        assert!(matches!(lox.eval("break;"), Err(Error::Resolve(_))));
+        let Err(Error::Runtime(error)) = lox.eval("nil();") else {
+            panic!("calling nil should be a runtime error");
+        };
+        assert_eq!(error.message, "Can only call functions and classes.");
+
+        let stderr = Buffer::default();
+        lox.set_stderr(stderr.clone());
+        assert!(lox.run("var ok = true;"));
+        assert!(!lox.run("-nil;"));
+        assert_eq!(
+            stderr.text(),
+            "[line 1] Error: Operand must be a number.\n    at <script> (<script>:1)\n"
+        );
+    }
+
+    #[test]
+    fn test_stdout() {
+        let lox = Lox::new();
+        let stdout = Buffer::default();
+        lox.set_stdout(stdout.clone());
+        lox.eval("print 1; print(\"two\"); var p = print; p([3]);")
+            .unwrap();
+        assert_eq!(stdout.text(), "1\ntwo\n[3]\n");
+
+        // Strings are printed as they are, except inside lists and maps.
+        let stdout = Buffer::default();
+        lox.set_stdout(stdout.clone());
+        lox.eval("print \"hi\"; print(str(1) + \"x\"); print [\"a\", {\"b\": \"c\"}];")
+            .unwrap();
+        assert_eq!(stdout.text(), "hi\n1x\n[\"a\", {\"b\": \"c\"}]\n");
+        assert_eq!(lox.eval("\"hi\"").unwrap().repr().to_string(), "\"hi\"");
+    }
+
+    #[test]
+    fn test_printing_cycles() {
+        let lox = Lox::new();
+        let stdout = Buffer::default();
+        lox.set_stdout(stdout.clone());
+        lox.eval("var xs = [1]; xs.push(xs); print xs; var m = {}; m[\"self\"] = m; print m;")
+            .unwrap();
+        assert_eq!(
+            lox.eval("str(m)"),
+            Ok(Value::Str("{\"self\": {...}}".to_string()))
+        );
+        // A container that appears twice without containing itself is written out both times.
+        lox.eval("var ys = [2]; print [ys, ys, [xs]];").unwrap();
+        assert_eq!(
+            stdout.text(),
+            "[1, [...]]\n{\"self\": {...}}\n[[2], [2], [[1, [...]]]]\n"
+        );
+    }
+
+    #[test]
+    fn test_natives_and_calls() {
+        let lox = Lox::new();
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        lox.register_native("double", &["x"], move |paren, arguments| {
+            counter.set(counter.get() + 1);
+            match arguments[0] {
+                Value::Number(n) => Ok(Value::Number(n * 2.0)),
+                _ => Err(RuntimeError::new(paren, "Expected a number.")),
+            }
+        });
+        lox.eval("fun add(a, b = 10) { return double(a) + b; }")
+            .unwrap();
+        let add = lox.get_global("add").unwrap();
+        assert_eq!(
+            lox.call(&add, vec![Value::Number(1.0)]),
+            Ok(Value::Number(12.0))
+        );
+        assert_eq!(calls.get(), 1);
+
+        let error = lox.call(&add, vec![Value::Nil]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 1] Error: Expected a number.\n    at add (<script>:1)\n    at <script> (<script>:0)"
+        );
+        let double = lox.get_global("double").unwrap();
+        assert_eq!(
+            lox.call(&double, vec![]).unwrap_err().to_string(),
+            "[line 0] Error: Expected 1 argument but got 0 for double(x).\n    at <script> (<script>:0)"
+        );
+    }
+}
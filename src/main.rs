@@ -1,46 +1,190 @@
+use std::env;
+use std::fs;
 use std::io::{self, Write};
+use std::rc::Rc;
+use std::thread;
 
-mod token;
-mod scanner;
-mod parser;
-mod expression;
-mod ast_printer;
-mod interpreter;
-use crate::scanner::Scanner;
-use crate::parser::Parser;
-use crate::ast_printer::AstPrinter;
-use crate::interpreter::Interpreter;
-
-fn run_prompt() {
-    let mut buffer = String::new();
+use signal_hook::consts::SIGINT;
+
+use rslox1::ast_printer::AstPrinter;
+use rslox1::cst;
+use rslox1::interpreter::{Interpreter, DEFAULT_MAX_CALL_DEPTH};
+use rslox1::optimizer::Optimizer;
+use rslox1::parser::{self, Parser};
+use rslox1::resolver::Resolver;
+use rslox1::scanner::Scanner;
+
+/// Rust stack reserved per level of nesting allowed by `--max-depth`, which the
+/// parser and every pass over the tree recurse through. Calls need none, as the
+/// interpreter grows its stack as needed.
+const STACK_PER_LEVEL: usize = 16 * 1024;
+
+const USAGE: &str = "Usage: rslox1 [--cst | --reprint] <path>
+       rslox1 --dump-optimized [--max-depth <n>] <path>
+       rslox1 [--max-call-depth <n>] [--max-depth <n>]";
+
+fn run_prompt(mut interpreter: Interpreter, max_depth: usize) {
+    interpreter.set_file("<repl>");
+    // Ctrl-C stops the line being run instead of the whole session.
+    let interrupt = interpreter.interrupt_handle();
+    if let Err(error) = signal_hook::flag::register(SIGINT, interrupt.flag()) {
+        eprintln!("Can't handle Ctrl-C: {error}");
+    }
     loop {
-        io::stdout().write(b"> ").unwrap();
+        let mut buffer = String::new();
+        io::stdout().write_all(b"> ").unwrap();
         io::stdout().flush().unwrap();
-        io::stdin().read_line(&mut buffer).unwrap();
-        io::stdout().write(format!("You type: {buffer}").as_bytes()).unwrap();
-        run(&buffer);
-        buffer.clear();
+        if io::stdin().read_line(&mut buffer).unwrap() == 0 {
+            break;
+        }
+        io::stdout()
+            .write_all(format!("You type: {buffer}").as_bytes())
+            .unwrap();
+        interpreter.reset_budget();
+        run(&mut interpreter, &buffer, max_depth);
+    }
+}
+
+fn run(interpreter: &mut Interpreter, source_code: &str, max_depth: usize) {
+    let tokens = Scanner::new(source_code).scan_tokens();
+    println!("Tokens: {:?}", tokens);
+
+    // A bare expression is evaluated and its value echoed back.
+    let mut parser = Parser::new(tokens.clone());
+    parser.set_max_depth(max_depth);
+    if let Ok(expr) = parser.parse_expression() {
+        let ast = Rc::new(parser.into_ast());
+        println!("expr: {:?}", ast[expr]);
+        println!("ast: {}", AstPrinter::new(&ast).print(expr));
+        let locals = match Resolver::new(&ast).resolve_expression(expr) {
+            Ok(locals) => Rc::new(locals),
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{error}");
+                }
+                return;
+            }
+        };
+        match interpreter.interpret(&ast, &locals, expr) {
+            Ok(eval) => println!("interpreter: {}", eval.repr()),
+            Err(error) => eprintln!("{error}"),
+        }
+        return;
+    }
+
+    let mut parser = Parser::new(tokens);
+    parser.set_max_depth(max_depth);
+    let statements = match parser.parse() {
+        Ok(statements) => statements,
+        Err(error) => {
+            eprintln!("{error}");
+            return;
+        }
+    };
+    let ast = Rc::new(parser.into_ast());
+    let ast_printer = AstPrinter::new(&ast);
+    for statement in &statements {
+        println!("ast: {}", ast_printer.print_stmt(*statement));
+    }
+    let locals = match Resolver::new(&ast).resolve(&statements) {
+        Ok(locals) => Rc::new(locals),
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{error}");
+            }
+            return;
+        }
+    };
+    if let Err(error) = interpreter.execute(&ast, &locals, &statements) {
+        eprintln!("{error}");
+    }
+}
+
+/// Prints the program in `source` as the optimizer rewrites it, one statement
+/// per line, or the errors that keep it from running.
+fn dump_optimized(source: &str, max_depth: usize) {
+    let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+    parser.set_max_depth(max_depth);
+    let statements = match parser.parse() {
+        Ok(statements) => statements,
+        Err(error) => {
+            eprintln!("{error}");
+            return;
+        }
+    };
+    let ast = parser.into_ast();
+    if let Err(errors) = Resolver::new(&ast).resolve(&statements) {
+        for error in errors {
+            eprintln!("{error}");
+        }
+        return;
+    }
+    let (ast, statements) = Optimizer::new(&ast).optimize(&statements);
+    let ast_printer = AstPrinter::new(&ast);
+    for statement in &statements {
+        println!("{}", ast_printer.print_stmt(*statement));
     }
 }
 
-fn run(source_code: &str) {
-    let mut scanner = Scanner::new(source_code);
-    scanner.scan_tokens();
-    println!("Tokens: {:?}", scanner.tokens);
-    
-    let mut parser = Parser::new(scanner.tokens);
-    let expr = parser.parse();
-    println!("expr: {:?}", expr);
-
-    let ast_printer = AstPrinter{};
-    let text = ast_printer.print(&expr);
-    println!("ast: {}", text);
-
-    let interpreter = Interpreter{};
-    let eval = interpreter.interpret(&expr);
-    println!("interpreter: {}", eval);
+/// Reads `--max-call-depth <n>` and `--max-depth <n>` options.
+fn limits(options: &[String]) -> Option<(usize, usize)> {
+    let mut max_call_depth = DEFAULT_MAX_CALL_DEPTH;
+    let mut max_depth = parser::DEFAULT_MAX_DEPTH;
+    for option in options.chunks(2) {
+        let [flag, n] = option else {
+            return None;
+        };
+        let limit = match flag.as_str() {
+            "--max-call-depth" => &mut max_call_depth,
+            "--max-depth" => &mut max_depth,
+            _ => return None,
+        };
+        *limit = n.parse().ok()?;
+    }
+    Some((max_call_depth, max_depth))
+}
+
+/// Runs `f` on a thread with enough stack for `max_depth` levels of nesting.
+fn with_stack(max_depth: usize, f: impl FnOnce() + Send + 'static) {
+    let stack_size = (max_depth * STACK_PER_LEVEL).max(8 * 1024 * 1024);
+    thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(f)
+        .unwrap()
+        .join()
+        .unwrap();
 }
 
 fn main() {
-    run_prompt();
+    let args: Vec<String> = env::args().collect();
+    match args.as_slice() {
+        [_, flag, path] if flag == "--cst" => {
+            let source = fs::read_to_string(path).unwrap();
+            print!("{}", cst::parse_cst(&source).dump());
+        }
+        [_, flag, path] if flag == "--reprint" => {
+            let source = fs::read_to_string(path).unwrap();
+            print!("{}", cst::parse_cst(&source).text());
+        }
+        [_, flag, options @ .., path] if flag == "--dump-optimized" => {
+            let Some((_, max_depth)) = limits(options) else {
+                eprintln!("{USAGE}");
+                return;
+            };
+            let source = fs::read_to_string(path).unwrap();
+            with_stack(max_depth, move || dump_optimized(&source, max_depth));
+        }
+        _ => {
+            let Some((max_call_depth, max_depth)) = limits(args.get(1..).unwrap_or_default())
+            else {
+                eprintln!("{USAGE}");
+                return;
+            };
+            with_stack(max_depth, move || {
+                let mut interpreter = Interpreter::new();
+                interpreter.set_max_call_depth(max_call_depth);
+                run_prompt(interpreter, max_depth);
+            });
+        }
+    }
 }
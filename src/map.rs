@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{interpreter::RuntimeError, token::Token, value::Value};
+
+/// A hashable map key. Numbers are keyed by their bits, with -0.0 stored as 0.0
+/// so that the two (which compare equal) are the same key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Str(String),
+    Number(u64),
+    Boolean(bool),
+    Nil,
+}
+
+impl Key {
+    pub fn new(token: &Token, value: &Value) -> Result<Key, RuntimeError> {
+        match value {
+            Value::Str(s) => Ok(Key::Str(s.clone())),
+            Value::Number(n) if n.is_nan() => {
+                Err(RuntimeError::new(token, "Map key can't be NaN."))
+            }
+            Value::Number(n) if *n == 0.0 => Ok(Key::Number(0f64.to_bits())),
+            Value::Number(n) => Ok(Key::Number(n.to_bits())),
+            Value::Boolean(b) => Ok(Key::Boolean(*b)),
+            Value::Nil => Ok(Key::Nil),
+            _ => Err(RuntimeError::new(
+                token,
+                "Map keys must be numbers, strings, booleans or nil.",
+            )),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Key::Str(s) => Value::Str(s.clone()),
+            Key::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            Key::Boolean(b) => Value::Boolean(*b),
+            Key::Nil => Value::Nil,
+        }
+    }
+}
+
+/// Entries in insertion order, plus a hash index into them.
+#[derive(Debug, Default)]
+pub struct Map {
+    entries: Vec<(Key, Value)>,
+    positions: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Map::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        self.positions
+            .get(key)
+            .map(|&position| &self.entries[position].1)
+    }
+
+    /// Replacing the value of an existing key keeps its place in the order.
+    pub fn insert(&mut self, key: Key, value: Value) {
+        match self.positions.get(&key) {
+            Some(&position) => self.entries[position].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Value> {
+        let position = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(position);
+        for later in self.positions.values_mut() {
+            if *later > position {
+                *later -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Key, Value)> {
+        self.entries.iter()
+    }
+
+    /// Empties the map, handing back its values.
+    pub fn drain_values(&mut self) -> impl Iterator<Item = Value> + '_ {
+        self.positions.clear();
+        self.entries.drain(..).map(|(_, value)| value)
+    }
+}
+
+fn list(elements: impl Iterator<Item = Value>) -> Value {
+    Value::List(Rc::new(RefCell::new(elements.collect())))
+}
+
+/// Looks up a built-in method and binds it to `map`.
+pub fn method(map: &Rc<RefCell<Map>>, name: &Token) -> Option<Value> {
+    let map = map.clone();
+    let method = match &*name.lexeme {
+        "len" => Value::native("len", &[], move |_, _| {
+            Ok(Value::Number(map.borrow().len() as f64))
+        }),
+        "keys" => Value::native("keys", &[], move |_, _| {
+            Ok(list(map.borrow().iter().map(|(key, _)| key.to_value())))
+        }),
+        "values" => Value::native("values", &[], move |_, _| {
+            Ok(list(map.borrow().iter().map(|(_, value)| value.clone())))
+        }),
+        "has" => Value::native("has", &["key"], move |paren, arguments| {
+            let key = Key::new(paren, &arguments[0])?;
+            Ok(Value::Boolean(map.borrow().get(&key).is_some()))
+        }),
+        "delete" => Value::native("delete", &["key"], move |paren, arguments| {
+            let key = Key::new(paren, &arguments[0])?;
+            Ok(Value::Boolean(map.borrow_mut().remove(&key).is_some()))
+        }),
+        _ => return None,
+    };
+    Some(method)
+}
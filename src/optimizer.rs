@@ -0,0 +1,293 @@
+use crate::{
+    ast::{Ast, ExprId, StmtId},
+    expression::Expr,
+    interpreter::{binary_operation, is_truthy, unary_operation},
+    statement::Stmt,
+    token::{Literal, Token, TokenType},
+    value::Value,
+    walk::Folder,
+};
+
+/// Rewrites a resolved program into a simpler one that prints the same output:
+///
+/// - operators on literals are evaluated ahead of time, as the interpreter would
+///   evaluate them. Operations that would fail are left for the interpreter to
+///   report, and so are those whose result has no literal, such as ranges.
+/// - `if` statements and ternaries with a literal condition are replaced by the
+///   branch that would run.
+/// - `- -x` becomes `x` where `x` is known to be a number, since negating
+///   anything else is an error.
+/// - groupings are left out, as the tree already says what they group.
+pub struct Optimizer<'a> {
+    ast: &'a Ast,
+    optimized: Ast,
+}
+
+impl<'a> Optimizer<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
+        Optimizer {
+            ast,
+            optimized: Ast::new(),
+        }
+    }
+
+    /// Returns the optimized tree, and the statements of the program in it.
+    pub fn optimize(mut self, statements: &[StmtId]) -> (Ast, Vec<StmtId>) {
+        let statements = self.fold_stmts(statements);
+        (self.optimized, statements)
+    }
+
+    /// The literal an already optimized expression is, if any.
+    fn constant(&self, expr: ExprId) -> Option<Value> {
+        match &self.optimized[expr] {
+            Expr::Literal(literal) => Some(Value::from(literal.clone())),
+            _ => None,
+        }
+    }
+
+    /// Whether an already optimized expression yields a number whenever it
+    /// doesn't fail.
+    fn is_number(&self, expr: ExprId) -> bool {
+        match &self.optimized[expr] {
+            Expr::Literal(literal) => matches!(literal, Literal::Number(_)),
+            Expr::Unary(operator, _) => operator.token_type != TokenType::Bang,
+            Expr::Binary(_, operator, _) => matches!(
+                operator.token_type,
+                TokenType::Minus
+                    | TokenType::Star
+                    | TokenType::Slash
+                    | TokenType::Percent
+                    | TokenType::StarStar
+                    | TokenType::TildeSlash
+                    | TokenType::Ampersand
+                    | TokenType::Pipe
+                    | TokenType::Caret
+                    | TokenType::LessLess
+                    | TokenType::GreaterGreater
+            ),
+            _ => false,
+        }
+    }
+
+    /// Adds `value` as a literal, if it has one.
+    fn fold_value(&mut self, mut value: Value) -> Option<ExprId> {
+        let literal = match &mut value {
+            Value::Nil => Literal::Nil,
+            Value::Boolean(b) => Literal::Boolean(*b),
+            Value::Number(n) => Literal::Number(*n),
+            Value::Str(s) => Literal::Str(std::mem::take(s)),
+            _ => return None,
+        };
+        Some(self.optimized.add_expr(Expr::Literal(literal)))
+    }
+}
+
+impl<'a> Folder<'a> for Optimizer<'a> {
+    fn ast(&self) -> &'a Ast {
+        self.ast
+    }
+
+    fn folded(&mut self) -> &mut Ast {
+        &mut self.optimized
+    }
+
+    fn fold_grouping(&mut self, expr: ExprId) -> ExprId {
+        self.fold_expr(expr)
+    }
+
+    fn fold_unary(&mut self, operator: &Token, right: ExprId) -> ExprId {
+        let right = self.fold_expr(right);
+        if let Some(value) = self.constant(right) {
+            if let Some(folded) = unary_operation(operator, value)
+                .ok()
+                .and_then(|value| self.fold_value(value))
+            {
+                return folded;
+            }
+        }
+        if operator.token_type == TokenType::Minus {
+            if let Expr::Unary(inner, operand) = &self.optimized[right] {
+                if inner.token_type == TokenType::Minus && self.is_number(*operand) {
+                    return *operand;
+                }
+            }
+        }
+        self.add_expr(Expr::Unary(operator.clone(), right))
+    }
+
+    fn fold_binary(&mut self, left: ExprId, operator: &Token, right: ExprId) -> ExprId {
+        let left = self.fold_expr(left);
+        let right = self.fold_expr(right);
+        if let (Some(left), Some(right)) = (self.constant(left), self.constant(right)) {
+            if let Some(folded) = binary_operation(operator, left, right)
+                .ok()
+                .and_then(|value| self.fold_value(value))
+            {
+                return folded;
+            }
+        }
+        self.add_expr(Expr::Binary(left, operator.clone(), right))
+    }
+
+    fn fold_ternary(
+        &mut self,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
+    ) -> ExprId {
+        let condition = self.fold_expr(condition);
+        match self.constant(condition) {
+            Some(value) if is_truthy(&value) => self.fold_expr(then_branch),
+            Some(_) => self.fold_expr(else_branch),
+            None => {
+                let then_branch = self.fold_expr(then_branch);
+                let else_branch = self.fold_expr(else_branch);
+                self.add_expr(Expr::Ternary(condition, then_branch, else_branch))
+            }
+        }
+    }
+
+    fn fold_if_stmt(
+        &mut self,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> StmtId {
+        let condition = self.fold_expr(condition);
+        match (self.constant(condition), else_branch) {
+            (Some(value), _) if is_truthy(&value) => self.fold_stmt(then_branch),
+            (Some(_), Some(else_branch)) => self.fold_stmt(else_branch),
+            (Some(_), None) => self.add_stmt(Stmt::Block(Vec::new())),
+            (None, _) => {
+                let then_branch = self.fold_stmt(then_branch);
+                let else_branch = else_branch.map(|else_branch| self.fold_stmt(else_branch));
+                self.add_stmt(Stmt::If(condition, then_branch, else_branch))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{
+        ast_printer::AstPrinter, interpreter::Interpreter, parser::Parser, resolver::Resolver,
+        scanner::Scanner,
+    };
+
+    fn parse(source: &str) -> (Ast, Vec<StmtId>) {
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+        let statements = parser.parse().unwrap();
+        Resolver::new(parser.ast()).resolve(&statements).unwrap();
+        (parser.into_ast(), statements)
+    }
+
+    fn optimized(source: &str) -> Vec<String> {
+        let (ast, statements) = parse(source);
+        let (ast, statements) = Optimizer::new(&ast).optimize(&statements);
+        let printer = AstPrinter::new(&ast);
+        statements
+            .iter()
+            .map(|statement| printer.print_stmt(*statement))
+            .collect()
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// What the program prints, followed by the error it stops with, if any.
+    fn output(ast: Ast, statements: &[StmtId]) -> String {
+        let stdout = Buffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_stdout(stdout.clone());
+        let locals = Resolver::new(&ast).resolve(statements).unwrap();
+        let result = interpreter.execute(&Rc::new(ast), &Rc::new(locals), statements);
+        let mut output = String::from_utf8(stdout.0.take()).unwrap();
+        if let Err(error) = result {
+            output.push_str(&error.to_string());
+        }
+        output
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(
+            optimized("print 1 + 2 * 3; print (1 < 2) == !nil; print \"a\" + \"b\" + c;"),
+            ["(print 7)", "(print true)", "(print (+ \"ab\" c))"]
+        );
+        assert_eq!(
+            optimized("print -(1 - 3) ~/ 0; print -\"a\"; print 1..3; print 5 & 1.5;"),
+            [
+                "(print (~/ 2 0))",
+                "(print (- \"a\"))",
+                "(print (.. 1 3))",
+                "(print (& 5 1.5))"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dead_branches() {
+        assert_eq!(
+            optimized("if (false) print 1; if (nil) print 2; else print 3; if (\"\") print 4;"),
+            ["(block)", "(print 3)", "(print 4)"]
+        );
+        assert_eq!(
+            optimized("if (1 > 2) print 1; else if (x) print 2; print 1 == 1 ? a : b;"),
+            ["(if x (print 2))", "(print a)"]
+        );
+    }
+
+    #[test]
+    fn test_double_negation() {
+        assert_eq!(
+            optimized("print - -(x * y); print -(-(-x)); print - -x; print !!x;"),
+            [
+                "(print (* x y))",
+                "(print (- x))",
+                "(print (- (- x)))",
+                "(print (! (! x)))"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_output() {
+        let sources = [
+            "var x = 2;
+            print 1 + 2 * 3 - x;
+            print \"con\" + \"cat\" + x;
+            print !(1 >= 2) and !!x;
+            if (1 != 1) print \"dead\"; else { var y = - -x * 2; print y; }
+            for (i in 0..3) print i % 2 == 0 ? \"even\" : \"odd\";
+            fun f(n = 2 ** 10) { if (true) return -(-n); }
+            print f();
+            print - -\"s\";",
+            "print (4 - 5) * 2; print 1 ~/ 0;",
+            "print -(-[1]);",
+        ];
+        for source in sources {
+            let (ast, statements) = parse(source);
+            let (optimized, optimized_statements) = Optimizer::new(&ast).optimize(&statements);
+            assert_eq!(
+                output(optimized, &optimized_statements),
+                output(ast, &statements),
+                "{source}"
+            );
+        }
+    }
+}
@@ -1,20 +1,217 @@
+use std::fmt;
+use std::rc::Rc;
+
 use crate::{
+    ast::{Ast, ExprId, StmtId},
     expression::Expr,
+    statement::{FunctionDecl, Param, Stmt},
     token::{Literal, Token, TokenType},
 };
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: u32,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)
+    }
+}
+
+type ParseResult = Result<ExprId, ParseError>;
+type StmtResult = Result<StmtId, ParseError>;
+
+/// Binding power of infix and postfix operators, weakest first.
+///
+/// Bitwise operators bind tighter than comparisons (so `x & 1 == 0` means
+/// `(x & 1) == 0`), and ranges sit between the two, so `0..n + 1` is
+/// `0..(n + 1)` and `0..n == r` compares the range.
+///
+/// `**` binds tighter than prefix operators on its left but its right operand
+/// may itself be a prefix expression: `-2 ** 2` is `-(2 ** 2)` and `2 ** -1`
+/// is `2 ** (-1)`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Precedence {
+    None,
+    Comma,
+    Assignment,
+    Ternary,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Range,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Term,
+    Factor,
+    Unary,
+    Exponent,
+    Postfix,
+    Primary,
+}
+
+impl Precedence {
+    pub fn next(self) -> Precedence {
+        match self {
+            Precedence::None => Precedence::Comma,
+            Precedence::Comma => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Ternary,
+            Precedence::Ternary => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Range,
+            Precedence::Range => Precedence::BitOr,
+            Precedence::BitOr => Precedence::BitXor,
+            Precedence::BitXor => Precedence::BitAnd,
+            Precedence::BitAnd => Precedence::Shift,
+            Precedence::Shift => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Exponent,
+            Precedence::Exponent => Precedence::Postfix,
+            Precedence::Postfix | Precedence::Primary => Precedence::Primary,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// Parses an expression that starts with the given token.
+type PrefixParselet = fn(&mut Parser, Token) -> ParseResult;
+/// Parses the rest of an expression given its left operand and operator token.
+/// Postfix operators use the same slot and simply don't consume a right operand.
+type InfixParselet = fn(&mut Parser, ExprId, Token) -> ParseResult;
+
+pub struct ParseRule {
+    pub prefix: Option<PrefixParselet>,
+    pub infix: Option<InfixParselet>,
+    pub precedence: Precedence,
+    pub associativity: Associativity,
+}
+
+/// The operator table. Adding an operator means adding a row here.
+pub fn rule(token_type: TokenType) -> ParseRule {
+    use Associativity::*;
+    use Precedence as P;
+    #[rustfmt::skip]
+    let (prefix, infix, precedence, associativity): (Option<PrefixParselet>, Option<InfixParselet>, _, _) = match token_type {
+        TokenType::LeftParen      => (Some(Parser::grouping),         Some(Parser::call),                P::Postfix,    Left),
+        TokenType::LeftBracket    => (Some(Parser::list),             Some(Parser::index),               P::Postfix,    Left),
+        TokenType::Dot            => (None,                           Some(Parser::property),            P::Postfix,    Left),
+        TokenType::LeftBrace      => (Some(Parser::map),              None,                              P::None,       Left),
+        TokenType::Fun            => (Some(Parser::lambda),           None,                              P::None,       Left),
+        TokenType::Comma          => (None,                           Some(Parser::comma),               P::Comma,      Left),
+        TokenType::Equal          => (None,                           Some(Parser::assignment),          P::Assignment, Right),
+        TokenType::PlusEqual      => (None,                           Some(Parser::compound_assignment), P::Assignment, Right),
+        TokenType::MinusEqual     => (None,                           Some(Parser::compound_assignment), P::Assignment, Right),
+        TokenType::StarEqual      => (None,                           Some(Parser::compound_assignment), P::Assignment, Right),
+        TokenType::SlashEqual     => (None,                           Some(Parser::compound_assignment), P::Assignment, Right),
+        TokenType::PercentEqual   => (None,                           Some(Parser::compound_assignment), P::Assignment, Right),
+        TokenType::PlusPlus       => (Some(Parser::prefix_increment), Some(Parser::postfix_increment),   P::Postfix,    Left),
+        TokenType::MinusMinus     => (Some(Parser::prefix_increment), Some(Parser::postfix_increment),   P::Postfix,    Left),
+        TokenType::Question       => (None,                           Some(Parser::ternary),             P::Ternary,    Right),
+        TokenType::Or             => (None,                           Some(Parser::logical),             P::Or,         Left),
+        TokenType::And            => (None,                           Some(Parser::logical),             P::And,        Left),
+        TokenType::Minus          => (Some(Parser::unary),            Some(Parser::binary),              P::Term,       Left),
+        TokenType::Plus           => (None,                           Some(Parser::binary),              P::Term,       Left),
+        TokenType::Slash          => (None,                           Some(Parser::binary),              P::Factor,     Left),
+        TokenType::Star           => (None,                           Some(Parser::binary),              P::Factor,     Left),
+        TokenType::Percent        => (None,                           Some(Parser::binary),              P::Factor,     Left),
+        TokenType::TildeSlash     => (None,                           Some(Parser::binary),              P::Factor,     Left),
+        TokenType::StarStar       => (None,                           Some(Parser::binary),              P::Exponent,   Right),
+        TokenType::DotDot         => (None,                           Some(Parser::binary),              P::Range,      Left),
+        TokenType::DotDotEqual    => (None,                           Some(Parser::binary),              P::Range,      Left),
+        TokenType::Pipe           => (None,                           Some(Parser::binary),              P::BitOr,      Left),
+        TokenType::Caret          => (None,                           Some(Parser::binary),              P::BitXor,     Left),
+        TokenType::Ampersand      => (None,                           Some(Parser::binary),              P::BitAnd,     Left),
+        TokenType::LessLess       => (None,                           Some(Parser::binary),              P::Shift,      Left),
+        TokenType::GreaterGreater => (None,                           Some(Parser::binary),              P::Shift,      Left),
+        TokenType::Tilde          => (Some(Parser::unary),            None,                              P::None,       Left),
+        TokenType::Bang           => (Some(Parser::unary),            None,                              P::None,       Left),
+        TokenType::BangEqual      => (None,                           Some(Parser::binary),              P::Equality,   Left),
+        TokenType::EqualEqual     => (None,                           Some(Parser::binary),              P::Equality,   Left),
+        TokenType::Greater        => (None,                           Some(Parser::binary),              P::Comparison, Left),
+        TokenType::GreaterEqual   => (None,                           Some(Parser::binary),              P::Comparison, Left),
+        TokenType::Less           => (None,                           Some(Parser::binary),              P::Comparison, Left),
+        TokenType::LessEqual      => (None,                           Some(Parser::binary),              P::Comparison, Left),
+        TokenType::Number         => (Some(Parser::literal),          None,                              P::None,       Left),
+        TokenType::StringLiteral  => (Some(Parser::literal),          None,                              P::None,       Left),
+        TokenType::False          => (Some(Parser::literal),          None,                              P::None,       Left),
+        TokenType::True           => (Some(Parser::literal),          None,                              P::None,       Left),
+        TokenType::Nil            => (Some(Parser::literal),          None,                              P::None,       Left),
+        TokenType::Print          => (Some(Parser::variable),         None,                              P::None,       Left),
+        TokenType::Identifier     => (Some(Parser::variable),         None,                              P::None,       Left),
+        _                         => (None,                           None,                              P::None,       Left),
+    };
+    ParseRule {
+        prefix,
+        infix,
+        precedence,
+        associativity,
+    }
+}
+
+/// How deeply statements, functions and expressions may nest by default. The parser
+/// and every pass over the tree recurse once per level.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
 pub struct Parser {
     tokens: Vec<Token>,
+    /// The tree parsed so far. Parse errors leave any nodes already added in it.
+    ast: Ast,
     current: usize,
+    depth: usize,
+    max_depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
-        return Parser { tokens, current: 0 };
+        Parser {
+            tokens,
+            ast: Ast::new(),
+            current: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// The tree that the IDs returned by `parse` and `parse_expression` refer to.
+    pub fn ast(&self) -> &Ast {
+        &self.ast
+    }
+
+    pub fn into_ast(self) -> Ast {
+        self.ast
+    }
+
+    /// Parses a whole program: a list of declarations up to the end of input.
+    pub fn parse(&mut self) -> Result<Vec<StmtId>, ParseError> {
+        let mut statements = Vec::new();
+        while !self.is_next(&[TokenType::Eof]) {
+            statements.push(self.declaration()?);
+        }
+        Ok(statements)
     }
 
-    pub fn parse(&mut self) -> Box<Expr> {
-        return self.expression();
+    /// Parses input consisting of a single expression, as typed at the REPL.
+    pub fn parse_expression(&mut self) -> ParseResult {
+        let expr = self.expression()?;
+        self.consume(TokenType::Eof, "Expect end of expression")?;
+        Ok(expr)
     }
 
     fn is_next(&self, expected: &[TokenType]) -> bool {
@@ -23,114 +220,1059 @@ impl Parser {
                 return true;
             }
         }
-        return false;
+        false
     }
 
-    fn expression(&mut self) -> Box<Expr> {
-        return self.equality();
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.current].clone();
+        if token.token_type != TokenType::Eof {
+            self.current += 1;
+        }
+        token
     }
 
-    fn equality(&mut self) -> Box<Expr> {
-        let expr = self.comparison();
-        if self.is_next(&[TokenType::BangEqual, TokenType::EqualEqual]) {
-            let op = self.tokens[self.current].clone();
-            self.current += 1;
-            let right = self.comparison();
-            let expr = Box::new(Expr::Binary(expr, op, right));
-            return expr;
-        }
-        return expr;
-    }
-
-    fn comparison(&mut self) -> Box<Expr> {
-        let expr = self.term();
-        if self.is_next(&[
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::Less,
-            TokenType::LessEqual,
-        ]) {
-            let op = self.tokens[self.current].clone();
-            self.current += 1;
-            let right = self.term();
-            let expr = Box::new(Expr::Binary(expr, op, right));
-            return expr;
+    fn consume(&mut self, expected: TokenType, message: &str) -> Result<Token, ParseError> {
+        if self.is_next(&[expected]) {
+            return Ok(self.advance());
         }
-        return expr;
+        Err(self.error(&self.tokens[self.current], message))
     }
 
-    fn term(&mut self) -> Box<Expr> {
-        let expr = self.factor();
-        if self.is_next(&[TokenType::Minus, TokenType::Plus]) {
-            let op = self.tokens[self.current].clone();
-            self.current += 1;
-            let right = self.factor();
-            let expr = Box::new(Expr::Binary(expr, op, right));
-            return expr;
+    /// Runs `parse` one level of nesting deeper, unless that's too deep.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == self.max_depth {
+            let token = self.tokens[self.current].clone();
+            return Err(self.error(&token, "Too much nesting"));
         }
-        return expr;
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
 
-    fn factor(&mut self) -> Box<Expr> {
-        let expr = self.unary();
-        if self.is_next(&[TokenType::Slash, TokenType::Star]) {
-            let op = self.tokens[self.current].clone();
-            self.current += 1;
-            let right = self.unary();
-            let expr = Box::new(Expr::Binary(expr, op, right));
-            return expr;
+    /// An error at `token`. Whatever was expected there, an unterminated string
+    /// is the problem to report.
+    fn error(&self, token: &Token, message: &str) -> ParseError {
+        let message = match token.token_type {
+            TokenType::UnterminatedString => "Unterminated string",
+            _ => message,
+        };
+        let location = if token.token_type == TokenType::Eof {
+            String::from(" at end")
+        } else {
+            format!(" at '{}'", token.lexeme)
+        };
+        ParseError {
+            line: token.line,
+            message: format!("{message}{location}"),
         }
-        return expr;
     }
 
-    fn unary(&mut self) -> Box<Expr> {
-        if self.is_next(&[TokenType::Bang, TokenType::Minus]) {
-            let op = self.tokens[self.current].clone();
-            self.current += 1;
-            let right = self.unary();
-            return Box::new(Expr::Unary(op, right));
-        }
-        return self.primary();
-    }
-
-    fn primary(&mut self) -> Box<Expr> {
-        match self.tokens[self.current].token_type {
-            TokenType::False => {
-                self.current += 1;
-                return Box::new(Expr::Literal(Literal::Boolean(false)));
-            }
-            TokenType::True => {
-                self.current += 1;
-                return Box::new(Expr::Literal(Literal::Boolean(true)));
-            }
-            TokenType::Nil => {
-                self.current += 1;
-                return Box::new(Expr::Literal(Literal::Nil));
-            }
-            TokenType::Number => {
-                self.current += 1;
-                return Box::new(Expr::Literal(
-                    self.tokens[self.current-1].literal.clone().unwrap(),
-                ));
-            }
-            TokenType::StringLiteral => {
-                self.current += 1;
-                return Box::new(Expr::Literal(
-                    self.tokens[self.current-1].literal.clone().unwrap(),
-                ));
-            }
-            TokenType::LeftParen => {
-                self.current += 1;
-                let expr = self.expression();
-                if self.tokens[self.current].token_type != TokenType::RightParen {
-                    println!("Parser.primary(), expected closing paren )");
+    fn declaration(&mut self) -> StmtResult {
+        if self.is_next(&[TokenType::Var]) {
+            self.advance();
+            return self.var_declaration();
+        }
+        // `fun (` starts a lambda, which is parsed as an expression statement.
+        if self.is_next(&[TokenType::Fun])
+            && self.tokens[self.current + 1].token_type == TokenType::Identifier
+        {
+            self.advance();
+            let name = self.advance();
+            let declaration = self.function(Some(name))?;
+            return Ok(self.ast.add_stmt(Stmt::Function(Rc::new(declaration))));
+        }
+        self.statement()
+    }
+
+    /// Parses the parameter list and body following `fun name` or `fun`.
+    fn function(&mut self, name: Option<Token>) -> Result<FunctionDecl, ParseError> {
+        self.nested(|parser| parser.function_inner(name))
+    }
+
+    fn function_inner(&mut self, name: Option<Token>) -> Result<FunctionDecl, ParseError> {
+        let message = match name {
+            Some(_) => "Expect '(' after function name",
+            None => "Expect '(' after 'fun'",
+        };
+        self.consume(TokenType::LeftParen, message)?;
+        let (params, rest) = self.parameters()?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body")?;
+        let body = self.block()?;
+        Ok(FunctionDecl {
+            name,
+            params,
+            rest,
+            body,
+        })
+    }
+
+    /// Parses `a, b = 1, ...rest)`, the rest of a parameter list after its opening
+    /// parenthesis. Parameters with defaults come after those without, `...rest` last.
+    fn parameters(&mut self) -> Result<(Vec<Param>, Option<Token>), ParseError> {
+        let mut params: Vec<Param> = Vec::new();
+        let mut rest = None;
+        while !self.is_next(&[TokenType::RightParen]) {
+            if self.is_next(&[TokenType::DotDotDot]) {
+                self.advance();
+                rest =
+                    Some(self.consume(TokenType::Identifier, "Expect parameter name after '...'")?);
+                if self.is_next(&[TokenType::Comma]) {
+                    return Err(
+                        self.error(&self.tokens[self.current], "Rest parameter must be last")
+                    );
                 }
-                self.current += 1;
-                return Box::new(Expr::Grouping(expr));
+                break;
+            }
+            let name = self.consume(TokenType::Identifier, "Expect parameter name")?;
+            let mut default = None;
+            if self.is_next(&[TokenType::Equal]) {
+                self.advance();
+                default = Some(self.parse_precedence(Precedence::Assignment)?);
+            } else if params.last().is_some_and(|param| param.default.is_some()) {
+                return Err(self.error(&name, "Expect default value after a parameter with one"));
+            }
+            params.push(Param { name, default });
+            if !self.is_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.advance();
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters")?;
+        Ok((params, rest))
+    }
+
+    fn var_declaration(&mut self) -> StmtResult {
+        let name = self.consume(TokenType::Identifier, "Expect variable name")?;
+        let mut initializer = None;
+        if self.is_next(&[TokenType::Equal]) {
+            self.advance();
+            initializer = Some(self.expression()?);
+        }
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration",
+        )?;
+        Ok(self.ast.add_stmt(Stmt::Var(name, initializer)))
+    }
+
+    fn statement(&mut self) -> StmtResult {
+        self.nested(Parser::statement_inner)
+    }
+
+    fn statement_inner(&mut self) -> StmtResult {
+        let token = self.tokens[self.current].clone();
+        match token.token_type {
+            TokenType::Print => {
+                self.advance();
+                let value = self.expression()?;
+                self.consume(TokenType::Semicolon, "Expect ';' after value")?;
+                return Ok(self.ast.add_stmt(Stmt::Print(value)));
+            }
+            TokenType::LeftBrace => {
+                self.advance();
+                let statements = self.block()?;
+                return Ok(self.ast.add_stmt(Stmt::Block(statements)));
+            }
+            TokenType::If => {
+                self.advance();
+                return self.if_statement();
+            }
+            TokenType::While => {
+                let keyword = self.advance();
+                return self.while_statement(keyword, None);
+            }
+            TokenType::For => {
+                let keyword = self.advance();
+                return self.for_statement(keyword, None);
+            }
+            TokenType::Break | TokenType::Continue => {
+                self.advance();
+                let mut label = None;
+                if self.is_next(&[TokenType::Identifier]) {
+                    label = Some(self.advance());
+                }
+                self.consume(
+                    TokenType::Semicolon,
+                    &format!("Expect ';' after '{}'", token.lexeme),
+                )?;
+                if token.token_type == TokenType::Break {
+                    return Ok(self.ast.add_stmt(Stmt::Break(token, label)));
+                }
+                return Ok(self.ast.add_stmt(Stmt::Continue(token, label)));
+            }
+            TokenType::Return => {
+                self.advance();
+                let mut value = None;
+                if !self.is_next(&[TokenType::Semicolon]) {
+                    value = Some(self.expression()?);
+                }
+                self.consume(TokenType::Semicolon, "Expect ';' after return value")?;
+                return Ok(self.ast.add_stmt(Stmt::Return(token, value)));
+            }
+            TokenType::Throw => {
+                self.advance();
+                let value = self.expression()?;
+                self.consume(TokenType::Semicolon, "Expect ';' after thrown value")?;
+                return Ok(self.ast.add_stmt(Stmt::Throw(token, value)));
+            }
+            TokenType::Try => {
+                self.advance();
+                return self.try_statement();
+            }
+            TokenType::Identifier
+                if self.tokens[self.current + 1].token_type == TokenType::Colon =>
+            {
+                self.advance();
+                self.advance();
+                return self.labeled_statement(token);
+            }
+            _ => {}
+        }
+        let expr = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression")?;
+        Ok(self.ast.add_stmt(Stmt::Expression(expr)))
+    }
+
+    fn labeled_statement(&mut self, label: Token) -> StmtResult {
+        if self.is_next(&[TokenType::While]) {
+            let keyword = self.advance();
+            return self.while_statement(keyword, Some(label));
+        }
+        if self.is_next(&[TokenType::For]) {
+            let keyword = self.advance();
+            return self.for_statement(keyword, Some(label));
+        }
+        Err(self.error(&label, "Only loops can be labeled"))
+    }
+
+    fn block(&mut self) -> Result<Vec<StmtId>, ParseError> {
+        let mut statements = Vec::new();
+        while !self.is_next(&[TokenType::RightBrace, TokenType::Eof]) {
+            statements.push(self.declaration()?);
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block")?;
+        Ok(statements)
+    }
+
+    fn try_statement(&mut self) -> StmtResult {
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'")?;
+        let body = self.block()?;
+        let mut catch = None;
+        if self.is_next(&[TokenType::Catch]) {
+            self.advance();
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'")?;
+            let name = self.consume(TokenType::Identifier, "Expect error variable name")?;
+            self.consume(TokenType::RightParen, "Expect ')' after error variable")?;
+            self.consume(TokenType::LeftBrace, "Expect '{' after catch clause")?;
+            catch = Some((name, self.block()?));
+        }
+        let mut finally = None;
+        if self.is_next(&[TokenType::Finally]) {
+            self.advance();
+            self.consume(TokenType::LeftBrace, "Expect '{' after 'finally'")?;
+            finally = Some(self.block()?);
+        }
+        if catch.is_none() && finally.is_none() {
+            let token = self.tokens[self.current].clone();
+            return Err(self.error(&token, "Expect 'catch' or 'finally' after try block"));
+        }
+        Ok(self.ast.add_stmt(Stmt::Try(body, catch, finally)))
+    }
+
+    fn if_statement(&mut self) -> StmtResult {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition")?;
+        let then_branch = self.statement()?;
+        let mut else_branch = None;
+        if self.is_next(&[TokenType::Else]) {
+            self.advance();
+            else_branch = Some(self.statement()?);
+        }
+        Ok(self
+            .ast
+            .add_stmt(Stmt::If(condition, then_branch, else_branch)))
+    }
+
+    fn while_statement(&mut self, keyword: Token, label: Option<Token>) -> StmtResult {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition")?;
+        let body = self.statement()?;
+        Ok(self
+            .ast
+            .add_stmt(Stmt::While(keyword, label, condition, body, None)))
+    }
+
+    /// Desugars `for (init; condition; increment) body` into a block holding the
+    /// initializer and a `while` loop that carries the increment clause.
+    fn for_statement(&mut self, keyword: Token, label: Option<Token>) -> StmtResult {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'")?;
+        if self.is_for_in() {
+            return self.for_in_statement(label);
+        }
+        let initializer = match self.tokens[self.current].token_type {
+            TokenType::Semicolon => {
+                self.advance();
+                None
+            }
+            TokenType::Var => {
+                self.advance();
+                Some(self.var_declaration()?)
             }
             _ => {
-                panic!("Error in primary: Have yet to support this");
+                let expr = self.expression()?;
+                self.consume(TokenType::Semicolon, "Expect ';' after loop initializer")?;
+                Some(self.ast.add_stmt(Stmt::Expression(expr)))
             }
+        };
+
+        let condition = if self.is_next(&[TokenType::Semicolon]) {
+            self.ast.add_expr(Expr::Literal(Literal::Boolean(true)))
+        } else {
+            self.expression()?
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition")?;
+
+        let mut increment = None;
+        if !self.is_next(&[TokenType::RightParen]) {
+            increment = Some(self.expression()?);
         }
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses")?;
+
+        let body = self.statement()?;
+        let mut statements: Vec<StmtId> = initializer.into_iter().collect();
+        statements.push(
+            self.ast
+                .add_stmt(Stmt::While(keyword, label, condition, body, increment)),
+        );
+        Ok(self.ast.add_stmt(Stmt::Block(statements)))
+    }
+
+    /// Whether the clauses start with `x in` or `var x in`.
+    fn is_for_in(&self) -> bool {
+        let mut position = self.current;
+        if self.tokens[position].token_type == TokenType::Var {
+            position += 1;
+        }
+        self.tokens[position].token_type == TokenType::Identifier
+            && self.tokens.get(position + 1).map(|token| token.token_type) == Some(TokenType::In)
+    }
+
+    fn for_in_statement(&mut self, label: Option<Token>) -> StmtResult {
+        if self.is_next(&[TokenType::Var]) {
+            self.advance();
+        }
+        let name = self.advance();
+        self.advance(); // in
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after iterable")?;
+        let body = self.statement()?;
+        Ok(self.ast.add_stmt(Stmt::ForIn(label, name, iterable, body)))
+    }
+
+    fn expression(&mut self) -> ParseResult {
+        self.parse_precedence(Precedence::None.next())
+    }
+
+    /// Parses an expression whose operators all bind at least as tightly as `precedence`.
+    fn parse_precedence(&mut self, precedence: Precedence) -> ParseResult {
+        self.nested(|parser| parser.parse_precedence_inner(precedence))
+    }
+
+    fn parse_precedence_inner(&mut self, precedence: Precedence) -> ParseResult {
+        let token = self.advance();
+        let Some(prefix) = rule(token.token_type).prefix else {
+            return Err(self.error(&token, "Expect expression"));
+        };
+        let mut expr = prefix(self, token)?;
+
+        // Each operator applied puts the expression so far one level deeper, so
+        // long chains like `1 + 1 + ...` count as nesting too.
+        let depth = self.depth;
+        let result = loop {
+            let next = rule(self.tokens[self.current].token_type);
+            match next.infix {
+                Some(infix) if precedence <= next.precedence => {
+                    if self.depth == self.max_depth {
+                        let token = self.tokens[self.current].clone();
+                        break Err(self.error(&token, "Too much nesting"));
+                    }
+                    self.depth += 1;
+                    let op = self.advance();
+                    match infix(self, expr, op) {
+                        Ok(infix) => expr = infix,
+                        Err(error) => break Err(error),
+                    }
+                }
+                _ => break Ok(expr),
+            }
+        };
+        self.depth = depth;
+        result
+    }
+}
+
+// Parselets.
+impl Parser {
+    fn literal(&mut self, token: Token) -> ParseResult {
+        let literal = match token.token_type {
+            TokenType::False => Literal::Boolean(false),
+            TokenType::True => Literal::Boolean(true),
+            TokenType::Nil => Literal::Nil,
+            _ => token.literal.unwrap(),
+        };
+        Ok(self.ast.add_expr(Expr::Literal(literal)))
+    }
+
+    fn variable(&mut self, name: Token) -> ParseResult {
+        Ok(self.ast.add_expr(Expr::Variable(name)))
+    }
+
+    fn grouping(&mut self, _paren: Token) -> ParseResult {
+        if self.is_arrow_function() {
+            return self.arrow_function();
+        }
+        let expr = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after expression")?;
+        Ok(self.ast.add_expr(Expr::Grouping(expr)))
+    }
+
+    /// Whether the parenthesis just consumed is closed by one followed by `=>`, in
+    /// which case it opens the parameter list of an arrow function rather than a grouping.
+    fn is_arrow_function(&self) -> bool {
+        let mut depth = 1;
+        for (index, token) in self.tokens.iter().enumerate().skip(self.current) {
+            match token.token_type {
+                TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+                TokenType::Eof => return false,
+                _ => {}
+            }
+            if depth == 0 {
+                return self.tokens[index + 1].token_type == TokenType::Arrow;
+            }
+        }
+        false
+    }
+
+    /// `(a, b) => a + b` is short for `fun (a, b) { return a + b; }`.
+    fn arrow_function(&mut self) -> ParseResult {
+        let (params, rest) = self.parameters()?;
+        let arrow = self.advance();
+        let value = self.parse_precedence(Precedence::Assignment)?;
+        let declaration = FunctionDecl {
+            name: None,
+            params,
+            rest,
+            body: vec![self.ast.add_stmt(Stmt::Return(arrow, Some(value)))],
+        };
+        Ok(self.ast.add_expr(Expr::Lambda(Rc::new(declaration))))
+    }
+
+    fn list(&mut self, _bracket: Token) -> ParseResult {
+        let mut elements = Vec::new();
+        while !self.is_next(&[TokenType::RightBracket]) {
+            elements.push(self.parse_precedence(Precedence::Assignment)?);
+            if !self.is_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.advance();
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after list elements")?;
+        Ok(self.ast.add_expr(Expr::List(elements)))
+    }
+
+    /// A brace in expression position starts a map; at the start of a statement
+    /// it starts a block instead.
+    fn map(&mut self, brace: Token) -> ParseResult {
+        let mut entries = Vec::new();
+        while !self.is_next(&[TokenType::RightBrace]) {
+            let key = self.parse_precedence(Precedence::Assignment)?;
+            self.consume(TokenType::Colon, "Expect ':' after map key")?;
+            let value = self.parse_precedence(Precedence::Assignment)?;
+            entries.push((key, value));
+            if !self.is_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.advance();
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after map entries")?;
+        Ok(self.ast.add_expr(Expr::Map(brace, entries)))
+    }
+
+    fn lambda(&mut self, _fun: Token) -> ParseResult {
+        let declaration = self.function(None)?;
+        Ok(self.ast.add_expr(Expr::Lambda(Rc::new(declaration))))
+    }
+
+    fn unary(&mut self, op: Token) -> ParseResult {
+        let right = self.parse_precedence(Precedence::Unary)?;
+        Ok(self.ast.add_expr(Expr::Unary(op, right)))
+    }
+
+    /// Parses the operand to the right of an infix operator, honouring its associativity.
+    fn right_operand(&mut self, op: &Token) -> ParseResult {
+        let rule = rule(op.token_type);
+        match rule.associativity {
+            Associativity::Left => self.parse_precedence(rule.precedence.next()),
+            Associativity::Right => self.parse_precedence(rule.precedence),
+        }
+    }
+
+    fn binary(&mut self, left: ExprId, op: Token) -> ParseResult {
+        let right = self.right_operand(&op)?;
+        Ok(self.ast.add_expr(Expr::Binary(left, op, right)))
+    }
+
+    fn logical(&mut self, left: ExprId, op: Token) -> ParseResult {
+        let right = self.right_operand(&op)?;
+        Ok(self.ast.add_expr(Expr::Logical(left, op, right)))
+    }
+
+    fn ternary(&mut self, condition: ExprId, question: Token) -> ParseResult {
+        let then_branch = self.expression()?;
+        self.consume(
+            TokenType::Colon,
+            "Expect ':' after then branch of conditional expression",
+        )?;
+        let else_branch = self.right_operand(&question)?;
+        Ok(self
+            .ast
+            .add_expr(Expr::Ternary(condition, then_branch, else_branch)))
+    }
+
+    /// The target, already parsed as an expression, is left out of the tree.
+    fn assignment(&mut self, target: ExprId, equal: Token) -> ParseResult {
+        let value = self.right_operand(&equal)?;
+        let assignment = match &self.ast[target] {
+            Expr::Variable(name) => Expr::Assign(name.clone(), value),
+            Expr::Index(object, bracket, index) => {
+                Expr::SetIndex(*object, bracket.clone(), *index, value)
+            }
+            Expr::Get(object, name) => Expr::Set(*object, name.clone(), value),
+            _ => return Err(self.error(&equal, "Invalid assignment target")),
+        };
+        Ok(self.ast.add_expr(assignment))
+    }
+
+    fn compound_assignment(&mut self, target: ExprId, op: Token) -> ParseResult {
+        if !self.ast[target].is_assignable() {
+            return Err(self.error(&op, "Invalid assignment target"));
+        }
+        let value = self.right_operand(&op)?;
+        Ok(self.ast.add_expr(Expr::CompoundAssign(target, op, value)))
+    }
+
+    fn prefix_increment(&mut self, op: Token) -> ParseResult {
+        let target = self.parse_precedence(Precedence::Unary)?;
+        if !self.ast[target].is_assignable() {
+            return Err(self.error(&op, "Invalid increment target"));
+        }
+        Ok(self.ast.add_expr(Expr::Increment(target, op, true)))
+    }
+
+    fn postfix_increment(&mut self, target: ExprId, op: Token) -> ParseResult {
+        if !self.ast[target].is_assignable() {
+            return Err(self.error(&op, "Invalid increment target"));
+        }
+        Ok(self.ast.add_expr(Expr::Increment(target, op, false)))
+    }
+
+    /// Arguments bind like assignments, so that the commas between them aren't
+    /// parsed as comma operators. Named arguments (`name: value`) follow positional ones.
+    fn call(&mut self, callee: ExprId, _paren: Token) -> ParseResult {
+        let mut arguments = Vec::new();
+        let mut named = Vec::new();
+        while !self.is_next(&[TokenType::RightParen]) {
+            if self.is_next(&[TokenType::Identifier])
+                && self.tokens[self.current + 1].token_type == TokenType::Colon
+            {
+                let name = self.advance();
+                self.advance();
+                named.push((name, self.parse_precedence(Precedence::Assignment)?));
+            } else if !named.is_empty() {
+                let token = &self.tokens[self.current];
+                return Err(self.error(token, "Positional argument can't follow named arguments"));
+            } else {
+                arguments.push(self.parse_precedence(Precedence::Assignment)?);
+            }
+            if !self.is_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.advance();
+        }
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments")?;
+        Ok(self
+            .ast
+            .add_expr(Expr::Call(callee, paren, arguments, named)))
+    }
+
+    /// Parses `object[index]` or the slice `object[start:end]`.
+    fn index(&mut self, object: ExprId, bracket: Token) -> ParseResult {
+        let mut start = None;
+        if !self.is_next(&[TokenType::Colon]) {
+            let index = self.expression()?;
+            if !self.is_next(&[TokenType::Colon]) {
+                self.consume(TokenType::RightBracket, "Expect ']' after index")?;
+                return Ok(self.ast.add_expr(Expr::Index(object, bracket, index)));
+            }
+            start = Some(index);
+        }
+        self.advance();
+        let mut end = None;
+        if !self.is_next(&[TokenType::RightBracket]) {
+            end = Some(self.expression()?);
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after slice")?;
+        Ok(self.ast.add_expr(Expr::Slice(object, bracket, start, end)))
+    }
+
+    fn property(&mut self, object: ExprId, _dot: Token) -> ParseResult {
+        let name = self.consume(TokenType::Identifier, "Expect property name after '.'")?;
+        Ok(self.ast.add_expr(Expr::Get(object, name)))
+    }
+
+    fn comma(&mut self, left: ExprId, op: Token) -> ParseResult {
+        let right = self.right_operand(&op)?;
+        Ok(self.ast.add_expr(Expr::Comma(left, right)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast_printer::AstPrinter, scanner::Scanner};
+
+    fn parse(source: &str) -> Result<String, ParseError> {
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+        let expr = parser.parse_expression()?;
+        Ok(AstPrinter::new(parser.ast()).print(expr))
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(parse("1 + 2 * 3").unwrap(), "(+ 1 (* 2 3))");
+        assert_eq!(
+            parse("-1 * 2 < 3 == true").unwrap(),
+            "(== (< (* (- 1) 2) 3) true)"
+        );
+        assert_eq!(parse("!(1 >= 2)").unwrap(), "(! (grouping (>= 1 2)))");
+    }
+
+    #[test]
+    fn test_left_associativity() {
+        assert_eq!(parse("1 - 2 - 3").unwrap(), "(- (- 1 2) 3)");
+        assert_eq!(parse("8 / 4 / 2 * 3").unwrap(), "(* (/ (/ 8 4) 2) 3)");
+    }
+
+    #[test]
+    fn test_ternary() {
+        assert_eq!(
+            parse("true or false ? 1 : 2").unwrap(),
+            "(?: (or true false) 1 2)"
+        );
+        assert_eq!(
+            parse("true ? 1 : false ? 2 : 3").unwrap(),
+            "(?: true 1 (?: false 2 3))"
+        );
+        assert_eq!(parse("true ? 1, 2 : 3").unwrap(), "(?: true (, 1 2) 3)");
+        assert_eq!(
+            parse("true ? 1").unwrap_err().message,
+            "Expect ':' after then branch of conditional expression at end"
+        );
+    }
+
+    #[test]
+    fn test_logical_and_comma() {
+        assert_eq!(parse("1 or 2 and 3").unwrap(), "(or 1 (and 2 3))");
+        assert_eq!(parse("1, 2 == 2, 3").unwrap(), "(, (, 1 (== 2 2)) 3)");
+        assert_eq!(parse("nil ? 1 : 2, 3").unwrap(), "(, (?: nil 1 2) 3)");
+    }
+
+    #[test]
+    fn test_arithmetic_and_bitwise_precedence() {
+        assert_eq!(parse("-2 ** 2").unwrap(), "(- (** 2 2))");
+        assert_eq!(parse("2 ** -1 ** 2").unwrap(), "(** 2 (- (** 1 2)))");
+        assert_eq!(parse("2 ** 3 ** 2").unwrap(), "(** 2 (** 3 2))");
+        assert_eq!(parse("1 + 2 % 3 ~/ 4").unwrap(), "(+ 1 (~/ (% 2 3) 4))");
+        assert_eq!(
+            parse("1 | 2 ^ 3 & 4 << 5 + 6").unwrap(),
+            "(| 1 (^ 2 (& 3 (<< 4 (+ 5 6)))))"
+        );
+        assert_eq!(parse("~1 & 1 == 0").unwrap(), "(== (& (~ 1) 1) 0)");
+    }
+
+    #[test]
+    fn test_assignment() {
+        assert_eq!(parse("a = b = 1").unwrap(), "(= a (= b 1))");
+        assert_eq!(parse("a += b -= 2 * 3").unwrap(), "(+= a (-= b (* 2 3)))");
+        assert_eq!(parse("a = 1, b %= 2").unwrap(), "(, (= a 1) (%= b 2))");
+        assert_eq!(parse("a = true ? 1 : 2").unwrap(), "(= a (?: true 1 2))");
+        assert_eq!(parse("-a++ + --b").unwrap(), "(+ (- (post++ a)) (-- b))");
+    }
+
+    #[test]
+    fn test_invalid_assignment_targets() {
+        assert_eq!(
+            parse("1 += 2").unwrap_err().message,
+            "Invalid assignment target at '+='"
+        );
+        assert_eq!(
+            parse("a + b = 2").unwrap_err().message,
+            "Invalid assignment target at '='"
+        );
+        assert_eq!(
+            parse("(a) *= 2").unwrap_err().message,
+            "Invalid assignment target at '*='"
+        );
+        assert_eq!(
+            parse("++1").unwrap_err().message,
+            "Invalid increment target at '++'"
+        );
+        assert_eq!(
+            parse("a++--").unwrap_err().message,
+            "Invalid increment target at '--'"
+        );
+        assert_eq!(
+            parse("++a++").unwrap_err().message,
+            "Invalid increment target at '++'"
+        );
+    }
+
+    #[test]
+    fn test_statements() {
+        let mut parser = Parser::new(Scanner::new("var a = 1; print a; a += 2;").scan_tokens());
+        let statements = parser.parse().unwrap();
+        assert_eq!(statements.len(), 3);
+        assert!(matches!(parser.ast()[statements[0]], Stmt::Var(_, Some(_))));
+
+        let mut parser = Parser::new(Scanner::new("var a = 1").scan_tokens());
+        assert_eq!(
+            parser.parse().unwrap_err().message,
+            "Expect ';' after variable declaration at end"
+        );
+    }
+
+    fn parse_program(source: &str) -> Result<String, ParseError> {
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+        let statements = parser.parse()?;
+        let printer = AstPrinter::new(parser.ast());
+        let printed: Vec<String> = statements
+            .iter()
+            .map(|stmt| printer.print_stmt(*stmt))
+            .collect();
+        Ok(printed.join(" "))
+    }
+
+    #[test]
+    fn test_control_flow() {
+        assert_eq!(
+            parse_program("if (a) print 1; else { print 2; }").unwrap(),
+            "(if a (print 1) (block (print 2)))"
+        );
+        assert_eq!(
+            parse_program("for (var i = 0; i < 3; i++) continue;").unwrap(),
+            "(block (var i 0) (while (< i 3) (continue) (post++ i)))"
+        );
+        assert_eq!(
+            parse_program("for (;;) break;").unwrap(),
+            "(block (while true (break)))"
+        );
+        assert_eq!(
+            parse_program("outer: while (true) { inner: for (;;) break outer; }").unwrap(),
+            "(while outer: true (block (block (while inner: true (break outer)))))"
+        );
+    }
+
+    #[test]
+    fn test_control_flow_errors() {
+        assert_eq!(
+            parse_program("label: print 1;").unwrap_err().message,
+            "Only loops can be labeled at 'label'"
+        );
+        assert_eq!(
+            parse_program("while (true) break").unwrap_err().message,
+            "Expect ';' after 'break' at end"
+        );
+        assert_eq!(
+            parse_program("{ print 1;").unwrap_err().message,
+            "Expect '}' after block at end"
+        );
+    }
+
+    #[test]
+    fn test_calls_and_lambdas() {
+        assert_eq!(parse("f(1, 2)(3)").unwrap(), "(call (call f 1 2) 3)");
+        assert_eq!(parse("-f() ** 2").unwrap(), "(- (** (call f) 2))");
+        assert_eq!(parse("f((1, 2))").unwrap(), "(call f (grouping (, 1 2)))");
+        assert_eq!(
+            parse("(a, b) => a + b").unwrap(),
+            "(fun (a b) (return (+ a b)))"
+        );
+        assert_eq!(
+            parse("() => x = 1, 2").unwrap(),
+            "(, (fun () (return (= x 1))) 2)"
+        );
+        assert_eq!(parse("(a) + 1").unwrap(), "(+ (grouping a) 1)");
+        // `print` starts a statement, anywhere else it names the built-in function.
+        assert_eq!(
+            parse_program("print(1); xs.map(print);").unwrap(),
+            "(print (grouping 1)) (; (call (. xs map) print))"
+        );
+        assert_eq!(
+            parse("map(xs, fun (x) { return x * 2; })").unwrap(),
+            "(call map xs (fun (x) (return (* x 2))))"
+        );
+        assert_eq!(
+            parse_program("fun add(a, b) { return a + b; } fun () {};").unwrap(),
+            "(fun add (a b) (return (+ a b))) (; (fun ()))"
+        );
+    }
+
+    #[test]
+    fn test_parameters_and_named_arguments() {
+        assert_eq!(
+            parse("fun (a, b = a * 2, ...rest) {}").unwrap(),
+            "(fun (a b=(* a 2) ...rest))"
+        );
+        assert_eq!(
+            parse("(x = (1), ...xs) => xs").unwrap(),
+            "(fun (x=(grouping 1) ...xs) (return xs))"
+        );
+        assert_eq!(
+            parse("f(1, c ? d : e, sep: \", \", end: 2)").unwrap(),
+            "(call f 1 (?: c d e) sep: \", \" end: 2)"
+        );
+        assert_eq!(
+            parse("f(a: 1, 2)").unwrap_err().message,
+            "Positional argument can't follow named arguments at '2'"
+        );
+        assert_eq!(
+            parse("fun (...rest, a) {}").unwrap_err().message,
+            "Rest parameter must be last at ','"
+        );
+        assert_eq!(
+            parse("fun (a = 1, b) {}").unwrap_err().message,
+            "Expect default value after a parameter with one at 'b'"
+        );
+        assert_eq!(
+            parse("fun (...) {}").unwrap_err().message,
+            "Expect parameter name after '...' at ')'"
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(parse("[1, [2], ]").unwrap(), "(list 1 (list 2))");
+        assert_eq!(
+            parse("xs[i + 1][-1]").unwrap(),
+            "(index (index xs (+ i 1)) (- 1))"
+        );
+        assert_eq!(parse("xs[1:]").unwrap(), "(slice xs 1 _)");
+        assert_eq!(parse("xs[:-1]").unwrap(), "(slice xs _ (- 1))");
+        assert_eq!(parse("xs[:]").unwrap(), "(slice xs _ _)");
+        assert_eq!(parse("xs[c ? 1 : 2]").unwrap(), "(index xs (?: c 1 2))");
+        assert_eq!(
+            parse("xs[0] = ys[1] = 2").unwrap(),
+            "(= (index xs 0) (= (index ys 1) 2))"
+        );
+        assert_eq!(parse("xs[0] += 1").unwrap(), "(+= (index xs 0) 1)");
+        assert_eq!(parse("-xs.pop()").unwrap(), "(- (call (. xs pop)))");
+        assert_eq!(
+            parse("xs[0:1] = 2").unwrap_err().message,
+            "Invalid assignment target at '='"
+        );
+        assert_eq!(parse("p.x = p.y = 2").unwrap(), "(= (. p x) (= (. p y) 2))");
+        assert_eq!(parse("p.x += 1").unwrap(), "(+= (. p x) 1)");
+        assert_eq!(parse("a.b.c++").unwrap(), "(post++ (. (. a b) c))");
+        assert_eq!(
+            parse("p.f() = 2").unwrap_err().message,
+            "Invalid assignment target at '='"
+        );
+        assert_eq!(
+            parse("xs[1").unwrap_err().message,
+            "Expect ']' after index at end"
+        );
+        assert_eq!(
+            parse("[1 2]").unwrap_err().message,
+            "Expect ']' after list elements at '2'"
+        );
+        assert_eq!(
+            parse("xs.1").unwrap_err().message,
+            "Expect property name after '.' at '1'"
+        );
+    }
+
+    #[test]
+    fn test_maps() {
+        assert_eq!(
+            parse("{\"a\": 1, 2: {}, nil: [],}").unwrap(),
+            "(map (\"a\" 1) (2 (map)) (nil (list)))"
+        );
+        assert_eq!(
+            parse("m[\"a\"] = {x ? 1 : 2: 3}").unwrap(),
+            "(= (index m \"a\") (map ((?: x 1 2) 3)))"
+        );
+        assert_eq!(parse("() => {}").unwrap(), "(fun () (return (map)))");
+        // At the start of a statement a brace opens a block.
+        assert_eq!(
+            parse_program("{} var m = {}; ({1: 2});").unwrap(),
+            "(block) (var m (map)) (; (grouping (map (1 2))))"
+        );
+        assert_eq!(
+            parse("{1}").unwrap_err().message,
+            "Expect ':' after map key at '}'"
+        );
+        assert_eq!(
+            parse("{1: 2 3: 4}").unwrap_err().message,
+            "Expect '}' after map entries at '3'"
+        );
+    }
+
+    #[test]
+    fn test_for_in_and_ranges() {
+        assert_eq!(parse("0..n + 1").unwrap(), "(.. 0 (+ n 1))");
+        assert_eq!(parse("a..=b == r").unwrap(), "(== (..= a b) r)");
+        assert_eq!(
+            parse_program("for (x in xs) print x; l: for (var c in \"ab\") {}").unwrap(),
+            "(for x xs (print x)) (for l: c \"ab\" (block))"
+        );
+        // Without `in` it's still a C-style loop.
+        assert_eq!(
+            parse_program("for (x; x; x) {}").unwrap(),
+            "(block (; x) (while x (block) x))"
+        );
+        assert_eq!(
+            parse_program("for (x in xs print x;").unwrap_err().message,
+            "Expect ')' after iterable at 'print'"
+        );
+    }
+
+    #[test]
+    fn test_exceptions() {
+        assert_eq!(
+            parse_program("try { throw \"a\"; } catch (e) { print e; }").unwrap(),
+            "(try (block (throw \"a\")) (catch e (block (print e))))"
+        );
+        assert_eq!(
+            parse_program("try {} finally { f(); }").unwrap(),
+            "(try (block) (finally (block (; (call f)))))"
+        );
+        assert_eq!(
+            parse_program("try {} catch (e) {} finally {}").unwrap(),
+            "(try (block) (catch e (block)) (finally (block)))"
+        );
+        assert_eq!(
+            parse_program("try {} print 1;").unwrap_err().message,
+            "Expect 'catch' or 'finally' after try block at 'print'"
+        );
+        assert_eq!(
+            parse_program("try {} catch {}").unwrap_err().message,
+            "Expect '(' after 'catch' at '{'"
+        );
+        assert_eq!(
+            parse_program("throw;").unwrap_err().message,
+            "Expect expression at ';'"
+        );
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |open: &str, close: &str, depth: usize| {
+            format!("{}1{}", open.repeat(depth), close.repeat(depth))
+        };
+        let mut parser = Parser::new(Scanner::new("(((((1)))))").scan_tokens());
+        parser.set_max_depth(6);
+        assert!(parser.parse_expression().is_ok());
+        let mut parser = Parser::new(Scanner::new("((((((1))))))").scan_tokens());
+        parser.set_max_depth(6);
+        assert_eq!(
+            parser.parse_expression().unwrap_err().message,
+            "Too much nesting at '1'"
+        );
+
+        let source = nested("!", "", DEFAULT_MAX_DEPTH);
+        assert_eq!(
+            parse(&source).unwrap_err().message,
+            "Too much nesting at '1'"
+        );
+        let source = format!("{};", nested("{", "}", DEFAULT_MAX_DEPTH));
+        assert_eq!(
+            parse_program(&source).unwrap_err().message,
+            "Too much nesting at '1'"
+        );
+        let depth = DEFAULT_MAX_DEPTH + 1;
+        let source = "fun f() { ".repeat(depth) + &"}".repeat(depth);
+        assert_eq!(
+            parse_program(&source).unwrap_err().message,
+            "Too much nesting at '('"
+        );
+
+        // Chains of operators nest their left operands.
+        let mut parser = Parser::new(Scanner::new("1 + 2 + 3 + 4").scan_tokens());
+        parser.set_max_depth(5);
+        assert!(parser.parse_expression().is_ok());
+        let mut parser = Parser::new(Scanner::new("1 + 2 + 3 + 4 + 5").scan_tokens());
+        parser.set_max_depth(5);
+        assert_eq!(
+            parser.parse_expression().unwrap_err().message,
+            "Too much nesting at '5'"
+        );
+        let source = format!("f{}", "()".repeat(DEFAULT_MAX_DEPTH));
+        assert_eq!(
+            parse(&source).unwrap_err().message,
+            "Too much nesting at '('"
+        );
+    }
+
+    #[test]
+    fn test_function_errors() {
+        assert_eq!(
+            parse("f(1, 2").unwrap_err().message,
+            "Expect ')' after arguments at end"
+        );
+        assert_eq!(
+            parse("fun (a, 1) {}").unwrap_err().message,
+            "Expect parameter name at '1'"
+        );
+        assert_eq!(
+            parse("fun (a) a").unwrap_err().message,
+            "Expect '{' before function body at 'a'"
+        );
+        assert_eq!(
+            parse("(a, b) =>").unwrap_err().message,
+            "Expect expression at end"
+        );
+        assert_eq!(
+            parse_program("var s = 1 \"abc\ndef;")
+                .unwrap_err()
+                .to_string(),
+            "[line 2] Error: Unterminated string at '\"abc\ndef;'"
+        );
+        assert_eq!(
+            parse_program("fun f(a { }").unwrap_err().message,
+            "Expect ')' after parameters at '{'"
+        );
+        assert_eq!(
+            parse_program("return 1").unwrap_err().message,
+            "Expect ';' after return value at end"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse("(1 + 2").unwrap_err().message,
+            "Expect ')' after expression at end"
+        );
+        assert_eq!(
+            parse("1 + *").unwrap_err().message,
+            "Expect expression at '*'"
+        );
     }
 }
@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::{
+    ast::{Ast, ExprId, SideTable, StmtId},
+    expression::Expr,
+    statement::FunctionDecl,
+    token::Token,
+    walk::{self, Walker},
+};
+
+/// How many scopes out from each variable or assignment expression its variable
+/// is declared. `None` for globals, which are looked up by name when the code
+/// runs, and for all other expressions.
+pub type Locals = SideTable<ExprId, Option<usize>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError {
+    pub line: u32,
+    pub message: String,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)
+    }
+}
+
+/// Static analysis that runs between parsing and interpreting. Finds the scope
+/// each local variable is declared in, so that closures see the variables around
+/// their definition rather than ones declared after it, and verifies that `break`
+/// and `continue` appear inside a (matching) loop and `return` inside a function.
+pub struct Resolver<'a> {
+    ast: &'a Ast,
+    /// The block and function scopes around the node being resolved, innermost
+    /// last, with whether each variable's initializer has been resolved yet.
+    scopes: Vec<HashMap<String, bool>>,
+    locals: Locals,
+    /// One entry per enclosing loop of the innermost function, innermost last,
+    /// holding the loop's label.
+    loops: Vec<Option<String>>,
+    in_function: bool,
+    errors: Vec<ResolveError>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
+        Resolver {
+            ast,
+            scopes: Vec::new(),
+            locals: ast.expr_table(None),
+            loops: Vec::new(),
+            in_function: false,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, statements: &[StmtId]) -> Result<Locals, Vec<ResolveError>> {
+        self.walk_stmts(statements);
+        self.finish()
+    }
+
+    /// Resolves a bare expression, such as one typed at the REPL.
+    pub fn resolve_expression(&mut self, expr: ExprId) -> Result<Locals, Vec<ResolveError>> {
+        self.walk_expr(expr);
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<Locals, Vec<ResolveError>> {
+        let errors = std::mem::take(&mut self.errors);
+        if errors.is_empty() {
+            return Ok(std::mem::replace(
+                &mut self.locals,
+                self.ast.expr_table(None),
+            ));
+        }
+        Err(errors)
+    }
+
+    /// Runs `resolve` in a new innermost scope.
+    fn in_scope(&mut self, resolve: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        resolve(self);
+        self.scopes.pop();
+    }
+
+    /// Adds a variable to the innermost scope, or leaves it to the globals at the
+    /// top level. It can't be read until `define` is called for it.
+    fn declare(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.to_string(), false);
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.to_string(), true);
+        }
+    }
+
+    /// Records how many scopes out `name`, used by `expr`, is declared, if it is local.
+    fn resolve_local(&mut self, expr: ExprId, name: &Token) {
+        self.locals[expr] = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&*name.lexeme));
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ResolveError {
+            line: token.line,
+            message: format!("{message} at '{}'", token.lexeme),
+        });
+    }
+
+    /// Function bodies start afresh: loops around the function can't be jumped to
+    /// from inside it. Parameters share a scope with the body, and each default
+    /// value sees the parameters before it, as when arguments are bound.
+    fn resolve_function(&mut self, declaration: &FunctionDecl) {
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        let enclosing_function = std::mem::replace(&mut self.in_function, true);
+        self.in_scope(|resolver| {
+            for param in &declaration.params {
+                if let Some(default) = param.default {
+                    resolver.walk_expr(default);
+                }
+                resolver.define(&param.name);
+            }
+            if let Some(rest) = &declaration.rest {
+                resolver.define(rest);
+            }
+            resolver.walk_stmts(&declaration.body);
+        });
+        self.in_function = enclosing_function;
+        self.loops = enclosing_loops;
+    }
+
+    /// Pushes a loop, which the caller pops after resolving its body.
+    fn enter_loop(&mut self, label: &Option<Token>) {
+        if let Some(label) = label {
+            let shadowed = self
+                .loops
+                .iter()
+                .any(|outer| outer.as_deref() == Some(&label.lexeme));
+            if shadowed {
+                self.error(label, "An enclosing loop already has this label");
+            }
+        }
+        let label = label.as_ref().map(|label| label.lexeme.to_string());
+        self.loops.push(label);
+    }
+
+    fn check_jump(&mut self, keyword: &Token, label: &Option<Token>) {
+        match label {
+            None if self.loops.is_empty() => self.error(
+                keyword,
+                &format!("Can't use '{}' outside of a loop", keyword.lexeme),
+            ),
+            Some(label)
+                if !self
+                    .loops
+                    .iter()
+                    .any(|name| name.as_deref() == Some(&label.lexeme)) =>
+            {
+                self.error(label, "No enclosing loop with this label")
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a> Walker<'a> for Resolver<'a> {
+    fn ast(&self) -> &'a Ast {
+        self.ast
+    }
+
+    fn walk_expr(&mut self, expr: ExprId) {
+        match &self.ast[expr] {
+            Expr::Variable(name) => {
+                let scope = self.scopes.last();
+                if scope.and_then(|scope| scope.get(&*name.lexeme)) == Some(&false) {
+                    self.error(name, "Can't read local variable in its own initializer");
+                }
+                self.resolve_local(expr, name);
+            }
+            Expr::Assign(name, value) => {
+                self.walk_expr(*value);
+                self.resolve_local(expr, name);
+            }
+            _ => walk::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_var_stmt(&mut self, name: &Token, initializer: Option<ExprId>) {
+        self.declare(name);
+        if let Some(initializer) = initializer {
+            self.walk_expr(initializer);
+        }
+        self.define(name);
+    }
+
+    fn visit_block_stmt(&mut self, statements: &[StmtId]) {
+        self.in_scope(|resolver| resolver.walk_stmts(statements));
+    }
+
+    fn visit_lambda(&mut self, declaration: &Rc<FunctionDecl>) {
+        self.resolve_function(declaration);
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: &Option<Token>,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+    ) {
+        self.walk_expr(condition);
+        self.enter_loop(label);
+        self.walk_stmt(body);
+        if let Some(increment) = increment {
+            self.walk_expr(increment);
+        }
+        self.loops.pop();
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        label: &Option<Token>,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) {
+        self.walk_expr(iterable);
+        self.enter_loop(label);
+        // Each iteration gets a scope of its own with the loop variable.
+        self.in_scope(|resolver| {
+            resolver.define(name);
+            resolver.walk_stmt(body);
+        });
+        self.loops.pop();
+    }
+
+    fn visit_break_stmt(&mut self, keyword: &Token, label: &Option<Token>) {
+        self.check_jump(keyword, label);
+    }
+
+    fn visit_continue_stmt(&mut self, keyword: &Token, label: &Option<Token>) {
+        self.check_jump(keyword, label);
+    }
+
+    /// The name is defined before the body is resolved, so the function can call itself.
+    fn visit_function_stmt(&mut self, declaration: &Rc<FunctionDecl>) {
+        if let Some(name) = &declaration.name {
+            self.define(name);
+        }
+        self.resolve_function(declaration);
+    }
+
+    fn visit_return_stmt(&mut self, keyword: &Token, value: Option<ExprId>) {
+        if !self.in_function {
+            self.error(keyword, "Can't return from top-level code");
+        }
+        if let Some(value) = value {
+            self.walk_expr(value);
+        }
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        body: &[StmtId],
+        catch: &Option<(Token, Vec<StmtId>)>,
+        finally: &Option<Vec<StmtId>>,
+    ) {
+        self.visit_block_stmt(body);
+        if let Some((name, handler)) = catch {
+            // The handler runs in the scope of the caught error's variable.
+            self.in_scope(|resolver| {
+                resolver.define(name);
+                resolver.walk_stmts(handler);
+            });
+        }
+        if let Some(finally) = finally {
+            self.visit_block_stmt(finally);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn resolve(source: &str) -> Vec<String> {
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+        let statements = parser.parse().unwrap();
+        match Resolver::new(parser.ast()).resolve(&statements) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+        }
+    }
+
+    /// Collects the variable and assignment expressions of a tree, in order.
+    struct Variables<'a> {
+        ast: &'a Ast,
+        found: Vec<(ExprId, String)>,
+    }
+
+    impl<'a> Walker<'a> for Variables<'a> {
+        fn ast(&self) -> &'a Ast {
+            self.ast
+        }
+
+        fn walk_expr(&mut self, expr: ExprId) {
+            if let Expr::Variable(name) | Expr::Assign(name, _) = &self.ast[expr] {
+                self.found.push((expr, name.lexeme.to_string()));
+            }
+            walk::walk_expr(self, expr);
+        }
+    }
+
+    /// The name and depth of each variable read or assigned in `source`, in order.
+    fn depths(source: &str) -> Vec<(String, Option<usize>)> {
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+        let statements = parser.parse().unwrap();
+        let ast = parser.ast();
+        let locals = Resolver::new(ast).resolve(&statements).unwrap();
+        let mut variables = Variables {
+            ast,
+            found: Vec::new(),
+        };
+        variables.walk_stmts(&statements);
+        variables
+            .found
+            .into_iter()
+            .map(|(expr, name)| (name, locals[expr]))
+            .collect()
+    }
+
+    #[test]
+    fn test_local_depths() {
+        let name = |name: &str, depth| (name.to_string(), depth);
+        assert_eq!(
+            depths("var a; { var b; fun f(c, d = c) { a; b = c; { d; } } }"),
+            [
+                name("c", Some(0)),
+                name("a", None),
+                name("b", Some(1)),
+                name("c", Some(0)),
+                name("d", Some(1))
+            ]
+        );
+        assert_eq!(
+            depths("{ fun f() { x; } var x; for (x in x) x; try {} catch (x) { x; } }"),
+            [
+                name("x", None),
+                name("x", Some(0)),
+                name("x", Some(0)),
+                name("x", Some(0))
+            ]
+        );
+        assert_eq!(
+            resolve("var a = a; { var b = 1; var b = b; var c = () => c; }"),
+            ["Can't read local variable in its own initializer at 'b'"]
+        );
+    }
+
+    #[test]
+    fn test_jumps_inside_loops() {
+        assert!(resolve("while (true) { if (true) break; else continue; }").is_empty());
+        assert!(resolve("a: for (;;) { b: while (true) { continue a; break b; } }").is_empty());
+        assert!(resolve("a: for (x in xs) for (y in x) if (y) continue a; else break;").is_empty());
+        assert_eq!(
+            resolve("a: for (x in xs) a: for (y in x) {}"),
+            ["An enclosing loop already has this label at 'a'"]
+        );
+    }
+
+    #[test]
+    fn test_jumps_outside_loops() {
+        assert_eq!(
+            resolve("break; { continue; }"),
+            [
+                "Can't use 'break' outside of a loop at 'break'",
+                "Can't use 'continue' outside of a loop at 'continue'"
+            ]
+        );
+        assert_eq!(
+            resolve("a: while (true) {} while (true) break a;"),
+            ["No enclosing loop with this label at 'a'"]
+        );
+        assert_eq!(
+            resolve("a: while (true) a: while (true) break a;"),
+            ["An enclosing loop already has this label at 'a'"]
+        );
+    }
+
+    #[test]
+    fn test_returns() {
+        assert!(resolve("fun f() { while (true) return 1; } var g = () => 2;").is_empty());
+        assert_eq!(
+            resolve("return 1; { return; }"),
+            [
+                "Can't return from top-level code at 'return'",
+                "Can't return from top-level code at 'return'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_bodies_reset_loops() {
+        assert_eq!(
+            resolve("a: while (true) { fun f() { break; } var g = fun () { continue a; }; }"),
+            [
+                "Can't use 'break' outside of a loop at 'break'",
+                "No enclosing loop with this label at 'a'"
+            ]
+        );
+        // Labels may be reused by loops inside a nested function.
+        assert!(resolve("a: while (true) print fun () { a: while (true) break a; };").is_empty());
+    }
+
+    #[test]
+    fn test_expressions() {
+        let mut parser = Parser::new(Scanner::new("(fun () { break; })()").scan_tokens());
+        let expr = parser.parse_expression().unwrap();
+        let errors = Resolver::new(parser.ast())
+            .resolve_expression(expr)
+            .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "Can't use 'break' outside of a loop at 'break'"
+        );
+    }
+}
@@ -1,168 +1,377 @@
-use crate::token::{Literal, Token, TokenType, KEYWORDS};
-use std::char;
+use std::rc::Rc;
 
+use crate::token::{Lexeme, Literal, Token, TokenType, Trivia, TriviaKind, KEYWORDS};
+
+/// Scans tokens lazily out of a shared copy of the source.
+/// `start` and `current` are byte offsets into `source`, so lexemes are spans of it.
 pub struct Scanner {
-    pub source: Vec<char>,
-    pub tokens: Vec<Token>,
+    pub source: Rc<str>,
     pub start: usize,
     pub current: usize,
     pub line: u32,
+    done: bool,
 }
 
 impl Scanner {
     pub fn new(source_code: &str) -> Scanner {
-        return Scanner {
-            source: source_code.chars().collect(),
-            tokens: Vec::<Token>::new(),
+        Scanner {
+            source: Rc::from(source_code),
             start: 0,
             current: 0,
             line: 1,
-        };
+            done: false,
+        }
     }
 }
 
 impl Scanner {
-    pub fn scan_tokens(&mut self) {
-        while self.current < self.source.len() {
-            self.scan_token();
-            self.start = self.current;
-        }
-        self.tokens
-            .push(Token::new(TokenType::Eof, String::new(), None, self.line));
+    pub fn scan_tokens(&mut self) -> Vec<Token> {
+        self.collect()
     }
 
-    fn scan_token(&mut self) {
-        let c = self.source[self.current];
-        self.current += 1;
+    /// Scans one lexeme starting at `start`: either a token, or the kind of trivia
+    /// (whitespace, comment, unrecognised character) that was skipped over.
+    fn scan_token(&mut self) -> Result<Token, TriviaKind> {
+        let c = self.advance();
         match c {
-            '(' => self.add_token(TokenType::LeftParen, None),
-            ')' => self.add_token(TokenType::RightParen, None),
-            '{' => self.add_token(TokenType::LeftBrace, None),
-            '}' => self.add_token(TokenType::RightBrace, None),
-            ',' => self.add_token(TokenType::Comma, None),
-            '.' => self.add_token(TokenType::Dot, None),
-            '+' => self.add_token(TokenType::Plus, None),
-            '-' => self.add_token(TokenType::Minus, None),
-            '*' => self.add_token(TokenType::Star, None),
-            ';' => self.add_token(TokenType::Semicolon, None),
+            '(' => self.make_token(TokenType::LeftParen, None),
+            ')' => self.make_token(TokenType::RightParen, None),
+            '{' => self.make_token(TokenType::LeftBrace, None),
+            '}' => self.make_token(TokenType::RightBrace, None),
+            '[' => self.make_token(TokenType::LeftBracket, None),
+            ']' => self.make_token(TokenType::RightBracket, None),
+            ',' => self.make_token(TokenType::Comma, None),
+            '.' => {
+                if self.peek() == Some('.') && self.peek_next() == Some('.') {
+                    self.current += 2;
+                    self.make_token(TokenType::DotDotDot, None)
+                } else if self.is_next('.') {
+                    if self.is_next('=') {
+                        self.make_token(TokenType::DotDotEqual, None)
+                    } else {
+                        self.make_token(TokenType::DotDot, None)
+                    }
+                } else {
+                    self.make_token(TokenType::Dot, None)
+                }
+            }
+            '+' => {
+                if self.is_next('+') {
+                    self.make_token(TokenType::PlusPlus, None)
+                } else if self.is_next('=') {
+                    self.make_token(TokenType::PlusEqual, None)
+                } else {
+                    self.make_token(TokenType::Plus, None)
+                }
+            }
+            '-' => {
+                if self.is_next('-') {
+                    self.make_token(TokenType::MinusMinus, None)
+                } else if self.is_next('=') {
+                    self.make_token(TokenType::MinusEqual, None)
+                } else {
+                    self.make_token(TokenType::Minus, None)
+                }
+            }
+            '*' => {
+                if self.is_next('*') {
+                    self.make_token(TokenType::StarStar, None)
+                } else if self.is_next('=') {
+                    self.make_token(TokenType::StarEqual, None)
+                } else {
+                    self.make_token(TokenType::Star, None)
+                }
+            }
+            '%' => {
+                if self.is_next('=') {
+                    self.make_token(TokenType::PercentEqual, None)
+                } else {
+                    self.make_token(TokenType::Percent, None)
+                }
+            }
+            '^' => self.make_token(TokenType::Caret, None),
+            '&' => self.make_token(TokenType::Ampersand, None),
+            '|' => self.make_token(TokenType::Pipe, None),
+            '~' => {
+                if self.is_next('/') {
+                    self.make_token(TokenType::TildeSlash, None)
+                } else {
+                    self.make_token(TokenType::Tilde, None)
+                }
+            }
+            ';' => self.make_token(TokenType::Semicolon, None),
+            '?' => self.make_token(TokenType::Question, None),
+            ':' => self.make_token(TokenType::Colon, None),
             '!' => {
                 if self.is_next('=') {
-                    self.add_token(TokenType::BangEqual, None);
+                    self.make_token(TokenType::BangEqual, None)
                 } else {
-                    self.add_token(TokenType::Bang, None);
+                    self.make_token(TokenType::Bang, None)
                 }
             }
             '=' => {
                 if self.is_next('=') {
-                    self.add_token(TokenType::EqualEqual, None);
+                    self.make_token(TokenType::EqualEqual, None)
+                } else if self.is_next('>') {
+                    self.make_token(TokenType::Arrow, None)
                 } else {
-                    self.add_token(TokenType::Equal, None);
+                    self.make_token(TokenType::Equal, None)
                 }
             }
             '<' => {
                 if self.is_next('=') {
-                    self.add_token(TokenType::LessEqual, None);
+                    self.make_token(TokenType::LessEqual, None)
+                } else if self.is_next('<') {
+                    self.make_token(TokenType::LessLess, None)
                 } else {
-                    self.add_token(TokenType::Less, None);
+                    self.make_token(TokenType::Less, None)
                 }
             }
             '>' => {
                 if self.is_next('=') {
-                    self.add_token(TokenType::GreaterEqual, None);
+                    self.make_token(TokenType::GreaterEqual, None)
+                } else if self.is_next('>') {
+                    self.make_token(TokenType::GreaterGreater, None)
                 } else {
-                    self.add_token(TokenType::Greater, None);
+                    self.make_token(TokenType::Greater, None)
                 }
             }
             '/' => {
                 if self.is_next('/') {
-                    while self.source[self.current] != '\n' && self.current < self.source.len() {
-                        self.current += 1;
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.advance();
                     }
+                    Err(TriviaKind::Comment)
+                } else if self.is_next('=') {
+                    self.make_token(TokenType::SlashEqual, None)
                 } else {
-                    self.add_token(TokenType::Slash, None);
+                    self.make_token(TokenType::Slash, None)
                 }
             }
-            '"' => {
-                let text = Literal::Str(self.string());
-                self.add_token(TokenType::StringLiteral, Some(text));
+            '\n' => {
+                self.line += 1;
+                Err(TriviaKind::Newline)
+            }
+            ' ' | '\r' | '\t' => {
+                while self.peek().is_some_and(|c| matches!(c, ' ' | '\r' | '\t')) {
+                    self.advance();
+                }
+                Err(TriviaKind::Whitespace)
             }
+            '"' => match self.string() {
+                Some(text) => self.make_token(TokenType::StringLiteral, Some(Literal::Str(text))),
+                None => self.make_token(TokenType::UnterminatedString, None),
+            },
             _ => {
-                if self.current < self.source.len() {
-                    if c.is_alphabetic() {
-                        let identifier: String = self.identifier();
-                        if let Some(&keyword_type) = KEYWORDS.get(&identifier) {
-                            self.add_token(keyword_type, None);
-                        } else {
-                            self.add_token(
-                                TokenType::Identifier,
-                                Some(Literal::Identifier(identifier)),
-                            );
-                        }
-                    } else if c.is_digit(10) {
-                        let number = Literal::Number(self.number());
-                        self.add_token(TokenType::Number, Some(number));
+                if c.is_alphabetic() || c == '_' {
+                    self.identifier();
+                    if let Some(&keyword_type) =
+                        KEYWORDS.get(&self.source[self.start..self.current])
+                    {
+                        self.make_token(keyword_type, None)
+                    } else {
+                        self.make_token(TokenType::Identifier, None)
                     }
+                } else if c.is_ascii_digit() {
+                    let number = Literal::Number(self.number());
+                    self.make_token(TokenType::Number, Some(number))
+                } else {
+                    Err(TriviaKind::Unknown)
                 }
             }
         }
     }
 
-    fn add_token(&mut self, token_type: TokenType, literal: Option<Literal>) {
-        let text = self.source[self.start..self.current].iter().collect();
-        self.tokens
-            .push(Token::new(token_type, text, literal, self.line));
+    fn make_token(
+        &self,
+        token_type: TokenType,
+        literal: Option<Literal>,
+    ) -> Result<Token, TriviaKind> {
+        let text = Lexeme::new(&self.source, self.start, self.current);
+        Ok(Token::new(token_type, text, literal, self.line))
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.current >= self.source.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.current..].chars().next()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        let mut chars = self.source[self.current..].chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn advance(&mut self) -> char {
+        let c = self.peek().unwrap();
+        self.current += c.len_utf8();
+        c
     }
 
     fn is_next(&mut self, expected: char) -> bool {
-        if self.current >= self.source.len() {
-            return false;
+        if self.peek() == Some(expected) {
+            self.current += expected.len_utf8();
+            return true;
         }
+        false
+    }
+}
 
-        if self.source[self.current] == expected {
-            self.current += 1;
-            return true;
+impl Scanner {
+    /// Lossless mode: like `next`, but also returns the trivia skipped before the token,
+    /// so that concatenating every trivia and lexeme reproduces the source exactly.
+    pub fn next_with_trivia(&mut self) -> Option<(Vec<Trivia>, Token)> {
+        let mut trivia = Vec::new();
+        let token = self.next_token(Some(&mut trivia))?;
+        Some((trivia, token))
+    }
+
+    fn next_token(&mut self, mut trivia: Option<&mut Vec<Trivia>>) -> Option<Token> {
+        while !self.is_at_end() {
+            self.start = self.current;
+            match self.scan_token() {
+                Ok(token) => return Some(token),
+                Err(kind) => {
+                    if let Some(trivia) = trivia.as_mut() {
+                        let text = Lexeme::new(&self.source, self.start, self.current);
+                        trivia.push(Trivia { kind, text });
+                    }
+                }
+            }
+        }
+        if self.done {
+            return None;
         }
-        return false;
+        self.done = true;
+        self.start = self.current;
+        let text = Lexeme::new(&self.source, self.current, self.current);
+        Some(Token::new(TokenType::Eof, text, None, self.line))
+    }
+}
+
+impl Iterator for Scanner {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.next_token(None)
     }
 }
 
 impl Scanner {
-    fn string(&mut self) -> String {
+    /// The text of a string literal, or `None` if the source ends before its closing quote.
+    fn string(&mut self) -> Option<String> {
         while !self.is_next('"') {
-            if self.current >= self.source.len() {
-                panic!("inside scanner.string()");
+            if self.is_at_end() {
+                return None;
+            }
+            if self.advance() == '\n' {
+                self.line += 1;
             }
-            self.current += 1;
         }
-        return self.source[self.start + 1..self.current - 1]
-            .iter()
-            .collect();
+        Some(self.source[self.start + 1..self.current - 1].to_string())
     }
 
     fn number(&mut self) -> f64 {
-        while self.current < self.source.len() && self.source[self.current].is_digit(10) {
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
             self.current += 1;
         }
 
-        if self.source[self.current] == '.' {
+        if self.peek() == Some('.') && self.peek_next().is_some_and(|c| c.is_ascii_digit()) {
             self.current += 1;
-            while self.current < self.source.len() && self.source[self.current].is_digit(10) {
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
                 self.current += 1;
             }
         }
-        return self.source[self.start..self.current]
-            .iter()
-            .collect::<String>() // compiler does not know to store this as String.
-            .to_string()
-            .trim()
+        self.source[self.start..self.current]
             .parse::<f64>()
-            .unwrap();
+            .unwrap()
     }
 
-    fn identifier(&mut self) -> String {
-        while self.current < self.source.len() && self.source[self.current].is_alphanumeric() {
-            self.current += 1;
+    fn identifier(&mut self) {
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.advance();
         }
-        return self.source[self.start..self.current].iter().collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_types(source: &str) -> Vec<TokenType> {
+        Scanner::new(source).map(|token| token.token_type).collect()
+    }
+
+    #[test]
+    fn test_lexemes_share_source() {
+        let source = "foo >= \"bar\" // trailing\n12.5";
+        let tokens = Scanner::new(source).scan_tokens();
+        let lexemes: Vec<&str> = tokens.iter().map(|token| &*token.lexeme).collect();
+        assert_eq!(lexemes, ["foo", ">=", "\"bar\"", "12.5", ""]);
+        assert_eq!(
+            tokens[1].lexeme.as_ptr(),
+            tokens[0].lexeme.source()[4..].as_ptr()
+        );
+        assert!(Rc::ptr_eq(
+            tokens[0].lexeme.source(),
+            tokens[3].lexeme.source()
+        ));
+        assert_eq!(tokens[2].literal, Some(Literal::Str("bar".to_string())));
+        assert_eq!(tokens[3].line, 2);
+    }
+
+    #[test]
+    fn test_tokens_at_end_of_input() {
+        assert_eq!(token_types("1"), [TokenType::Number, TokenType::Eof]);
+        assert_eq!(token_types("nil"), [TokenType::Nil, TokenType::Eof]);
+        assert_eq!(token_types("// only a comment"), [TokenType::Eof]);
+        assert_eq!(
+            token_types("1."),
+            [TokenType::Number, TokenType::Dot, TokenType::Eof]
+        );
+    }
+
+    #[test]
+    fn test_ranges_after_numbers() {
+        assert_eq!(
+            token_types("0..10"),
+            [
+                TokenType::Number,
+                TokenType::DotDot,
+                TokenType::Number,
+                TokenType::Eof
+            ]
+        );
+        assert_eq!(
+            token_types("1..=n"),
+            [
+                TokenType::Number,
+                TokenType::DotDotEqual,
+                TokenType::Identifier,
+                TokenType::Eof
+            ]
+        );
+        assert_eq!(token_types("...")[0], TokenType::DotDotDot);
+    }
+
+    #[test]
+    fn test_multibyte_source() {
+        let tokens = Scanner::new("\"héllo\" + ünïcode").scan_tokens();
+        assert_eq!(tokens[0].literal, Some(Literal::Str("héllo".to_string())));
+        assert_eq!(tokens[2].token_type, TokenType::Identifier);
+        assert_eq!(tokens[2].lexeme, "ünïcode");
+    }
+
+    #[test]
+    fn test_iterator_is_fused_after_eof() {
+        let mut scanner = Scanner::new("");
+        assert_eq!(
+            scanner.next().map(|token| token.token_type),
+            Some(TokenType::Eof)
+        );
+        assert_eq!(scanner.next(), None);
     }
 }
@@ -0,0 +1,221 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{Ast, ExprId, StmtId},
+    token::Token,
+};
+
+#[derive(Debug)]
+pub enum Stmt {
+    Expression(ExprId),
+    Print(ExprId),
+    Var(Token, Option<ExprId>),
+    Block(Vec<StmtId>),
+    If(ExprId, StmtId, Option<StmtId>),
+    /// The `while` (or `for`) keyword, optional label, condition, body, and the
+    /// increment clause of a desugared `for` loop, which runs after the body and
+    /// after `continue`.
+    While(Token, Option<Token>, ExprId, StmtId, Option<ExprId>),
+    /// `for (name in iterable) body`, with an optional label.
+    ForIn(Option<Token>, Token, ExprId, StmtId),
+    /// The `break` keyword and an optional loop label.
+    Break(Token, Option<Token>),
+    /// The `continue` keyword and an optional loop label.
+    Continue(Token, Option<Token>),
+    Function(Rc<FunctionDecl>),
+    /// The `return` keyword and the returned value, if any.
+    Return(Token, Option<ExprId>),
+    /// The `throw` keyword and the thrown value.
+    Throw(Token, ExprId),
+    /// The `try` block, the `catch` clause's variable and block, and the `finally`
+    /// block. At least one of the clauses is present.
+    Try(
+        Vec<StmtId>,
+        Option<(Token, Vec<StmtId>)>,
+        Option<Vec<StmtId>>,
+    ),
+}
+
+/// A named function or a lambda. Its default values and body are nodes of the
+/// tree it was parsed from, which function values keep alive along with it.
+#[derive(Debug)]
+pub struct FunctionDecl {
+    pub name: Option<Token>,
+    pub params: Vec<Param>,
+    /// `...rest`, which collects any extra positional arguments into a list.
+    pub rest: Option<Token>,
+    pub body: Vec<StmtId>,
+}
+
+impl FunctionDecl {
+    /// The line of the name, the first parameter or the first statement with one.
+    pub fn line(&self, ast: &Ast) -> Option<u32> {
+        let param = self.params.first().map(|param| &param.name);
+        match self.name.as_ref().or(param).or(self.rest.as_ref()) {
+            Some(token) => Some(token.line),
+            None => self
+                .body
+                .iter()
+                .find_map(|statement| ast[*statement].line(ast)),
+        }
+    }
+}
+
+/// A parameter and its default value, which is evaluated at call time (in the
+/// scope of the call, after earlier parameters are bound) when the argument is omitted.
+#[derive(Debug)]
+pub struct Param {
+    pub name: Token,
+    pub default: Option<ExprId>,
+}
+
+/// A pass over statements that only reads its own state. See `expression::Visitor`.
+pub trait Visitor<R> {
+    fn visit_expression_stmt(&self, expr: ExprId) -> R;
+    fn visit_print_stmt(&self, expr: ExprId) -> R;
+    fn visit_var_stmt(&self, name: &Token, initializer: Option<ExprId>) -> R;
+    fn visit_block_stmt(&self, statements: &[StmtId]) -> R;
+    fn visit_if_stmt(
+        &self,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> R;
+    fn visit_while_stmt(
+        &self,
+        keyword: &Token,
+        label: &Option<Token>,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+    ) -> R;
+    fn visit_for_in_stmt(
+        &self,
+        label: &Option<Token>,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> R;
+    fn visit_break_stmt(&self, keyword: &Token, label: &Option<Token>) -> R;
+    fn visit_continue_stmt(&self, keyword: &Token, label: &Option<Token>) -> R;
+    fn visit_function_stmt(&self, declaration: &Rc<FunctionDecl>) -> R;
+    fn visit_return_stmt(&self, keyword: &Token, value: Option<ExprId>) -> R;
+    fn visit_throw_stmt(&self, keyword: &Token, value: ExprId) -> R;
+    fn visit_try_stmt(
+        &self,
+        body: &[StmtId],
+        catch: &Option<(Token, Vec<StmtId>)>,
+        finally: &Option<Vec<StmtId>>,
+    ) -> R;
+}
+
+/// A pass over statements that updates its own state. See `expression::VisitorMut`.
+pub trait VisitorMut<R> {
+    fn visit_expression_stmt(&mut self, expr: ExprId) -> R;
+    fn visit_print_stmt(&mut self, expr: ExprId) -> R;
+    fn visit_var_stmt(&mut self, name: &Token, initializer: Option<ExprId>) -> R;
+    fn visit_block_stmt(&mut self, statements: &[StmtId]) -> R;
+    fn visit_if_stmt(
+        &mut self,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> R;
+    fn visit_while_stmt(
+        &mut self,
+        keyword: &Token,
+        label: &Option<Token>,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+    ) -> R;
+    fn visit_for_in_stmt(
+        &mut self,
+        label: &Option<Token>,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> R;
+    fn visit_break_stmt(&mut self, keyword: &Token, label: &Option<Token>) -> R;
+    fn visit_continue_stmt(&mut self, keyword: &Token, label: &Option<Token>) -> R;
+    fn visit_function_stmt(&mut self, declaration: &Rc<FunctionDecl>) -> R;
+    fn visit_return_stmt(&mut self, keyword: &Token, value: Option<ExprId>) -> R;
+    fn visit_throw_stmt(&mut self, keyword: &Token, value: ExprId) -> R;
+    fn visit_try_stmt(
+        &mut self,
+        body: &[StmtId],
+        catch: &Option<(Token, Vec<StmtId>)>,
+        finally: &Option<Vec<StmtId>>,
+    ) -> R;
+}
+
+impl Stmt {
+    /// The line of one of the statement's tokens, as `Expr::line` finds it.
+    /// Empty blocks and statements of only literals have none.
+    pub fn line(&self, ast: &Ast) -> Option<u32> {
+        match self {
+            Stmt::Expression(expr) | Stmt::Print(expr) => ast[*expr].line(ast),
+            Stmt::Var(name, _) | Stmt::ForIn(_, name, _, _) => Some(name.line),
+            Stmt::Block(statements) | Stmt::Try(statements, _, _) => statements
+                .iter()
+                .find_map(|statement| ast[*statement].line(ast)),
+            Stmt::If(condition, then_branch, _) => ast[*condition]
+                .line(ast)
+                .or_else(|| ast[*then_branch].line(ast)),
+            Stmt::While(keyword, _, _, _, _)
+            | Stmt::Break(keyword, _)
+            | Stmt::Continue(keyword, _)
+            | Stmt::Return(keyword, _)
+            | Stmt::Throw(keyword, _) => Some(keyword.line),
+            Stmt::Function(declaration) => declaration.line(ast),
+        }
+    }
+
+    pub fn accept<T: Visitor<R>, R>(&self, visitor: &T) -> R {
+        match self {
+            Stmt::Expression(expr) => visitor.visit_expression_stmt(*expr),
+            Stmt::Print(expr) => visitor.visit_print_stmt(*expr),
+            Stmt::Var(name, initializer) => visitor.visit_var_stmt(name, *initializer),
+            Stmt::Block(statements) => visitor.visit_block_stmt(statements),
+            Stmt::If(condition, then_branch, else_branch) => {
+                visitor.visit_if_stmt(*condition, *then_branch, *else_branch)
+            }
+            Stmt::While(keyword, label, condition, body, increment) => {
+                visitor.visit_while_stmt(keyword, label, *condition, *body, *increment)
+            }
+            Stmt::ForIn(label, name, iterable, body) => {
+                visitor.visit_for_in_stmt(label, name, *iterable, *body)
+            }
+            Stmt::Break(keyword, label) => visitor.visit_break_stmt(keyword, label),
+            Stmt::Continue(keyword, label) => visitor.visit_continue_stmt(keyword, label),
+            Stmt::Function(declaration) => visitor.visit_function_stmt(declaration),
+            Stmt::Return(keyword, value) => visitor.visit_return_stmt(keyword, *value),
+            Stmt::Throw(keyword, value) => visitor.visit_throw_stmt(keyword, *value),
+            Stmt::Try(body, catch, finally) => visitor.visit_try_stmt(body, catch, finally),
+        }
+    }
+
+    pub fn accept_mut<T: VisitorMut<R>, R>(&self, visitor: &mut T) -> R {
+        match self {
+            Stmt::Expression(expr) => visitor.visit_expression_stmt(*expr),
+            Stmt::Print(expr) => visitor.visit_print_stmt(*expr),
+            Stmt::Var(name, initializer) => visitor.visit_var_stmt(name, *initializer),
+            Stmt::Block(statements) => visitor.visit_block_stmt(statements),
+            Stmt::If(condition, then_branch, else_branch) => {
+                visitor.visit_if_stmt(*condition, *then_branch, *else_branch)
+            }
+            Stmt::While(keyword, label, condition, body, increment) => {
+                visitor.visit_while_stmt(keyword, label, *condition, *body, *increment)
+            }
+            Stmt::ForIn(label, name, iterable, body) => {
+                visitor.visit_for_in_stmt(label, name, *iterable, *body)
+            }
+            Stmt::Break(keyword, label) => visitor.visit_break_stmt(keyword, label),
+            Stmt::Continue(keyword, label) => visitor.visit_continue_stmt(keyword, label),
+            Stmt::Function(declaration) => visitor.visit_function_stmt(declaration),
+            Stmt::Return(keyword, value) => visitor.visit_return_stmt(keyword, *value),
+            Stmt::Throw(keyword, value) => visitor.visit_throw_stmt(keyword, *value),
+            Stmt::Try(body, catch, finally) => visitor.visit_try_stmt(body, catch, finally),
+        }
+    }
+}
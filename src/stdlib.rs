@@ -0,0 +1,236 @@
+use std::io::{self, Write};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    interpreter::{Interpreter, Output, RuntimeError},
+    token::Token,
+    value::Value,
+};
+
+/// The body of a built-in. Unlike a host-defined native it captures nothing.
+type Builtin = fn(&Token, Vec<Value>) -> Result<Value, RuntimeError>;
+
+/// The functions every interpreter starts with in its global scope, besides `print`:
+/// name, parameters and implementation.
+pub const BUILTINS: &[(&str, &[&str], Builtin)] = &[
+    ("clock", &[], clock),
+    ("len", &["value"], len),
+    ("type", &["value"], type_of),
+    ("str", &["value"], str),
+    ("num", &["value"], num),
+];
+
+/// The built-ins that reach outside the interpreter, which sandboxed scripts don't get.
+pub const SYSTEM_BUILTINS: &[(&str, &[&str], Builtin)] =
+    &[("input", &[], input), ("exit", &["code"], exit)];
+
+/// Defines the built-ins in the global scope of a new interpreter.
+pub fn define_builtins(interpreter: &Interpreter, allow_system: bool) {
+    let system = if allow_system { SYSTEM_BUILTINS } else { &[] };
+    for &(name, params, function) in BUILTINS.iter().chain(system) {
+        interpreter.define_native(name, params, function);
+    }
+    let stdout = interpreter.stdout();
+    interpreter.define_native("print", &["value"], move |_, arguments| {
+        print(&stdout, &arguments[0]);
+        Ok(Value::Nil)
+    });
+}
+
+/// Prints a value the way the `print` statement does. Output that can't be
+/// written is dropped, so that a closed pipe doesn't stop the program.
+pub fn print(out: &Output, value: &Value) {
+    let _ = writeln!(out.borrow_mut(), "{}", value);
+}
+
+/// Seconds since the Unix epoch.
+fn clock(_paren: &Token, _arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+/// The number of characters in a string, or elements in a list or map.
+fn len(paren: &Token, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let len = match &arguments[0] {
+        Value::Str(s) => s.chars().count(),
+        Value::List(list) => list.borrow().len(),
+        Value::Map(map) => map.borrow().len(),
+        _ => {
+            return Err(RuntimeError::new(
+                paren,
+                "Can only take the length of strings, lists and maps.",
+            ))
+        }
+    };
+    Ok(Value::Number(len as f64))
+}
+
+fn type_of(_paren: &Token, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let name = match &arguments[0] {
+        Value::Str(_) => "string",
+        Value::Number(_) => "number",
+        Value::Boolean(_) => "boolean",
+        Value::Nil => "nil",
+        Value::Range(..) => "range",
+        Value::List(_) => "list",
+        Value::Map(_) => "map",
+        Value::Function(_) | Value::Native(_) => "function",
+        Value::Class(_) => "class",
+        // Instances are typed by their class, as `Counter`.
+        Value::Instance(instance) => instance.class.name,
+    };
+    Ok(Value::Str(name.to_string()))
+}
+
+/// Strings are returned as they are, anything else as it would be printed.
+fn str(_paren: &Token, mut arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    match arguments.pop().unwrap() {
+        string @ Value::Str(_) => Ok(string),
+        value => Ok(Value::Str(value.to_string())),
+    }
+}
+
+/// Numbers are returned as they are, strings are parsed.
+fn num(paren: &Token, mut arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let value = arguments.pop().unwrap();
+    match &value {
+        Value::Number(_) => return Ok(value),
+        Value::Str(s) => {
+            if let Ok(n) = s.trim().parse() {
+                return Ok(Value::Number(n));
+            }
+        }
+        _ => {}
+    }
+    let message = format!("Can't convert {} to a number.", value.repr());
+    Err(RuntimeError::new(paren, &message))
+}
+
+/// Reads a line from standard input, without its line ending, or nil at the end of input.
+fn input(paren: &Token, _arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) => Ok(Value::Nil),
+        Ok(_) => {
+            let len = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(len);
+            Ok(Value::Str(line))
+        }
+        Err(_) => Err(RuntimeError::new(
+            paren,
+            "Couldn't read from standard input.",
+        )),
+    }
+}
+
+/// Ends the process with the given status code.
+fn exit(paren: &Token, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    match arguments[0] {
+        Value::Number(code) if code.fract() == 0.0 && code.abs() <= i32::MAX as f64 => {
+            process::exit(code as i32)
+        }
+        _ => Err(RuntimeError::new(paren, "Exit code must be an integer.")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        interpreter::Interpreter, parser::Parser, resolver::Resolver, scanner::Scanner,
+        value::Value,
+    };
+
+    fn eval(interpreter: &mut Interpreter, source: &str) -> Value {
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+        let expr = parser.parse_expression().unwrap();
+        let locals = Resolver::new(parser.ast())
+            .resolve_expression(expr)
+            .unwrap();
+        interpreter
+            .interpret(&Rc::new(parser.into_ast()), &Rc::new(locals), expr)
+            .unwrap()
+    }
+
+    fn eval_error(source: &str) -> String {
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+        let expr = parser.parse_expression().unwrap();
+        let locals = Resolver::new(parser.ast())
+            .resolve_expression(expr)
+            .unwrap();
+        Interpreter::new()
+            .interpret(&Rc::new(parser.into_ast()), &Rc::new(locals), expr)
+            .unwrap_err()
+            .message
+    }
+
+    #[test]
+    fn test_builtins() {
+        let mut interpreter = Interpreter::new();
+        let str = |s: &str| Value::Str(s.to_string());
+        assert_eq!(eval(&mut interpreter, "len(\"héllo\")"), Value::Number(5.0));
+        assert_eq!(
+            eval(&mut interpreter, "len([1, 2]) + len({})"),
+            Value::Number(2.0)
+        );
+        assert_eq!(eval(&mut interpreter, "type(1)"), str("number"));
+        assert_eq!(eval(&mut interpreter, "type(0..1)"), str("range"));
+        assert_eq!(eval(&mut interpreter, "type(len)"), str("function"));
+        assert_eq!(eval(&mut interpreter, "type(() => 1)"), str("function"));
+        assert_eq!(
+            eval(&mut interpreter, "str(\"a\") + str([1, \"b\"])"),
+            str("a[1, \"b\"]")
+        );
+        assert_eq!(
+            eval(&mut interpreter, "num(\" 2.5 \") + num(1)"),
+            Value::Number(3.5)
+        );
+        assert_eq!(eval(&mut interpreter, "print(nil)"), Value::Nil);
+        assert_eq!(
+            eval(&mut interpreter, "str(print)"),
+            str("<native fn print>")
+        );
+        let Value::Number(now) = eval(&mut interpreter, "clock()") else {
+            panic!("clock() should return a number");
+        };
+        assert!(now > 0.0);
+    }
+
+    #[test]
+    fn test_builtin_errors() {
+        assert_eq!(
+            eval_error("len(1)"),
+            "Can only take the length of strings, lists and maps."
+        );
+        assert_eq!(
+            eval_error("num(\"1x\")"),
+            "Can't convert \"1x\" to a number."
+        );
+        assert_eq!(eval_error("num(nil)"), "Can't convert nil to a number.");
+        assert_eq!(eval_error("exit(0.5)"), "Exit code must be an integer.");
+        assert_eq!(
+            eval_error("type()"),
+            "Expected 1 argument but got 0 for type(value)."
+        );
+    }
+
+    #[test]
+    fn test_host_natives() {
+        let mut interpreter = Interpreter::new();
+        let greeting = String::from("Hello, ");
+        interpreter.define_native("greet", &["name"], move |_, arguments| {
+            Ok(Value::Str(format!("{}{}", greeting, arguments[0])))
+        });
+        assert_eq!(
+            eval(&mut interpreter, "greet(1)"),
+            Value::Str("Hello, 1".to_string())
+        );
+        // Built-ins are ordinary globals, so hosts can replace them.
+        interpreter.define_native("len", &[], |_, _| Ok(Value::Nil));
+        assert_eq!(eval(&mut interpreter, "len()"), Value::Nil);
+    }
+}
@@ -1,10 +1,10 @@
-use phf;
 use phf::phf_map;
 use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
-    Identifier(String),
     Str(String),
     Number(f64),
     Boolean(bool),
@@ -14,7 +14,6 @@ pub enum Literal {
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Literal::Identifier(s) => write!(f, "Identifier({})", s),
             Literal::Str(s) => write!(f, "\"{}\"", s),
             Literal::Number(n) => write!(f, "{}", n),
             Literal::Boolean(b) => write!(f, "{}", b),
@@ -30,19 +29,43 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
+    DotDot,
+    DotDotEqual,
+    DotDotDot,
     Minus,
     Plus,
     Semicolon,
     Slash,
     Star,
+    Question,
+    Colon,
+    Percent,
+    Caret,
+    Ampersand,
+    Pipe,
 
     // One or two character tokens.
+    PlusPlus,
+    PlusEqual,
+    MinusMinus,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    StarStar,
+    Tilde,
+    TildeSlash,
+    LessLess,
+    GreaterGreater,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
@@ -51,6 +74,8 @@ pub enum TokenType {
     // Literals.
     Identifier,
     StringLiteral,
+    /// A string missing its closing quote, which runs to the end of the source.
+    UnterminatedString,
     Number,
 
     // Keywords.
@@ -61,6 +86,7 @@ pub enum TokenType {
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
@@ -70,6 +96,12 @@ pub enum TokenType {
     True,
     Var,
     While,
+    Break,
+    Continue,
+    Throw,
+    Try,
+    Catch,
+    Finally,
 
     Eof,
 }
@@ -122,25 +154,117 @@ pub enum TokenType {
 //     }
 // }
 
+/// A piece of source text. It shares the source it was scanned from, so tokens,
+/// and the trees and values built from them, don't borrow the caller's string.
+#[derive(Clone)]
+pub struct Lexeme {
+    source: Rc<str>,
+    start: usize,
+    end: usize,
+}
+
+impl Lexeme {
+    /// The text of `source` between the byte offsets `start` and `end`.
+    pub fn new(source: &Rc<str>, start: usize, end: usize) -> Self {
+        assert!(source.is_char_boundary(start) && source.is_char_boundary(end));
+        Lexeme {
+            source: source.clone(),
+            start,
+            end,
+        }
+    }
+
+    /// The whole source this lexeme is part of.
+    pub fn source(&self) -> &Rc<str> {
+        &self.source
+    }
+}
+
+/// Text that isn't part of a source, such as the names of tokens made up by
+/// the interpreter.
+impl From<&str> for Lexeme {
+    fn from(text: &str) -> Self {
+        Lexeme::new(&Rc::from(text), 0, text.len())
+    }
+}
+
+impl Deref for Lexeme {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.source[self.start..self.end]
+    }
+}
+
+impl fmt::Debug for Lexeme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl fmt::Display for Lexeme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl PartialEq for Lexeme {
+    fn eq(&self, other: &Lexeme) -> bool {
+        **self == **other
+    }
+}
+
+impl PartialEq<str> for Lexeme {
+    fn eq(&self, other: &str) -> bool {
+        &**self == other
+    }
+}
+
+impl PartialEq<&str> for Lexeme {
+    fn eq(&self, other: &&str) -> bool {
+        &**self == *other
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: Lexeme,
     pub literal: Option<Literal>,
     pub line: u32,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, literal: Option<Literal>, line: u32) -> Self {
+    pub fn new(
+        token_type: TokenType,
+        lexeme: impl Into<Lexeme>,
+        literal: Option<Literal>,
+        line: u32,
+    ) -> Self {
         Token {
             token_type,
-            lexeme,
+            lexeme: lexeme.into(),
             literal,
             line,
         }
     }
 }
 
+/// Source text the scanner skips over. Only kept around in lossless mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    Comment,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: Lexeme,
+}
+
 pub static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "and"    => TokenType::And,
     "or"     => TokenType::Or,
@@ -151,6 +275,7 @@ pub static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "var"    => TokenType::Var,
     "fun"    => TokenType::Fun,
     "if"     => TokenType::If,
+    "in"     => TokenType::In,
     "else"   => TokenType::Else,
     "print"  => TokenType::Print,
     "return" => TokenType::Return,
@@ -158,4 +283,10 @@ pub static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "this"   => TokenType::This,
     "true"   => TokenType::True,
     "false"  => TokenType::False,
+    "break"  => TokenType::Break,
+    "continue" => TokenType::Continue,
+    "throw"  => TokenType::Throw,
+    "try"    => TokenType::Try,
+    "catch"  => TokenType::Catch,
+    "finally" => TokenType::Finally,
 };
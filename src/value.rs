@@ -0,0 +1,320 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use crate::{
+    ast::Ast,
+    class::{Instance, NativeClass},
+    environment::Environment,
+    interpreter::RuntimeError,
+    map::Map,
+    resolver::Locals,
+    statement::FunctionDecl,
+    token::{Literal, Token},
+};
+
+/// A runtime value. Besides everything a `Literal` can spell out in the source,
+/// this includes ranges, lists, maps and functions created while the program runs,
+/// and the classes and instances hosts define in Rust.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Str(String),
+    Number(f64),
+    Boolean(bool),
+    Nil,
+    /// `start..end`, with the end included when the flag is set.
+    Range(f64, f64, bool),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Map>>),
+    Function(Rc<Function>),
+    Native(Rc<NativeFunction>),
+    Class(Rc<NativeClass>),
+    Instance(Instance),
+}
+
+impl Value {
+    pub fn native(
+        name: &'static str,
+        params: &'static [&'static str],
+        function: impl Fn(&Token, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    ) -> Self {
+        Value::Native(Rc::new(NativeFunction {
+            name,
+            params,
+            function: Box::new(function),
+        }))
+    }
+
+    /// The list, map, function or instance the value refers to, which the object
+    /// limit counts while it is alive.
+    pub fn object(&self) -> Option<Weak<dyn Any>> {
+        match self {
+            Value::List(list) => Some(Rc::downgrade(list) as Weak<dyn Any>),
+            Value::Map(map) => Some(Rc::downgrade(map) as Weak<dyn Any>),
+            Value::Function(function) => Some(Rc::downgrade(function) as Weak<dyn Any>),
+            Value::Instance(instance) => Some(instance.downgrade()),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Range(a, b, c), Value::Range(x, y, z)) => a == x && b == y && c == z,
+            // Lists, maps, functions and objects are only equal to themselves.
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => a.ptr_eq(b),
+            _ => false,
+        }
+    }
+}
+
+impl From<Literal> for Value {
+    fn from(literal: Literal) -> Self {
+        match literal {
+            Literal::Str(s) => Value::Str(s),
+            Literal::Number(n) => Value::Number(n),
+            Literal::Boolean(b) => Value::Boolean(b),
+            Literal::Nil => Value::Nil,
+        }
+    }
+}
+
+/// How `print` and `str` write a value: strings as they are. Strings inside lists
+/// and maps are written as by [`Value::repr`].
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, false)
+    }
+}
+
+/// A value written with its strings quoted. See [`Value::repr`].
+pub struct Repr<'a>(&'a Value);
+
+impl fmt::Display for Repr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.write(f, true)
+    }
+}
+
+impl Value {
+    /// How the REPL echoes a value and error messages quote it: like `Display`,
+    /// but strings are quoted, so that `"1"` can be told apart from `1`.
+    pub fn repr(&self) -> Repr<'_> {
+        Repr(self)
+    }
+
+    /// Writes the value, quoting it if it is a string and `quoted` is set.
+    /// Lists and maps are written from a stack of the ones still open rather than
+    /// by recursion, as they can be nested deeper than the host's stack allows.
+    /// One that contains itself is written as `[...]` or `{...}` the second time.
+    fn write(&self, f: &mut fmt::Formatter<'_>, quoted: bool) -> fmt::Result {
+        if !matches!(self, Value::List(_) | Value::Map(_)) {
+            return self.write_scalar(f, quoted);
+        }
+        let mut stack: Vec<Open> = Vec::new();
+        let mut open: HashSet<*const ()> = HashSet::new();
+        let mut next = Some(self.clone());
+        loop {
+            if let Some(value) = next.take() {
+                match &value {
+                    Value::List(list) => {
+                        if open.insert(Rc::as_ptr(list) as *const ()) {
+                            write!(f, "[")?;
+                            stack.push(Open::List(list.clone(), 0));
+                        } else {
+                            write!(f, "[...]")?;
+                        }
+                    }
+                    Value::Map(map) => {
+                        let id = Rc::as_ptr(map) as *const ();
+                        if open.insert(id) {
+                            write!(f, "{{")?;
+                            let entries = map
+                                .borrow()
+                                .iter()
+                                .flat_map(|(key, value)| [key.to_value(), value.clone()])
+                                .collect();
+                            stack.push(Open::Map(id, entries, 0));
+                        } else {
+                            write!(f, "{{...}}")?;
+                        }
+                    }
+                    // Strings inside lists and maps are always quoted.
+                    _ => value.write_scalar(f, true)?,
+                }
+            }
+            let Some(top) = stack.last_mut() else {
+                return Ok(());
+            };
+            match top {
+                Open::List(list, index) => {
+                    let element = list.borrow().get(*index).cloned();
+                    if let Some(element) = element {
+                        if *index > 0 {
+                            write!(f, ", ")?;
+                        }
+                        *index += 1;
+                        next = Some(element);
+                    } else {
+                        open.remove(&(Rc::as_ptr(list) as *const ()));
+                        stack.pop();
+                        write!(f, "]")?;
+                    }
+                }
+                Open::Map(id, entries, index) => {
+                    if *index == entries.len() {
+                        open.remove(id);
+                        stack.pop();
+                        write!(f, "}}")?;
+                    } else {
+                        if *index > 0 {
+                            write!(f, "{}", if *index % 2 == 0 { ", " } else { ": " })?;
+                        }
+                        next = Some(std::mem::replace(&mut entries[*index], Value::Nil));
+                        *index += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes a value other than a list or map.
+    fn write_scalar(&self, f: &mut fmt::Formatter<'_>, quoted: bool) -> fmt::Result {
+        match self {
+            Value::Str(s) if quoted => write!(f, "\"{}\"", s),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Nil => write!(f, "nil"),
+            Value::Range(start, end, false) => write!(f, "{}..{}", start, end),
+            Value::Range(start, end, true) => write!(f, "{}..={}", start, end),
+            Value::List(_) | Value::Map(_) => {
+                unreachable!("Lists and maps are written by Value::write")
+            }
+            Value::Function(function) => write!(f, "<fn {}>", function.name()),
+            Value::Native(native) => write!(f, "<native fn {}>", native.name),
+            Value::Class(class) => write!(f, "<class {}>", class.name),
+            Value::Instance(instance) => write!(f, "<{} instance>", instance.class.name),
+        }
+    }
+}
+
+/// A list or map being written, with the position of the next element to write.
+enum Open {
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    /// The address of the map, and its keys and values in turn as of when it was
+    /// opened.
+    Map(*const (), Vec<Value>, usize),
+}
+
+/// Lists and maps nested a million deep are legal, so a value is taken apart one
+/// level at a time rather than by recursive drops that could overflow the host's
+/// stack.
+impl Drop for Value {
+    fn drop(&mut self) {
+        let mut orphans = Vec::new();
+        self.take_children(&mut orphans);
+        while let Some(mut orphan) = orphans.pop() {
+            orphan.take_children(&mut orphans);
+        }
+    }
+}
+
+impl Value {
+    /// Moves the elements of a list or map only this value refers to into `orphans`,
+    /// leaving it empty.
+    fn take_children(&mut self, orphans: &mut Vec<Value>) {
+        match self {
+            Value::List(list) if Rc::strong_count(list) == 1 => {
+                if let Ok(mut list) = list.try_borrow_mut() {
+                    orphans.append(&mut list);
+                }
+            }
+            Value::Map(map) if Rc::strong_count(map) == 1 => {
+                if let Ok(mut map) = map.try_borrow_mut() {
+                    orphans.extend(map.drain_values());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A function declaration or lambda together with the environment it was created in.
+pub struct Function {
+    pub declaration: Rc<FunctionDecl>,
+    pub closure: Rc<RefCell<Environment>>,
+    /// The tree the declaration was parsed from.
+    pub ast: Rc<Ast>,
+    /// Where the resolver found the local variables of `ast` declared.
+    pub locals: Rc<Locals>,
+    /// Where the function was defined, for stack traces.
+    pub file: Rc<str>,
+}
+
+impl Function {
+    pub fn name(&self) -> &str {
+        match &self.declaration.name {
+            Some(name) => &name.lexeme,
+            None => "lambda",
+        }
+    }
+
+    /// How the function is declared, with optional parameters in brackets,
+    /// e.g. `greet(name, [greeting], ...rest)`.
+    pub fn signature(&self) -> String {
+        let mut params: Vec<String> = Vec::new();
+        for param in &self.declaration.params {
+            match param.default {
+                Some(_) => params.push(format!("[{}]", param.name.lexeme)),
+                None => params.push(param.name.lexeme.to_string()),
+            }
+        }
+        if let Some(rest) = &self.declaration.rest {
+            params.push(format!("...{}", rest.lexeme));
+        }
+        format!("{}({})", self.name(), params.join(", "))
+    }
+}
+
+// The closure usually contains the function itself, so it is left out.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+/// Body of a native function: gets the closing parenthesis of the call, for error
+/// locations, and exactly as many arguments as the function has parameters.
+pub type NativeFn = dyn Fn(&Token, Vec<Value>) -> Result<Value, RuntimeError>;
+
+/// A function implemented in Rust, such as a built-in method bound to its list.
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+    pub function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params.join(", "))
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
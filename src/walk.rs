@@ -0,0 +1,769 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{Ast, ExprId, StmtId},
+    expression::Expr,
+    statement::{FunctionDecl, Param, Stmt},
+    token::{Literal, Token},
+};
+
+/// A pass that reads a tree, such as a lint rule. Every node kind has a method
+/// that visits the node's children; passes override the ones for the nodes they
+/// care about, and call `walk_*` from them to keep going down.
+///
+/// To see every node with its ID, for a [`SideTable`](crate::ast::SideTable),
+/// override `walk_expr` or `walk_stmt` and call [`walk_expr`] or [`walk_stmt`] from it,
+/// as the resolver does to record where each variable is declared.
+pub trait Walker<'a> {
+    /// The tree being walked.
+    fn ast(&self) -> &'a Ast;
+
+    fn walk_expr(&mut self, expr: ExprId) {
+        walk_expr(self, expr);
+    }
+
+    fn walk_stmt(&mut self, stmt: StmtId) {
+        walk_stmt(self, stmt);
+    }
+
+    fn walk_stmts(&mut self, statements: &[StmtId]) {
+        for statement in statements {
+            self.walk_stmt(*statement);
+        }
+    }
+
+    /// Walks the default values, then the body.
+    fn walk_function(&mut self, declaration: &FunctionDecl) {
+        for param in &declaration.params {
+            if let Some(default) = param.default {
+                self.walk_expr(default);
+            }
+        }
+        self.walk_stmts(&declaration.body);
+    }
+
+    fn visit_literal(&mut self, _literal: &Literal) {}
+
+    fn visit_grouping(&mut self, expr: ExprId) {
+        self.walk_expr(expr);
+    }
+
+    fn visit_binary(&mut self, left: ExprId, _operator: &Token, right: ExprId) {
+        self.walk_expr(left);
+        self.walk_expr(right);
+    }
+
+    fn visit_unary(&mut self, _operator: &Token, right: ExprId) {
+        self.walk_expr(right);
+    }
+
+    fn visit_logical(&mut self, left: ExprId, _operator: &Token, right: ExprId) {
+        self.walk_expr(left);
+        self.walk_expr(right);
+    }
+
+    fn visit_ternary(&mut self, condition: ExprId, then_branch: ExprId, else_branch: ExprId) {
+        self.walk_expr(condition);
+        self.walk_expr(then_branch);
+        self.walk_expr(else_branch);
+    }
+
+    fn visit_comma(&mut self, left: ExprId, right: ExprId) {
+        self.walk_expr(left);
+        self.walk_expr(right);
+    }
+
+    fn visit_variable(&mut self, _name: &Token) {}
+
+    fn visit_assign(&mut self, _name: &Token, value: ExprId) {
+        self.walk_expr(value);
+    }
+
+    fn visit_compound_assign(&mut self, target: ExprId, _operator: &Token, value: ExprId) {
+        self.walk_expr(target);
+        self.walk_expr(value);
+    }
+
+    fn visit_increment(&mut self, target: ExprId, _operator: &Token, _prefix: bool) {
+        self.walk_expr(target);
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: ExprId,
+        _paren: &Token,
+        arguments: &[ExprId],
+        named: &[(Token, ExprId)],
+    ) {
+        self.walk_expr(callee);
+        for argument in arguments {
+            self.walk_expr(*argument);
+        }
+        for (_name, argument) in named {
+            self.walk_expr(*argument);
+        }
+    }
+
+    fn visit_lambda(&mut self, declaration: &Rc<FunctionDecl>) {
+        self.walk_function(declaration);
+    }
+
+    fn visit_list(&mut self, elements: &[ExprId]) {
+        for element in elements {
+            self.walk_expr(*element);
+        }
+    }
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(ExprId, ExprId)]) {
+        for (key, value) in entries {
+            self.walk_expr(*key);
+            self.walk_expr(*value);
+        }
+    }
+
+    fn visit_index(&mut self, object: ExprId, _bracket: &Token, index: ExprId) {
+        self.walk_expr(object);
+        self.walk_expr(index);
+    }
+
+    fn visit_slice(
+        &mut self,
+        object: ExprId,
+        _bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) {
+        self.walk_expr(object);
+        for bound in [start, end].into_iter().flatten() {
+            self.walk_expr(bound);
+        }
+    }
+
+    fn visit_set_index(&mut self, object: ExprId, _bracket: &Token, index: ExprId, value: ExprId) {
+        self.walk_expr(object);
+        self.walk_expr(index);
+        self.walk_expr(value);
+    }
+
+    fn visit_get(&mut self, object: ExprId, _name: &Token) {
+        self.walk_expr(object);
+    }
+
+    fn visit_set(&mut self, object: ExprId, _name: &Token, value: ExprId) {
+        self.walk_expr(object);
+        self.walk_expr(value);
+    }
+
+    fn visit_expression_stmt(&mut self, expr: ExprId) {
+        self.walk_expr(expr);
+    }
+
+    fn visit_print_stmt(&mut self, expr: ExprId) {
+        self.walk_expr(expr);
+    }
+
+    fn visit_var_stmt(&mut self, _name: &Token, initializer: Option<ExprId>) {
+        if let Some(initializer) = initializer {
+            self.walk_expr(initializer);
+        }
+    }
+
+    fn visit_block_stmt(&mut self, statements: &[StmtId]) {
+        self.walk_stmts(statements);
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) {
+        self.walk_expr(condition);
+        self.walk_stmt(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.walk_stmt(else_branch);
+        }
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        _keyword: &Token,
+        _label: &Option<Token>,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+    ) {
+        self.walk_expr(condition);
+        self.walk_stmt(body);
+        if let Some(increment) = increment {
+            self.walk_expr(increment);
+        }
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        _label: &Option<Token>,
+        _name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) {
+        self.walk_expr(iterable);
+        self.walk_stmt(body);
+    }
+
+    fn visit_break_stmt(&mut self, _keyword: &Token, _label: &Option<Token>) {}
+
+    fn visit_continue_stmt(&mut self, _keyword: &Token, _label: &Option<Token>) {}
+
+    fn visit_function_stmt(&mut self, declaration: &Rc<FunctionDecl>) {
+        self.walk_function(declaration);
+    }
+
+    fn visit_return_stmt(&mut self, _keyword: &Token, value: Option<ExprId>) {
+        if let Some(value) = value {
+            self.walk_expr(value);
+        }
+    }
+
+    fn visit_throw_stmt(&mut self, _keyword: &Token, value: ExprId) {
+        self.walk_expr(value);
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        body: &[StmtId],
+        catch: &Option<(Token, Vec<StmtId>)>,
+        finally: &Option<Vec<StmtId>>,
+    ) {
+        self.walk_stmts(body);
+        if let Some((_name, handler)) = catch {
+            self.walk_stmts(handler);
+        }
+        if let Some(finally) = finally {
+            self.walk_stmts(finally);
+        }
+    }
+}
+
+/// Calls the walker's method for the kind of `expr`.
+pub fn walk_expr<'a, W: Walker<'a> + ?Sized>(walker: &mut W, expr: ExprId) {
+    match &walker.ast()[expr] {
+        Expr::Literal(literal) => walker.visit_literal(literal),
+        Expr::Grouping(expr) => walker.visit_grouping(*expr),
+        Expr::Binary(left, op, right) => walker.visit_binary(*left, op, *right),
+        Expr::Unary(op, right) => walker.visit_unary(op, *right),
+        Expr::Logical(left, op, right) => walker.visit_logical(*left, op, *right),
+        Expr::Ternary(condition, then_branch, else_branch) => {
+            walker.visit_ternary(*condition, *then_branch, *else_branch)
+        }
+        Expr::Comma(left, right) => walker.visit_comma(*left, *right),
+        Expr::Variable(name) => walker.visit_variable(name),
+        Expr::Assign(name, value) => walker.visit_assign(name, *value),
+        Expr::CompoundAssign(target, op, value) => {
+            walker.visit_compound_assign(*target, op, *value)
+        }
+        Expr::Increment(target, op, prefix) => walker.visit_increment(*target, op, *prefix),
+        Expr::Call(callee, paren, arguments, named) => {
+            walker.visit_call(*callee, paren, arguments, named)
+        }
+        Expr::Lambda(declaration) => walker.visit_lambda(declaration),
+        Expr::List(elements) => walker.visit_list(elements),
+        Expr::Map(brace, entries) => walker.visit_map(brace, entries),
+        Expr::Index(object, bracket, index) => walker.visit_index(*object, bracket, *index),
+        Expr::Slice(object, bracket, start, end) => {
+            walker.visit_slice(*object, bracket, *start, *end)
+        }
+        Expr::SetIndex(object, bracket, index, value) => {
+            walker.visit_set_index(*object, bracket, *index, *value)
+        }
+        Expr::Get(object, name) => walker.visit_get(*object, name),
+        Expr::Set(object, name, value) => walker.visit_set(*object, name, *value),
+    }
+}
+
+/// Calls the walker's method for the kind of `stmt`.
+pub fn walk_stmt<'a, W: Walker<'a> + ?Sized>(walker: &mut W, stmt: StmtId) {
+    match &walker.ast()[stmt] {
+        Stmt::Expression(expr) => walker.visit_expression_stmt(*expr),
+        Stmt::Print(expr) => walker.visit_print_stmt(*expr),
+        Stmt::Var(name, initializer) => walker.visit_var_stmt(name, *initializer),
+        Stmt::Block(statements) => walker.visit_block_stmt(statements),
+        Stmt::If(condition, then_branch, else_branch) => {
+            walker.visit_if_stmt(*condition, *then_branch, *else_branch)
+        }
+        Stmt::While(keyword, label, condition, body, increment) => {
+            walker.visit_while_stmt(keyword, label, *condition, *body, *increment)
+        }
+        Stmt::ForIn(label, name, iterable, body) => {
+            walker.visit_for_in_stmt(label, name, *iterable, *body)
+        }
+        Stmt::Break(keyword, label) => walker.visit_break_stmt(keyword, label),
+        Stmt::Continue(keyword, label) => walker.visit_continue_stmt(keyword, label),
+        Stmt::Function(declaration) => walker.visit_function_stmt(declaration),
+        Stmt::Return(keyword, value) => walker.visit_return_stmt(keyword, *value),
+        Stmt::Throw(keyword, value) => walker.visit_throw_stmt(keyword, *value),
+        Stmt::Try(body, catch, finally) => walker.visit_try_stmt(body, catch, finally),
+    }
+}
+
+/// A pass that builds a new tree from one it reads, such as desugaring or
+/// constant folding. Every node kind has a method that folds the node's children
+/// and adds a copy of the node over the folded children; passes override the
+/// ones for the nodes they rewrite.
+///
+/// The methods are given the IDs of the old tree and return IDs of the new one.
+/// Nodes a pass folds and then drops stay in the new tree, unused.
+pub trait Folder<'a> {
+    /// The tree being folded.
+    fn ast(&self) -> &'a Ast;
+
+    /// The tree being built.
+    fn folded(&mut self) -> &mut Ast;
+
+    fn fold_expr(&mut self, expr: ExprId) -> ExprId {
+        fold_expr(self, expr)
+    }
+
+    fn fold_stmt(&mut self, stmt: StmtId) -> StmtId {
+        fold_stmt(self, stmt)
+    }
+
+    fn fold_stmts(&mut self, statements: &[StmtId]) -> Vec<StmtId> {
+        statements
+            .iter()
+            .map(|statement| self.fold_stmt(*statement))
+            .collect()
+    }
+
+    fn fold_function(&mut self, declaration: &FunctionDecl) -> Rc<FunctionDecl> {
+        let params = declaration
+            .params
+            .iter()
+            .map(|param| Param {
+                name: param.name.clone(),
+                default: param.default.map(|default| self.fold_expr(default)),
+            })
+            .collect();
+        Rc::new(FunctionDecl {
+            name: declaration.name.clone(),
+            params,
+            rest: declaration.rest.clone(),
+            body: self.fold_stmts(&declaration.body),
+        })
+    }
+
+    fn add_expr(&mut self, expr: Expr) -> ExprId {
+        self.folded().add_expr(expr)
+    }
+
+    fn add_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.folded().add_stmt(stmt)
+    }
+
+    fn fold_literal(&mut self, literal: &Literal) -> ExprId {
+        self.add_expr(Expr::Literal(literal.clone()))
+    }
+
+    fn fold_grouping(&mut self, expr: ExprId) -> ExprId {
+        let expr = self.fold_expr(expr);
+        self.add_expr(Expr::Grouping(expr))
+    }
+
+    fn fold_binary(&mut self, left: ExprId, operator: &Token, right: ExprId) -> ExprId {
+        let left = self.fold_expr(left);
+        let right = self.fold_expr(right);
+        self.add_expr(Expr::Binary(left, operator.clone(), right))
+    }
+
+    fn fold_unary(&mut self, operator: &Token, right: ExprId) -> ExprId {
+        let right = self.fold_expr(right);
+        self.add_expr(Expr::Unary(operator.clone(), right))
+    }
+
+    fn fold_logical(&mut self, left: ExprId, operator: &Token, right: ExprId) -> ExprId {
+        let left = self.fold_expr(left);
+        let right = self.fold_expr(right);
+        self.add_expr(Expr::Logical(left, operator.clone(), right))
+    }
+
+    fn fold_ternary(
+        &mut self,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
+    ) -> ExprId {
+        let condition = self.fold_expr(condition);
+        let then_branch = self.fold_expr(then_branch);
+        let else_branch = self.fold_expr(else_branch);
+        self.add_expr(Expr::Ternary(condition, then_branch, else_branch))
+    }
+
+    fn fold_comma(&mut self, left: ExprId, right: ExprId) -> ExprId {
+        let left = self.fold_expr(left);
+        let right = self.fold_expr(right);
+        self.add_expr(Expr::Comma(left, right))
+    }
+
+    fn fold_variable(&mut self, name: &Token) -> ExprId {
+        self.add_expr(Expr::Variable(name.clone()))
+    }
+
+    fn fold_assign(&mut self, name: &Token, value: ExprId) -> ExprId {
+        let value = self.fold_expr(value);
+        self.add_expr(Expr::Assign(name.clone(), value))
+    }
+
+    fn fold_compound_assign(&mut self, target: ExprId, operator: &Token, value: ExprId) -> ExprId {
+        let target = self.fold_expr(target);
+        let value = self.fold_expr(value);
+        self.add_expr(Expr::CompoundAssign(target, operator.clone(), value))
+    }
+
+    fn fold_increment(&mut self, target: ExprId, operator: &Token, prefix: bool) -> ExprId {
+        let target = self.fold_expr(target);
+        self.add_expr(Expr::Increment(target, operator.clone(), prefix))
+    }
+
+    fn fold_call(
+        &mut self,
+        callee: ExprId,
+        paren: &Token,
+        arguments: &[ExprId],
+        named: &[(Token, ExprId)],
+    ) -> ExprId {
+        let callee = self.fold_expr(callee);
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.fold_expr(*argument))
+            .collect();
+        let named = named
+            .iter()
+            .map(|(name, argument)| (name.clone(), self.fold_expr(*argument)))
+            .collect();
+        self.add_expr(Expr::Call(callee, paren.clone(), arguments, named))
+    }
+
+    fn fold_lambda(&mut self, declaration: &Rc<FunctionDecl>) -> ExprId {
+        let declaration = self.fold_function(declaration);
+        self.add_expr(Expr::Lambda(declaration))
+    }
+
+    fn fold_list(&mut self, elements: &[ExprId]) -> ExprId {
+        let elements = elements
+            .iter()
+            .map(|element| self.fold_expr(*element))
+            .collect();
+        self.add_expr(Expr::List(elements))
+    }
+
+    fn fold_map(&mut self, brace: &Token, entries: &[(ExprId, ExprId)]) -> ExprId {
+        let entries = entries
+            .iter()
+            .map(|(key, value)| (self.fold_expr(*key), self.fold_expr(*value)))
+            .collect();
+        self.add_expr(Expr::Map(brace.clone(), entries))
+    }
+
+    fn fold_index(&mut self, object: ExprId, bracket: &Token, index: ExprId) -> ExprId {
+        let object = self.fold_expr(object);
+        let index = self.fold_expr(index);
+        self.add_expr(Expr::Index(object, bracket.clone(), index))
+    }
+
+    fn fold_slice(
+        &mut self,
+        object: ExprId,
+        bracket: &Token,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    ) -> ExprId {
+        let object = self.fold_expr(object);
+        let start = start.map(|start| self.fold_expr(start));
+        let end = end.map(|end| self.fold_expr(end));
+        self.add_expr(Expr::Slice(object, bracket.clone(), start, end))
+    }
+
+    fn fold_set_index(
+        &mut self,
+        object: ExprId,
+        bracket: &Token,
+        index: ExprId,
+        value: ExprId,
+    ) -> ExprId {
+        let object = self.fold_expr(object);
+        let index = self.fold_expr(index);
+        let value = self.fold_expr(value);
+        self.add_expr(Expr::SetIndex(object, bracket.clone(), index, value))
+    }
+
+    fn fold_get(&mut self, object: ExprId, name: &Token) -> ExprId {
+        let object = self.fold_expr(object);
+        self.add_expr(Expr::Get(object, name.clone()))
+    }
+
+    fn fold_set(&mut self, object: ExprId, name: &Token, value: ExprId) -> ExprId {
+        let object = self.fold_expr(object);
+        let value = self.fold_expr(value);
+        self.add_expr(Expr::Set(object, name.clone(), value))
+    }
+
+    fn fold_expression_stmt(&mut self, expr: ExprId) -> StmtId {
+        let expr = self.fold_expr(expr);
+        self.add_stmt(Stmt::Expression(expr))
+    }
+
+    fn fold_print_stmt(&mut self, expr: ExprId) -> StmtId {
+        let expr = self.fold_expr(expr);
+        self.add_stmt(Stmt::Print(expr))
+    }
+
+    fn fold_var_stmt(&mut self, name: &Token, initializer: Option<ExprId>) -> StmtId {
+        let initializer = initializer.map(|initializer| self.fold_expr(initializer));
+        self.add_stmt(Stmt::Var(name.clone(), initializer))
+    }
+
+    fn fold_block_stmt(&mut self, statements: &[StmtId]) -> StmtId {
+        let statements = self.fold_stmts(statements);
+        self.add_stmt(Stmt::Block(statements))
+    }
+
+    fn fold_if_stmt(
+        &mut self,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> StmtId {
+        let condition = self.fold_expr(condition);
+        let then_branch = self.fold_stmt(then_branch);
+        let else_branch = else_branch.map(|else_branch| self.fold_stmt(else_branch));
+        self.add_stmt(Stmt::If(condition, then_branch, else_branch))
+    }
+
+    fn fold_while_stmt(
+        &mut self,
+        keyword: &Token,
+        label: &Option<Token>,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+    ) -> StmtId {
+        let condition = self.fold_expr(condition);
+        let body = self.fold_stmt(body);
+        let increment = increment.map(|increment| self.fold_expr(increment));
+        self.add_stmt(Stmt::While(
+            keyword.clone(),
+            label.clone(),
+            condition,
+            body,
+            increment,
+        ))
+    }
+
+    fn fold_for_in_stmt(
+        &mut self,
+        label: &Option<Token>,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+    ) -> StmtId {
+        let iterable = self.fold_expr(iterable);
+        let body = self.fold_stmt(body);
+        self.add_stmt(Stmt::ForIn(label.clone(), name.clone(), iterable, body))
+    }
+
+    fn fold_break_stmt(&mut self, keyword: &Token, label: &Option<Token>) -> StmtId {
+        self.add_stmt(Stmt::Break(keyword.clone(), label.clone()))
+    }
+
+    fn fold_continue_stmt(&mut self, keyword: &Token, label: &Option<Token>) -> StmtId {
+        self.add_stmt(Stmt::Continue(keyword.clone(), label.clone()))
+    }
+
+    fn fold_function_stmt(&mut self, declaration: &Rc<FunctionDecl>) -> StmtId {
+        let declaration = self.fold_function(declaration);
+        self.add_stmt(Stmt::Function(declaration))
+    }
+
+    fn fold_return_stmt(&mut self, keyword: &Token, value: Option<ExprId>) -> StmtId {
+        let value = value.map(|value| self.fold_expr(value));
+        self.add_stmt(Stmt::Return(keyword.clone(), value))
+    }
+
+    fn fold_throw_stmt(&mut self, keyword: &Token, value: ExprId) -> StmtId {
+        let value = self.fold_expr(value);
+        self.add_stmt(Stmt::Throw(keyword.clone(), value))
+    }
+
+    fn fold_try_stmt(
+        &mut self,
+        body: &[StmtId],
+        catch: &Option<(Token, Vec<StmtId>)>,
+        finally: &Option<Vec<StmtId>>,
+    ) -> StmtId {
+        let body = self.fold_stmts(body);
+        let catch = catch
+            .as_ref()
+            .map(|(name, handler)| (name.clone(), self.fold_stmts(handler)));
+        let finally = finally.as_ref().map(|finally| self.fold_stmts(finally));
+        self.add_stmt(Stmt::Try(body, catch, finally))
+    }
+}
+
+/// Calls the folder's method for the kind of `expr`.
+pub fn fold_expr<'a, F: Folder<'a> + ?Sized>(folder: &mut F, expr: ExprId) -> ExprId {
+    match &folder.ast()[expr] {
+        Expr::Literal(literal) => folder.fold_literal(literal),
+        Expr::Grouping(expr) => folder.fold_grouping(*expr),
+        Expr::Binary(left, op, right) => folder.fold_binary(*left, op, *right),
+        Expr::Unary(op, right) => folder.fold_unary(op, *right),
+        Expr::Logical(left, op, right) => folder.fold_logical(*left, op, *right),
+        Expr::Ternary(condition, then_branch, else_branch) => {
+            folder.fold_ternary(*condition, *then_branch, *else_branch)
+        }
+        Expr::Comma(left, right) => folder.fold_comma(*left, *right),
+        Expr::Variable(name) => folder.fold_variable(name),
+        Expr::Assign(name, value) => folder.fold_assign(name, *value),
+        Expr::CompoundAssign(target, op, value) => folder.fold_compound_assign(*target, op, *value),
+        Expr::Increment(target, op, prefix) => folder.fold_increment(*target, op, *prefix),
+        Expr::Call(callee, paren, arguments, named) => {
+            folder.fold_call(*callee, paren, arguments, named)
+        }
+        Expr::Lambda(declaration) => folder.fold_lambda(declaration),
+        Expr::List(elements) => folder.fold_list(elements),
+        Expr::Map(brace, entries) => folder.fold_map(brace, entries),
+        Expr::Index(object, bracket, index) => folder.fold_index(*object, bracket, *index),
+        Expr::Slice(object, bracket, start, end) => {
+            folder.fold_slice(*object, bracket, *start, *end)
+        }
+        Expr::SetIndex(object, bracket, index, value) => {
+            folder.fold_set_index(*object, bracket, *index, *value)
+        }
+        Expr::Get(object, name) => folder.fold_get(*object, name),
+        Expr::Set(object, name, value) => folder.fold_set(*object, name, *value),
+    }
+}
+
+/// Calls the folder's method for the kind of `stmt`.
+pub fn fold_stmt<'a, F: Folder<'a> + ?Sized>(folder: &mut F, stmt: StmtId) -> StmtId {
+    match &folder.ast()[stmt] {
+        Stmt::Expression(expr) => folder.fold_expression_stmt(*expr),
+        Stmt::Print(expr) => folder.fold_print_stmt(*expr),
+        Stmt::Var(name, initializer) => folder.fold_var_stmt(name, *initializer),
+        Stmt::Block(statements) => folder.fold_block_stmt(statements),
+        Stmt::If(condition, then_branch, else_branch) => {
+            folder.fold_if_stmt(*condition, *then_branch, *else_branch)
+        }
+        Stmt::While(keyword, label, condition, body, increment) => {
+            folder.fold_while_stmt(keyword, label, *condition, *body, *increment)
+        }
+        Stmt::ForIn(label, name, iterable, body) => {
+            folder.fold_for_in_stmt(label, name, *iterable, *body)
+        }
+        Stmt::Break(keyword, label) => folder.fold_break_stmt(keyword, label),
+        Stmt::Continue(keyword, label) => folder.fold_continue_stmt(keyword, label),
+        Stmt::Function(declaration) => folder.fold_function_stmt(declaration),
+        Stmt::Return(keyword, value) => folder.fold_return_stmt(keyword, *value),
+        Stmt::Throw(keyword, value) => folder.fold_throw_stmt(keyword, *value),
+        Stmt::Try(body, catch, finally) => folder.fold_try_stmt(body, catch, finally),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast_printer::AstPrinter, parser::Parser, scanner::Scanner};
+
+    fn parse(source: &str) -> (Ast, Vec<StmtId>) {
+        let mut parser = Parser::new(Scanner::new(source).scan_tokens());
+        let statements = parser.parse().unwrap();
+        (parser.into_ast(), statements)
+    }
+
+    fn print(ast: &Ast, statements: &[StmtId]) -> Vec<String> {
+        let printer = AstPrinter::new(ast);
+        statements
+            .iter()
+            .map(|statement| printer.print_stmt(*statement))
+            .collect()
+    }
+
+    /// Collects the variables a program reads.
+    struct Reads<'a> {
+        ast: &'a Ast,
+        names: Vec<String>,
+    }
+
+    impl<'a> Walker<'a> for Reads<'a> {
+        fn ast(&self) -> &'a Ast {
+            self.ast
+        }
+
+        fn visit_variable(&mut self, name: &Token) {
+            self.names.push(name.lexeme.to_string());
+        }
+    }
+
+    #[test]
+    fn test_walker() {
+        let (ast, statements) = parse(
+            "var a = b + c[d];
+            fun f(x = e) { while (g) { print h(i: j)[k:]; } }
+            try { throw () => l; } catch (m) { n.o = p; }",
+        );
+        let mut reads = Reads {
+            ast: &ast,
+            names: Vec::new(),
+        };
+        reads.walk_stmts(&statements);
+        assert_eq!(
+            reads.names,
+            ["b", "c", "d", "e", "g", "h", "j", "k", "l", "n", "p"]
+        );
+    }
+
+    /// Copies a tree, leaving out groupings.
+    struct Ungroup<'a> {
+        ast: &'a Ast,
+        folded: Ast,
+    }
+
+    impl<'a> Folder<'a> for Ungroup<'a> {
+        fn ast(&self) -> &'a Ast {
+            self.ast
+        }
+
+        fn folded(&mut self) -> &mut Ast {
+            &mut self.folded
+        }
+
+        fn fold_grouping(&mut self, expr: ExprId) -> ExprId {
+            self.fold_expr(expr)
+        }
+    }
+
+    #[test]
+    fn test_folder() {
+        let source = "var a = (1 + (2)) * 3;
+            fun f(x = (y)) { for (z in (xs)) print [(z), {(k): (v)}]; }
+            if ((a)) { (f)(); } else a += (1);";
+        let (ast, statements) = parse(source);
+        let mut ungroup = Ungroup {
+            ast: &ast,
+            folded: Ast::new(),
+        };
+        let folded = ungroup.fold_stmts(&statements);
+        assert_eq!(
+            print(&ungroup.folded, &folded),
+            [
+                "(var a (* (+ 1 2) 3))",
+                "(fun f (x=y) (for z xs (print (list z (map (k v))))))",
+                "(if a (block (; (call f))) (; (+= a 1)))",
+            ]
+        );
+        // Nothing else was touched.
+        assert_eq!(
+            print(&ast, &statements)[0],
+            "(var a (* (grouping (+ 1 (grouping 2))) 3))"
+        );
+    }
+}